@@ -1,15 +1,2 @@
-pub fn erase_from_canvas () {
-
-}
-
-pub fn add_diffusion_segment () {
-
-}
-
-pub fn create_new_board () {
-
-}
-
-pub fn add_layer () {
-    
-}
\ No newline at end of file
+pub(crate) mod raster;
+pub(crate) mod svg;
@@ -0,0 +1,1020 @@
+use crate::model::{
+    blend::Blend,
+    brush::{Brush, BrushShape},
+    document::{decode_png, ColorDoc, Document, ElementDoc, PixelDoc},
+    elements::{ellipse_points, Element},
+    fill::Fill,
+    layers::Layer,
+    picture::{tint, Picture},
+    shapes::{arrow_outlines, inside, polygon_points, star_points},
+    style::{is_closed, ShapeStyle},
+};
+use nannou::{
+    geom::Rect,
+    image::{self, DynamicImage, GenericImageView, GrayImage, Luma, RgbaImage},
+    prelude::*,
+};
+use std::{error::Error, path::Path};
+
+// the view draws the background texture at this size around the origin
+pub const BACKGROUND_SIZE: f32 = 400.;
+// color distance past the fill tolerance over which smoothed fill edges
+// fade out
+const FILL_EDGE: f32 = 0.25;
+
+// software copy of the frame, rgba in 0..1 with the same coordinates nannou
+// uses: origin at the center of `bounds` and y going up
+pub struct Raster {
+    width: u32,
+    height: u32,
+    bounds: Rect,
+    scale: f32,
+    pixels: Vec<[f32; 4]>,
+    // how `blend` mixes, set per element
+    mode: Blend,
+}
+
+impl Raster {
+    pub fn new(bounds: Rect, scale: f32, clear: [f32; 4]) -> Self {
+        let width = (bounds.w() * scale).round().max(1.) as u32;
+        let height = (bounds.h() * scale).round().max(1.) as u32;
+        Self {
+            width,
+            height,
+            bounds,
+            scale,
+            pixels: vec![clear; (width * height) as usize],
+            mode: Blend::Normal,
+        }
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> [f32; 4] {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    // world position of the center of an image pixel
+    pub fn to_world(&self, x: u32, y: u32) -> Point2 {
+        pt2(
+            self.bounds.left() + (x as f32 + 0.5) / self.scale,
+            self.bounds.top() - (y as f32 + 0.5) / self.scale,
+        )
+    }
+
+    // image pixel range covering a world rectangle
+    fn pixel_range(&self, min: Point2, max: Point2) -> (u32, u32, u32, u32) {
+        let x0 = ((min.x - self.bounds.left()) * self.scale).floor().max(0.) as u32;
+        let x1 = ((max.x - self.bounds.left()) * self.scale)
+            .ceil()
+            .min(self.width as f32)
+            .max(0.) as u32;
+        let y0 = ((self.bounds.top() - max.y) * self.scale).floor().max(0.) as u32;
+        let y1 = ((self.bounds.top() - min.y) * self.scale)
+            .ceil()
+            .min(self.height as f32)
+            .max(0.) as u32;
+        (x0, x1, y0, y1)
+    }
+
+    // blend of a straight alpha color in the mode of the element being drawn
+    pub fn blend(&mut self, x: u32, y: u32, color: [f32; 4]) {
+        let dst = &mut self.pixels[(y * self.width + x) as usize];
+        *dst = self.mode.mix(*dst, color);
+    }
+
+    pub fn fill_rect(&mut self, center: Point2, wh: Vec2, color: [f32; 4]) {
+        let half = wh.abs() / 2.;
+        let (x0, x1, y0, y1) = self.pixel_range(center - half, center + half);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                self.blend(x, y, color);
+            }
+        }
+    }
+
+    // fills the union of closed outlines, the last point of each repeating
+    // the first, every pixel is blended once so overlaps do not darken
+    pub fn fill_polygons(&mut self, polygons: &[Vec<Point2>], color: [f32; 4]) {
+        let polygons: Vec<_> = polygons.iter().filter(|p| p.len() > 3).collect();
+        let (min, max) = match polygons.first() {
+            Some(first) => polygons
+                .iter()
+                .flat_map(|p| p.iter())
+                .fold((first[0], first[0]), |(min, max), p| {
+                    (min.min(*p), max.max(*p))
+                }),
+            None => return,
+        };
+        let aa = 0.5 / self.scale;
+        let (x0, x1, y0, y1) = self.pixel_range(min - vec2(aa, aa), max + vec2(aa, aa));
+        let width = x1.saturating_sub(x0) as usize;
+        let mut coverage = vec![0f32; width * y1.saturating_sub(y0) as usize];
+        for polygon in polygons {
+            let (pmin, pmax) = polygon
+                .iter()
+                .fold((polygon[0], polygon[0]), |(min, max), p| {
+                    (min.min(*p), max.max(*p))
+                });
+            let (px0, px1, py0, py1) = self.pixel_range(pmin - vec2(aa, aa), pmax + vec2(aa, aa));
+            for y in py0..py1 {
+                for x in px0..px1 {
+                    let p = self.to_world(x, y);
+                    let edge = polygon
+                        .windows(2)
+                        .map(|w| segment_distance(p, w[0], w[1]).0)
+                        .fold(f32::MAX, f32::min);
+                    // signed distance to the outline, positive inside
+                    let edge = if inside(polygon, p) { edge } else { -edge };
+                    let c = &mut coverage[(y - y0) as usize * width + (x - x0) as usize];
+                    *c = c.max(((edge + aa) / (2. * aa)).clamp(0., 1.));
+                }
+            }
+        }
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let c = coverage[(y - y0) as usize * width + (x - x0) as usize];
+                if c > 0. {
+                    self.blend(x, y, [color[0], color[1], color[2], color[3] * c]);
+                }
+            }
+        }
+    }
+
+    // fills and strokes an outline like `ShapeStyle::draw` does on screen
+    pub fn draw_styled(&mut self, outline: &[Point2], style: &ShapeStyle, alpha: f32) {
+        if let Some(fill) = style.fill.filter(|_| is_closed(outline)) {
+            let mut color = doc_to_rgba(fill.into());
+            color[3] *= alpha;
+            self.fill_polygons(&[outline.to_vec()], color);
+        }
+        let mut color = doc_to_rgba(style.stroke.into());
+        color[3] *= alpha;
+        self.fill_polygons(&style.stroke_pieces(outline), color);
+    }
+
+    // strokes a polyline with colors interpolated between its points, every
+    // pixel is blended once so overlapping segments do not darken the joins,
+    // `widths` scales the weight at each point and may be empty
+    pub fn stroke_polyline(
+        &mut self,
+        points: &[(Point2, [f32; 4])],
+        weight: f32,
+        widths: &[f32],
+        alpha: f32,
+    ) {
+        if points.is_empty() {
+            return;
+        }
+        let halves: Vec<f32> = (0..points.len())
+            .map(|i| (weight * widths.get(i).copied().unwrap_or(1.)).max(1. / self.scale) / 2.)
+            .collect();
+        let half = halves.iter().copied().fold(0., f32::max);
+        let aa = 0.5 / self.scale;
+        let min = points
+            .iter()
+            .fold(points[0].0, |m, (p, _)| pt2(m.x.min(p.x), m.y.min(p.y)));
+        let max = points
+            .iter()
+            .fold(points[0].0, |m, (p, _)| pt2(m.x.max(p.x), m.y.max(p.y)));
+        let pad = vec2(half + aa, half + aa);
+        let (x0, x1, y0, y1) = self.pixel_range(min - pad, max + pad);
+        let segments: Vec<_> = if points.len() == 1 {
+            vec![(0, 0)]
+        } else {
+            (1..points.len()).map(|i| (i - 1, i)).collect()
+        };
+        // distance past the edge of the nearest segment for every pixel,
+        // each segment only visits the pixels around itself
+        let width = (x1 - x0) as usize;
+        let mut best: Vec<Option<(f32, [f32; 4])>> = vec![None; width * (y1 - y0) as usize];
+        for &(i, j) in segments.iter() {
+            let ((a, ca), (b, cb)) = (points[i], points[j]);
+            let pad = Vec2::splat(halves[i].max(halves[j]) + aa);
+            let (sx0, sx1, sy0, sy1) = self.pixel_range(a.min(b) - pad, a.max(b) + pad);
+            for y in sy0.max(y0)..sy1.min(y1) {
+                for x in sx0.max(x0)..sx1.min(x1) {
+                    let p = self.to_world(x, y);
+                    let (d, t) = segment_distance(p, a, b);
+                    let outside = d - (halves[i] + (halves[j] - halves[i]) * t);
+                    let cell = &mut best[(y - y0) as usize * width + (x - x0) as usize];
+                    if cell.is_none_or(|(bo, _)| outside < bo) {
+                        *cell = Some((outside, lerp_color(ca, cb, t)));
+                    }
+                }
+            }
+        }
+        for y in y0..y1 {
+            for x in x0..x1 {
+                if let Some((outside, color)) = best[(y - y0) as usize * width + (x - x0) as usize]
+                {
+                    let coverage = ((aa - outside) / (2. * aa)).clamp(0., 1.);
+                    if coverage > 0. {
+                        self.blend(
+                            x,
+                            y,
+                            [color[0], color[1], color[2], color[3] * coverage * alpha],
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    // prints the tip of a brush along a stroke, the points carry their
+    // color and width as a share of the weight
+    pub fn draw_dabs(
+        &mut self,
+        brush: &Brush,
+        points: &[(Point2, (Hsva, f32))],
+        weight: f32,
+        alpha: f32,
+    ) {
+        let stamp = (brush.shape == BrushShape::Texture).then(|| brush.load_stamp());
+        let roundness = match brush.shape {
+            BrushShape::Flat => brush.roundness,
+            _ => 1.,
+        };
+        for dab in brush.dabs(points, weight) {
+            let mut color = doc_to_rgba(dab.color.into());
+            color[3] *= brush.opacity * alpha;
+            let radius = vec2(dab.size, dab.size * roundness) / 2.;
+            self.dab(
+                dab.center,
+                radius,
+                dab.angle,
+                brush.hardness,
+                stamp.as_deref(),
+                color,
+            );
+        }
+    }
+
+    // one print of a tip turned by `angle`, an ellipse fading out past
+    // `hardness` of its radius or the gray levels of `stamp` stretched over it
+    pub fn dab(
+        &mut self,
+        center: Point2,
+        radius: Vec2,
+        angle: f32,
+        hardness: f32,
+        stamp: Option<&GrayImage>,
+        color: [f32; 4],
+    ) {
+        if radius.x <= 0. || radius.y <= 0. {
+            return;
+        }
+        let reach = Vec2::splat(radius.max_element());
+        let (x0, x1, y0, y1) = self.pixel_range(center - reach, center + reach);
+        let aa = 0.5 / self.scale;
+        let (sin, cos) = (-angle).sin_cos();
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let d = self.to_world(x, y) - center;
+                // into the unturned tip, -1 to 1 across it
+                let local = vec2(d.x * cos - d.y * sin, d.x * sin + d.y * cos) / radius;
+                let coverage = match stamp {
+                    Some(stamp) => {
+                        let u = ((local.x + 1.) / 2. * stamp.width() as f32).floor();
+                        let v = ((1. - local.y) / 2. * stamp.height() as f32).floor();
+                        if u < 0.
+                            || v < 0.
+                            || u >= stamp.width() as f32
+                            || v >= stamp.height() as f32
+                        {
+                            continue;
+                        }
+                        stamp.get_pixel(u as u32, v as u32).0[0] as f32 / 255.
+                    }
+                    None => {
+                        let r = local.length();
+                        let edge = ((1. - r) * radius.min_element() + aa) / (2. * aa);
+                        let fade = if hardness >= 1. {
+                            1.
+                        } else {
+                            (1. - r) / (1. - hardness)
+                        };
+                        edge.clamp(0., 1.) * fade.clamp(0., 1.)
+                    }
+                };
+                if coverage > 0. {
+                    self.blend(x, y, [color[0], color[1], color[2], color[3] * coverage]);
+                }
+            }
+        }
+    }
+
+    // coverage of the area around `seed` whose colors are within `tolerance`
+    // of the color there, cropped to the pixels it reaches, with the world
+    // center and size of the crop. `antialias` partly covers the pixels just
+    // past the edge by how close they come to the tolerance
+    pub fn flood_fill(
+        &self,
+        seed: Point2,
+        tolerance: f32,
+        antialias: bool,
+    ) -> Option<(GrayImage, Point2, Vec2)> {
+        let sx = ((seed.x - self.bounds.left()) * self.scale).floor();
+        let sy = ((self.bounds.top() - seed.y) * self.scale).floor();
+        if sx < 0. || sy < 0. || sx >= self.width as f32 || sy >= self.height as f32 {
+            return None;
+        }
+        let (w, h) = (self.width as usize, self.height as usize);
+        let target = premultiplied(self.pixels[sy as usize * w + sx as usize]);
+        let distance = |i: usize| {
+            let c = premultiplied(self.pixels[i]);
+            (0..4).map(|k| (c[k] - target[k]).abs()).fold(0., f32::max)
+        };
+        let neighbours = |i: usize| {
+            let (x, y) = (i % w, i / w);
+            [
+                (x > 0).then(|| i - 1),
+                (x + 1 < w).then(|| i + 1),
+                (y > 0).then(|| i - w),
+                (y + 1 < h).then(|| i + w),
+            ]
+            .into_iter()
+            .flatten()
+        };
+
+        let mut coverage = vec![0u8; w * h];
+        let mut stack = vec![sy as usize * w + sx as usize];
+        while let Some(i) = stack.pop() {
+            if coverage[i] == u8::MAX {
+                continue;
+            }
+            coverage[i] = u8::MAX;
+            stack.extend(neighbours(i).filter(|&j| coverage[j] == 0 && distance(j) <= tolerance));
+        }
+        if antialias {
+            let edge: Vec<usize> = (0..w * h)
+                .filter(|&i| coverage[i] == u8::MAX)
+                .flat_map(|i| neighbours(i).filter(|&j| coverage[j] == 0))
+                .collect();
+            for j in edge {
+                let level = (1. - (distance(j) - tolerance) / FILL_EDGE).clamp(0., 1.);
+                coverage[j] = (level * 254.).round() as u8;
+            }
+        }
+
+        let covered = |i: &usize| coverage[*i] > 0;
+        let (mut x0, mut y0, mut x1, mut y1) = (w, h, 0, 0);
+        for i in (0..w * h).filter(covered) {
+            let (x, y) = (i % w, i / w);
+            x0 = x0.min(x);
+            y0 = y0.min(y);
+            x1 = x1.max(x + 1);
+            y1 = y1.max(y + 1);
+        }
+        let mask = GrayImage::from_fn((x1 - x0) as u32, (y1 - y0) as u32, |x, y| {
+            Luma([coverage[(y as usize + y0) * w + x as usize + x0]])
+        });
+        let wh = vec2((x1 - x0) as f32, (y1 - y0) as f32) / self.scale;
+        let center = pt2(
+            self.bounds.left() + x0 as f32 / self.scale + wh.x / 2.,
+            self.bounds.top() - y0 as f32 / self.scale - wh.y / 2.,
+        );
+        Some((mask, center, wh))
+    }
+
+    // draws an image stretched over a world rectangle turned by `rotation`
+    // radians around its center
+    pub fn draw_image(
+        &mut self,
+        img: &DynamicImage,
+        center: Point2,
+        wh: Vec2,
+        rotation: f32,
+        alpha: f32,
+    ) {
+        let half = wh.abs() / 2.;
+        let reach = vec2(half.length(), half.length());
+        let (x0, x1, y0, y1) = self.pixel_range(center - reach, center + reach);
+        let (iw, ih) = img.dimensions();
+        let (sin, cos) = (-rotation).sin_cos();
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let d = self.to_world(x, y) - center;
+                // back into the unrotated frame of the image
+                let p = vec2(d.x * cos - d.y * sin, d.x * sin + d.y * cos) + half;
+                let u = (p.x / wh.x.abs() * iw as f32).floor();
+                let v = ((1. - p.y / wh.y.abs()) * ih as f32).floor();
+                if u < 0. || v < 0. || u >= iw as f32 || v >= ih as f32 {
+                    continue;
+                }
+                let px = img.get_pixel(u as u32, v as u32).0;
+                self.blend(
+                    x,
+                    y,
+                    [
+                        px[0] as f32 / 255.,
+                        px[1] as f32 / 255.,
+                        px[2] as f32 / 255.,
+                        px[3] as f32 / 255. * alpha,
+                    ],
+                );
+            }
+        }
+    }
+
+    pub fn draw_picture(&mut self, picture: &Picture, alpha: f32) {
+        self.draw_image(
+            picture.get_image(),
+            picture.get_center(),
+            picture.get_wh(),
+            picture.get_rotation(),
+            picture.get_opacity() * alpha,
+        );
+    }
+
+    pub fn draw_element(&mut self, elem: &ElementDoc, alpha: f32) {
+        self.mode = elem.get_blend();
+        self.draw_shape(elem, alpha);
+        self.mode = Blend::Normal;
+    }
+
+    fn draw_shape(&mut self, elem: &ElementDoc, alpha: f32) {
+        match elem {
+            ElementDoc::Line {
+                pixels,
+                weight,
+                widths,
+                brush: Some(brush),
+                ..
+            } => {
+                let points: Vec<_> = pixels
+                    .iter()
+                    .enumerate()
+                    .map(|(i, p)| {
+                        let width = widths.get(i).copied().unwrap_or(1.);
+                        (pt2(p.point[0], p.point[1]), (p.color.into(), width))
+                    })
+                    .collect();
+                self.draw_dabs(brush, &points, *weight, alpha)
+            }
+            ElementDoc::Line {
+                pixels,
+                weight,
+                widths,
+                ..
+            } => self.stroke_polyline(&rgba_points(pixels), *weight, widths, alpha),
+            ElementDoc::RectangleCustom {
+                center, wh, color, ..
+            } => {
+                // the frame is filled whole from its center and size
+                let mut color = doc_to_rgba(*color);
+                color[3] *= alpha;
+                self.fill_rect(pt2(center[0], center[1]), vec2(wh[0], wh[1]), color);
+            }
+            ElementDoc::Rectangle {
+                center,
+                wh,
+                rotation,
+                style,
+            } => {
+                let center = pt2(center[0], center[1]);
+                let half = vec2(wh[0], wh[1]) / 2.;
+                let (sin, cos) = rotation.sin_cos();
+                let corners: Vec<_> = [
+                    vec2(-half.x, half.y),
+                    vec2(half.x, half.y),
+                    vec2(half.x, -half.y),
+                    vec2(-half.x, -half.y),
+                    vec2(-half.x, half.y),
+                ]
+                .iter()
+                .map(|&c| center + vec2(c.x * cos - c.y * sin, c.x * sin + c.y * cos))
+                .collect();
+                self.draw_styled(&corners, &style.into(), alpha);
+            }
+            ElementDoc::Ellipse {
+                center,
+                radii,
+                rotation,
+                style,
+            } => {
+                let center = pt2(center[0], center[1]);
+                let outline = ellipse_points(center, vec2(radii[0], radii[1]), *rotation);
+                self.draw_styled(&outline, &style.into(), alpha);
+            }
+            ElementDoc::Image {
+                png,
+                center,
+                wh,
+                rotation,
+                opacity,
+                ..
+            } => {
+                if let Ok(img) = decode_png(png) {
+                    self.draw_image(
+                        &img,
+                        pt2(center[0], center[1]),
+                        vec2(wh[0], wh[1]),
+                        *rotation,
+                        opacity * alpha,
+                    );
+                }
+            }
+            ElementDoc::Fill {
+                mask,
+                center,
+                wh,
+                rotation,
+                color,
+                ..
+            } => {
+                if let Ok(mask) = decode_png(mask) {
+                    let img = tint(&mask.to_luma8(), (*color).into());
+                    self.draw_image(
+                        &DynamicImage::ImageRgba8(img),
+                        pt2(center[0], center[1]),
+                        vec2(wh[0], wh[1]),
+                        *rotation,
+                        alpha,
+                    );
+                }
+            }
+            ElementDoc::Segment { start, end, style } => {
+                let outline = [pt2(start[0], start[1]), pt2(end[0], end[1])];
+                self.draw_styled(&outline, &style.into(), alpha);
+            }
+            ElementDoc::Polygon {
+                center,
+                radius,
+                sides,
+                rotation,
+                style,
+            } => {
+                let outline = polygon_points(pt2(center[0], center[1]), *radius, *sides, *rotation);
+                self.draw_styled(&outline, &style.into(), alpha);
+            }
+            ElementDoc::Star {
+                center,
+                radius,
+                points,
+                ratio,
+                rotation,
+                style,
+            } => {
+                let outline = star_points(
+                    pt2(center[0], center[1]),
+                    *radius,
+                    *points,
+                    *ratio,
+                    *rotation,
+                );
+                self.draw_styled(&outline, &style.into(), alpha);
+            }
+            ElementDoc::Arrow {
+                start,
+                end,
+                head,
+                style,
+            } => {
+                let start = pt2(start[0], start[1]);
+                let end = pt2(end[0], end[1]);
+                for (outline, style) in arrow_outlines(start, end, *head, &style.into()) {
+                    self.draw_styled(&outline, &style, alpha);
+                }
+            }
+        }
+    }
+
+    pub fn draw_layers(&mut self, layers: &[Layer]) {
+        for layer in layers.iter().filter(|layer| layer.visible) {
+            for elem in layer.elements.iter() {
+                // pictures and fills skip the round trip through png
+                let picture = elem
+                    .downcast_ref::<Picture>()
+                    .or_else(|| elem.downcast_ref::<Fill>().map(Fill::get_picture));
+                match picture {
+                    Some(picture) => {
+                        self.mode = picture.get_blend();
+                        self.draw_picture(picture, layer.opacity);
+                        self.mode = Blend::Normal;
+                    }
+                    // only pictures and fills encode a png for their doc,
+                    // the rest always have one
+                    None => {
+                        if let Ok(doc) = elem.to_doc() {
+                            self.draw_element(&doc, layer.opacity)
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn to_image(&self) -> RgbaImage {
+        RgbaImage::from_fn(self.width, self.height, |x, y| {
+            let px = self.get_pixel(x, y);
+            image::Rgba([
+                (px[0].clamp(0., 1.) * 255.).round() as u8,
+                (px[1].clamp(0., 1.) * 255.).round() as u8,
+                (px[2].clamp(0., 1.) * 255.).round() as u8,
+                (px[3].clamp(0., 1.) * 255.).round() as u8,
+            ])
+        })
+    }
+}
+
+// renders what the view shows inside `bounds`, `scale` output pixels per point
+pub fn render(
+    layers: &[Layer],
+    background: Option<&DynamicImage>,
+    bounds: Rect,
+    scale: f32,
+) -> RgbaImage {
+    composite(layers, background, bounds, scale).to_image()
+}
+
+// the frame as a raster, for tools that look at what the canvas shows
+pub fn composite(
+    layers: &[Layer],
+    background: Option<&DynamicImage>,
+    bounds: Rect,
+    scale: f32,
+) -> Raster {
+    let mut raster = Raster::new(bounds, scale, [0., 0., 0., 1.]);
+    if let Some(img) = background {
+        raster.draw_image(
+            img,
+            pt2(0., 0.),
+            vec2(BACKGROUND_SIZE, BACKGROUND_SIZE),
+            0.,
+            1.,
+        );
+    }
+    raster.draw_layers(layers);
+    raster
+}
+
+pub fn export_png(
+    layers: &[Layer],
+    background: Option<&DynamicImage>,
+    bounds: Rect,
+    scale: f32,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    render(layers, background, bounds, scale).save(path)?;
+    Ok(())
+}
+
+// exports a saved project without opening a window
+pub fn export_project(
+    project: &Path,
+    out: &Path,
+    width: f32,
+    height: f32,
+    scale: f32,
+) -> Result<(), Box<dyn Error>> {
+    let document = Document::open(project)?;
+    let background = document.get_background()?;
+    export_png(
+        &document.get_layers()?,
+        background.as_ref(),
+        Rect::from_w_h(width, height),
+        scale,
+        out,
+    )
+}
+
+pub fn hsv_to_rgba(hue: f32, saturation: f32, value: f32) -> [f32; 4] {
+    let h = (hue.rem_euclid(360.)) / 60.;
+    let c = value * saturation;
+    let x = c * (1. - (h % 2. - 1.).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.),
+        1 => (x, c, 0.),
+        2 => (0., c, x),
+        3 => (0., x, c),
+        4 => (x, 0., c),
+        _ => (c, 0., x),
+    };
+    let m = value - c;
+    [r + m, g + m, b + m, 1.]
+}
+
+pub fn doc_to_rgba(color: ColorDoc) -> [f32; 4] {
+    let rgba = hsv_to_rgba(color.hue, color.saturation, color.value);
+    [rgba[0], rgba[1], rgba[2], color.alpha]
+}
+
+fn premultiplied(c: [f32; 4]) -> [f32; 4] {
+    [c[0] * c[3], c[1] * c[3], c[2] * c[3], c[3]]
+}
+
+fn rgba_points(pixels: &[PixelDoc]) -> Vec<(Point2, [f32; 4])> {
+    pixels
+        .iter()
+        .map(|p| (pt2(p.point[0], p.point[1]), doc_to_rgba(p.color)))
+        .collect()
+}
+
+fn lerp_color(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+        a[3] + (b[3] - a[3]) * t,
+    ]
+}
+
+// distance from p to segment ab and where along ab it lands
+fn segment_distance(p: Point2, a: Point2, b: Point2) -> (f32, f32) {
+    let ab = b - a;
+    let len2 = ab.length_squared();
+    if len2 <= f32::EPSILON {
+        return (p.distance(a), 0.);
+    }
+    let t = ((p - a).dot(ab) / len2).clamp(0., 1.);
+    (p.distance(a + ab * t), t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{
+        document::{encode_png, StyleDoc},
+        shapes::ArrowHead,
+        style::{StrokeCap, StrokeJoin},
+    };
+
+    fn red() -> ColorDoc {
+        ColorDoc {
+            hue: 0.,
+            saturation: 1.,
+            value: 1.,
+            alpha: 1.,
+        }
+    }
+
+    fn style() -> StyleDoc {
+        StyleDoc {
+            fill: Some(red()),
+            stroke: red(),
+            width: 2.,
+            dash: Vec::new(),
+            join: StrokeJoin::Round,
+            cap: StrokeCap::Round,
+            blend: Blend::Normal,
+        }
+    }
+
+    fn line(points: &[[f32; 2]], widths: Vec<f32>, brush: Option<Brush>) -> ElementDoc {
+        ElementDoc::Line {
+            pixels: points
+                .iter()
+                .map(|&point| PixelDoc {
+                    point,
+                    color: red(),
+                })
+                .collect(),
+            weight: 4.,
+            widths,
+            color: red(),
+            brush,
+            blend: Blend::Normal,
+        }
+    }
+
+    // a 40 by 40 transparent canvas around the origin, one pixel per point
+    fn paint(elem: ElementDoc) -> Raster {
+        let mut raster = Raster::new(Rect::from_w_h(40., 40.), 1., [0.; 4]);
+        raster.draw_element(&elem, 1.);
+        raster
+    }
+
+    // the pixel under a world point
+    fn at(raster: &Raster, x: f32, y: f32) -> [f32; 4] {
+        raster.get_pixel((x + 20.).floor() as u32, (20. - y).floor() as u32)
+    }
+
+    fn covered(raster: &Raster, x: f32, y: f32) -> bool {
+        at(raster, x, y)[3] > 0.99
+    }
+
+    fn empty(raster: &Raster, x: f32, y: f32) -> bool {
+        at(raster, x, y)[3] == 0.
+    }
+
+    // a white 10 by 10 canvas with a ring of `gray` one pixel wide around
+    // the 6 by 6 pixels in the middle
+    fn ring(gray: f32) -> Raster {
+        let mut raster = Raster::new(Rect::from_w_h(10., 10.), 1., [1.; 4]);
+        raster.fill_rect(pt2(0., 0.), vec2(8., 8.), [gray, gray, gray, 1.]);
+        raster.fill_rect(pt2(0., 0.), vec2(6., 6.), [1.; 4]);
+        raster
+    }
+
+    #[test]
+    fn lines_cover_their_weight() {
+        let raster = paint(line(&[[-10., 0.5], [10., 0.5]], Vec::new(), None));
+        assert!(covered(&raster, 0.5, 0.5));
+        assert_eq!(at(&raster, 0.5, 0.5), [1., 0., 0., 1.]);
+        assert!(covered(&raster, 0.5, 1.5));
+        assert!(empty(&raster, 0.5, 3.5));
+        // round caps reach past the ends
+        assert!(covered(&raster, 11.5, 0.5));
+        assert!(empty(&raster, 13.5, 0.5));
+    }
+
+    #[test]
+    fn line_widths_taper_the_stroke() {
+        let raster = paint(line(&[[-10., 0.5], [10., 0.5]], vec![1., 0.25], None));
+        assert!(covered(&raster, -9.5, 1.5));
+        assert!(empty(&raster, 9.5, 2.5));
+    }
+
+    #[test]
+    fn segments_only_cover_their_own_stretch() {
+        let raster = paint(line(
+            &[[-15., -14.5], [15., -14.5], [15., 15.]],
+            Vec::new(),
+            None,
+        ));
+        assert!(covered(&raster, 0.5, -14.5));
+        assert!(covered(&raster, 14.5, -14.5));
+        assert!(covered(&raster, 15.5, 0.5));
+        assert!(empty(&raster, -14.5, 14.5));
+        assert!(empty(&raster, 0.5, 0.5));
+    }
+
+    #[test]
+    fn brushes_stamp_along_the_line() {
+        let raster = paint(line(
+            &[[-10., 0.5], [10., 0.5]],
+            Vec::new(),
+            Some(Brush::default()),
+        ));
+        assert!(covered(&raster, 0.5, 0.5));
+        assert!(empty(&raster, 0.5, 5.5));
+    }
+
+    #[test]
+    fn rectangles_and_ellipses_fill_inside_their_stroke() {
+        let raster = paint(ElementDoc::Rectangle {
+            center: [0., 0.],
+            wh: [20., 10.],
+            rotation: 0.,
+            style: style(),
+        });
+        assert!(covered(&raster, 0.5, 0.5));
+        assert!(covered(&raster, 9.5, 4.5));
+        assert!(empty(&raster, 0.5, 7.5));
+
+        let raster = paint(ElementDoc::Ellipse {
+            center: [0., 0.],
+            radii: [10., 4.],
+            rotation: 0.,
+            style: style(),
+        });
+        assert!(covered(&raster, 0.5, 0.5));
+        assert!(covered(&raster, 9.5, 0.5));
+        assert!(empty(&raster, 0.5, 6.5));
+    }
+
+    #[test]
+    fn shapes_cover_their_outline() {
+        let raster = paint(ElementDoc::Segment {
+            start: [-10., 0.5],
+            end: [10., 0.5],
+            style: style(),
+        });
+        assert!(covered(&raster, 0.5, 0.5));
+        assert!(empty(&raster, 0.5, 3.5));
+
+        for elem in [
+            ElementDoc::Polygon {
+                center: [0., 0.],
+                radius: 8.,
+                sides: 6,
+                rotation: 0.,
+                style: style(),
+            },
+            ElementDoc::Star {
+                center: [0., 0.],
+                radius: 8.,
+                points: 5,
+                ratio: 0.5,
+                rotation: 0.,
+                style: style(),
+            },
+        ] {
+            let raster = paint(elem);
+            assert!(covered(&raster, 0.5, 0.5));
+            assert!(empty(&raster, 0.5, 12.5));
+            assert!(empty(&raster, 12.5, 0.5));
+        }
+
+        let raster = paint(ElementDoc::Arrow {
+            start: [-15., 0.],
+            end: [15., 0.],
+            head: ArrowHead::Filled,
+            style: style(),
+        });
+        assert!(covered(&raster, 0.5, 0.5));
+        assert!(covered(&raster, 12.5, 0.5));
+        assert!(empty(&raster, 0.5, 8.5));
+    }
+
+    #[test]
+    fn custom_rects_fill_their_frame() {
+        let raster = paint(ElementDoc::RectangleCustom {
+            center: [0., 0.],
+            wh: [10., 6.],
+            weight: 1.,
+            color: red(),
+            blend: Blend::Normal,
+        });
+        assert!(covered(&raster, -4.5, -2.5));
+        assert!(covered(&raster, 4.5, 2.5));
+        assert!(empty(&raster, 5.5, 0.5));
+        assert!(empty(&raster, 0.5, 3.5));
+    }
+
+    #[test]
+    fn images_and_fills_stretch_over_their_frame() {
+        let png = encode_png(&DynamicImage::ImageRgba8(RgbaImage::from_fn(
+            2,
+            1,
+            |x, _| image::Rgba([255 * (1 - x as u8), 0, 255 * x as u8, 255]),
+        )))
+        .unwrap();
+        let raster = paint(ElementDoc::Image {
+            png,
+            center: [0., 0.],
+            wh: [20., 10.],
+            rotation: 0.,
+            opacity: 1.,
+            blend: Blend::Normal,
+        });
+        assert_eq!(at(&raster, -5.5, 0.5), [1., 0., 0., 1.]);
+        assert_eq!(at(&raster, 5.5, 0.5), [0., 0., 1., 1.]);
+        assert!(empty(&raster, 0.5, 5.5));
+
+        let mask = encode_png(&DynamicImage::ImageLuma8(GrayImage::from_fn(
+            2,
+            1,
+            |x, _| Luma([255 * (1 - x as u8)]),
+        )))
+        .unwrap();
+        let raster = paint(ElementDoc::Fill {
+            mask,
+            center: [0., 0.],
+            wh: [20., 10.],
+            rotation: 0.,
+            color: red(),
+            blend: Blend::Normal,
+        });
+        assert_eq!(at(&raster, -5.5, 0.5), [1., 0., 0., 1.]);
+        assert!(empty(&raster, 5.5, 0.5));
+    }
+
+    #[test]
+    fn flood_fill_stops_at_the_outline() {
+        let (mask, center, wh) = ring(0.).flood_fill(pt2(0.5, 0.5), 0.1, false).unwrap();
+        assert_eq!(mask.dimensions(), (6, 6));
+        assert!(mask.pixels().all(|p| p.0[0] == u8::MAX));
+        assert_eq!(center, pt2(0., 0.));
+        assert_eq!(wh, vec2(6., 6.));
+    }
+
+    #[test]
+    fn flood_fill_leaks_past_the_tolerance() {
+        let raster = ring(0.5);
+        let (mask, ..) = raster.flood_fill(pt2(0.5, 0.5), 0.45, false).unwrap();
+        assert_eq!(mask.dimensions(), (6, 6));
+        let (mask, center, wh) = raster.flood_fill(pt2(0.5, 0.5), 0.5, false).unwrap();
+        assert_eq!(mask.dimensions(), (10, 10));
+        assert!(mask.pixels().all(|p| p.0[0] == u8::MAX));
+        assert_eq!((center, wh), (pt2(0., 0.), vec2(10., 10.)));
+    }
+
+    #[test]
+    fn flood_fill_smooths_the_edge() {
+        let (mask, center, wh) = ring(0.5).flood_fill(pt2(0.5, 0.5), 0.4, true).unwrap();
+        assert_eq!((center, wh), (pt2(0., 0.), vec2(8., 8.)));
+        // the ring is 0.1 past the tolerance, fading over FILL_EDGE
+        let level = ((1. - 0.1 / FILL_EDGE) * 254.).round() as u8;
+        assert_eq!(mask.get_pixel(0, 3).0[0], level);
+        assert_eq!(mask.get_pixel(7, 3).0[0], level);
+        assert_eq!(mask.get_pixel(3, 0).0[0], level);
+        assert_eq!(mask.get_pixel(3, 3).0[0], u8::MAX);
+        // corners only touch the inside diagonally
+        assert_eq!(mask.get_pixel(0, 0).0[0], 0);
+        // too far past the tolerance to be touched at all
+        let (mask, ..) = ring(0.).flood_fill(pt2(0.5, 0.5), 0.4, true).unwrap();
+        assert_eq!(mask.dimensions(), (6, 6));
+    }
+
+    #[test]
+    fn flood_fill_needs_a_seed_on_the_canvas() {
+        let raster = ring(0.);
+        assert!(raster.flood_fill(pt2(5.5, 0.), 0.1, false).is_none());
+        assert!(raster.flood_fill(pt2(0., -5.5), 0.1, false).is_none());
+        // a seed on the outline fills only the outline
+        let (mask, ..) = raster.flood_fill(pt2(-3.5, 0.5), 0.1, false).unwrap();
+        assert_eq!(mask.dimensions(), (8, 8));
+        assert_eq!(mask.get_pixel(3, 3).0[0], 0);
+        assert_eq!(mask.get_pixel(0, 3).0[0], u8::MAX);
+    }
+}
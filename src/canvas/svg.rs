@@ -0,0 +1,1420 @@
+use super::raster::doc_to_rgba;
+use crate::{
+    diffusion::decode_image,
+    model::{
+        blend::Blend,
+        document::{decode_png, encode_png, ColorDoc, ElementDoc, LayerDoc, PixelDoc, StyleDoc},
+        elements::Elements,
+        layers::Layer,
+        picture::tint,
+        shapes::{arrow_outlines, polygon_points, star_points},
+        stroke,
+        style::{is_closed, StrokeCap, StrokeJoin},
+    },
+};
+use nannou::{geom::Rect, image::DynamicImage, prelude::*};
+use std::{error::Error, fmt::Write, fs, path::Path};
+
+// marks the rects that come from the Custom Rect tool so they load back as one
+const CUSTOM_RECT_CLASS: &str = "custom-rect";
+// marks the groups holding a pencil stroke, the reader takes the points and
+// colors from their data attributes and skips what is drawn inside
+const STROKE_CLASS: &str = "stroke";
+// curves in imported paths are flattened into this many points
+const CURVE_STEPS: usize = 16;
+
+// canvas coordinates have the origin in the middle and y going up, svg has
+// it in the top left corner with y going down
+fn to_svg(p: Point2, bounds: Rect) -> Point2 {
+    pt2(p.x - bounds.left(), bounds.top() - p.y)
+}
+
+fn from_svg(p: Point2, bounds: Rect) -> Point2 {
+    pt2(p.x + bounds.left(), bounds.top() - p.y)
+}
+
+fn hex_color(color: ColorDoc) -> String {
+    let rgba = doc_to_rgba(color);
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        (rgba[0] * 255.).round() as u8,
+        (rgba[1] * 255.).round() as u8,
+        (rgba[2] * 255.).round() as u8
+    )
+}
+
+// with the alpha as a fourth byte when the color is translucent
+fn hex_rgba(color: ColorDoc) -> String {
+    if color.alpha < 1. {
+        format!(
+            "{}{:02x}",
+            hex_color(color),
+            (color.alpha * 255.).round() as u8
+        )
+    } else {
+        hex_color(color)
+    }
+}
+
+// fill-opacity or stroke-opacity of translucent colors
+fn opacity_attr(name: &str, color: ColorDoc) -> String {
+    if color.alpha < 1. {
+        format!(r#" {}-opacity="{}""#, name, color.alpha)
+    } else {
+        String::new()
+    }
+}
+
+// css mix-blend-mode of a blend, erasing has none
+fn blend_mode(blend: Blend) -> Option<&'static str> {
+    match blend {
+        Blend::Normal => Some("normal"),
+        Blend::Multiply => Some("multiply"),
+        Blend::Screen => Some("screen"),
+        Blend::Overlay => Some("overlay"),
+        Blend::Add => Some("plus-lighter"),
+        Blend::Erase => None,
+    }
+}
+
+fn parse_blend(text: &str) -> Option<Blend> {
+    Blend::ALL
+        .iter()
+        .copied()
+        .find(|&blend| blend_mode(blend) == Some(text))
+}
+
+fn points_attr(points: &[Point2], bounds: Rect) -> String {
+    points
+        .iter()
+        .map(|&p| {
+            let p = to_svg(p, bounds);
+            format!("{:.2},{:.2}", p.x, p.y)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// one polyline per run of equally coloured points, svg strokes carry one color
+fn write_pixels(out: &mut String, pixels: &[PixelDoc], weight: f32, bounds: Rect) {
+    let mut start = 0;
+    while start < pixels.len() {
+        let color = pixels[start].color;
+        let mut end = start + 1;
+        while end < pixels.len() && pixels[end].color == color {
+            end += 1;
+        }
+        // reach into the next run so the stroke stays connected
+        let last = end.min(pixels.len() - 1);
+        let points: Vec<Point2> = pixels[start..=last]
+            .iter()
+            .map(|p| pt2(p.point[0], p.point[1]))
+            .collect();
+        let _ = writeln!(
+            out,
+            r#"    <polyline points="{}" fill="none" stroke="{}"{} stroke-width="{}" stroke-linecap="round" stroke-linejoin="round"/>"#,
+            points_attr(&points, bounds),
+            hex_color(color),
+            opacity_attr("stroke", color),
+            weight
+        );
+        start = end;
+    }
+}
+
+// the group around a stroke, it carries the points, their colors and the
+// widths of strokes that change width
+fn write_line(out: &mut String, pixels: &[PixelDoc], widths: &[f32], weight: f32, bounds: Rect) {
+    let points: Vec<Point2> = pixels.iter().map(|p| pt2(p.point[0], p.point[1])).collect();
+    let colors: Vec<String> = pixels.iter().map(|p| hex_rgba(p.color)).collect();
+    let variable = !widths.is_empty() && widths.len() == pixels.len();
+    let widths_attr = if variable {
+        let widths: Vec<String> = widths.iter().map(f32::to_string).collect();
+        format!(r#" data-widths="{}""#, widths.join(" "))
+    } else {
+        String::new()
+    };
+    let _ = writeln!(
+        out,
+        r#"    <g class="{}" data-weight="{}"{} data-points="{}" data-colors="{}">"#,
+        STROKE_CLASS,
+        weight,
+        widths_attr,
+        points_attr(&points, bounds),
+        colors.join(" ")
+    );
+    if variable {
+        write_outline(out, pixels, widths, weight, bounds);
+    } else {
+        write_pixels(out, pixels, weight, bounds);
+    }
+    let _ = writeln!(out, "    </g>");
+}
+
+// strokes of changing width have no svg stroke, their outline is filled
+fn write_outline(out: &mut String, pixels: &[PixelDoc], widths: &[f32], weight: f32, bounds: Rect) {
+    let pixels: Vec<(Point2, Hsva)> = pixels
+        .iter()
+        .map(|p| (pt2(p.point[0], p.point[1]), p.color.into()))
+        .collect();
+    let halves: Vec<f32> = widths.iter().map(|w| w * weight / 2.).collect();
+    let points: Vec<Point2> = stroke::outline(&pixels, &halves)
+        .into_iter()
+        .map(|(p, _)| p)
+        .collect();
+    let color = match pixels.first() {
+        Some((_, color)) => (*color).into(),
+        None => return,
+    };
+    let _ = writeln!(
+        out,
+        r#"    <polygon points="{}" fill="{}"{}/>"#,
+        points_attr(&points, bounds),
+        hex_color(color),
+        opacity_attr("fill", color)
+    );
+}
+
+// fill and stroke attributes of a shape
+fn style_attrs(style: &StyleDoc) -> String {
+    let mut attrs = match style.fill {
+        Some(color) => format!(
+            r#"fill="{}"{}"#,
+            hex_color(color),
+            opacity_attr("fill", color)
+        ),
+        None => String::from(r#"fill="none""#),
+    };
+    if style.width <= 0. {
+        attrs.push_str(r#" stroke="none""#);
+        return attrs;
+    }
+    let _ = write!(
+        attrs,
+        r#" stroke="{}"{} stroke-width="{}" stroke-linejoin="{}" stroke-linecap="{}""#,
+        hex_color(style.stroke),
+        opacity_attr("stroke", style.stroke),
+        style.width,
+        match style.join {
+            StrokeJoin::Miter => "miter",
+            StrokeJoin::Round => "round",
+            StrokeJoin::Bevel => "bevel",
+        },
+        match style.cap {
+            StrokeCap::Butt => "butt",
+            StrokeCap::Round => "round",
+            StrokeCap::Square => "square",
+        }
+    );
+    if !style.dash.is_empty() {
+        let dash: Vec<String> = style
+            .dash
+            .iter()
+            .map(|d| format!("{:.2}", d * style.width))
+            .collect();
+        let _ = write!(attrs, r#" stroke-dasharray="{}""#, dash.join(" "));
+    }
+    attrs
+}
+
+// closed outlines repeat their first point and go out as polygons
+fn write_styled(out: &mut String, outline: &[Point2], style: &StyleDoc, bounds: Rect) {
+    let (tag, points) = if is_closed(outline) {
+        ("polygon", &outline[..outline.len() - 1])
+    } else {
+        ("polyline", outline)
+    };
+    let _ = writeln!(
+        out,
+        r#"    <{} points="{}" {}/>"#,
+        tag,
+        points_attr(points, bounds),
+        style_attrs(style)
+    );
+}
+
+fn write_element(out: &mut String, elem: &ElementDoc, bounds: Rect) {
+    match elem {
+        ElementDoc::Line {
+            pixels,
+            weight,
+            widths,
+            ..
+        } => write_line(out, pixels, widths, *weight, bounds),
+        ElementDoc::Rectangle {
+            center,
+            wh,
+            rotation,
+            style,
+        } => {
+            let c = to_svg(pt2(center[0], center[1]), bounds);
+            let _ = writeln!(
+                out,
+                r#"    <rect x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}" {} transform="rotate({:.3} {:.2} {:.2})"/>"#,
+                c.x - wh[0].abs() / 2.,
+                c.y - wh[1].abs() / 2.,
+                wh[0].abs(),
+                wh[1].abs(),
+                style_attrs(style),
+                -rotation.to_degrees(),
+                c.x,
+                c.y
+            );
+        }
+        ElementDoc::Ellipse {
+            center,
+            radii,
+            rotation,
+            style,
+        } => {
+            let c = to_svg(pt2(center[0], center[1]), bounds);
+            let _ = writeln!(
+                out,
+                r#"    <ellipse cx="{:.2}" cy="{:.2}" rx="{:.2}" ry="{:.2}" {} transform="rotate({:.3} {:.2} {:.2})"/>"#,
+                c.x,
+                c.y,
+                radii[0].abs(),
+                radii[1].abs(),
+                style_attrs(style),
+                -rotation.to_degrees(),
+                c.x,
+                c.y
+            );
+        }
+        ElementDoc::RectangleCustom {
+            center, wh, color, ..
+        } => {
+            let corner = to_svg(pt2(center[0] - wh[0] / 2., center[1] + wh[1] / 2.), bounds);
+            let _ = writeln!(
+                out,
+                r#"    <rect class="{}" x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}" fill="{}"{}/>"#,
+                CUSTOM_RECT_CLASS,
+                corner.x,
+                corner.y,
+                wh[0].abs(),
+                wh[1].abs(),
+                hex_color(*color),
+                opacity_attr("fill", *color)
+            );
+        }
+        ElementDoc::Image {
+            png,
+            center,
+            wh,
+            rotation,
+            opacity,
+            ..
+        } => {
+            let c = to_svg(pt2(center[0], center[1]), bounds);
+            let _ = writeln!(
+                out,
+                r#"    <image x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}" opacity="{}" transform="rotate({:.3} {:.2} {:.2})" preserveAspectRatio="none" href="data:image/png;base64,{}"/>"#,
+                c.x - wh[0].abs() / 2.,
+                c.y - wh[1].abs() / 2.,
+                wh[0].abs(),
+                wh[1].abs(),
+                opacity,
+                // svg turns clockwise because its y axis points down
+                -rotation.to_degrees(),
+                c.x,
+                c.y,
+                png
+            );
+        }
+        // fills go out as pictures, they come back as pictures too
+        ElementDoc::Fill {
+            mask,
+            center,
+            wh,
+            rotation,
+            color,
+            ..
+        } => {
+            let png = decode_png(mask)
+                .map(|mask| DynamicImage::ImageRgba8(tint(&mask.to_luma8(), (*color).into())))
+                .and_then(|img| encode_png(&img));
+            if let Ok(png) = png {
+                let image = ElementDoc::Image {
+                    png,
+                    center: *center,
+                    wh: *wh,
+                    rotation: *rotation,
+                    opacity: 1.,
+                    blend: Blend::Normal,
+                };
+                write_element(out, &image, bounds);
+            }
+        }
+        ElementDoc::Segment { start, end, style } => {
+            let outline = [pt2(start[0], start[1]), pt2(end[0], end[1])];
+            write_styled(out, &outline, style, bounds);
+        }
+        ElementDoc::Polygon {
+            center,
+            radius,
+            sides,
+            rotation,
+            style,
+        } => {
+            let outline = polygon_points(pt2(center[0], center[1]), *radius, *sides, *rotation);
+            write_styled(out, &outline, style, bounds);
+        }
+        ElementDoc::Star {
+            center,
+            radius,
+            points,
+            ratio,
+            rotation,
+            style,
+        } => {
+            let outline = star_points(
+                pt2(center[0], center[1]),
+                *radius,
+                *points,
+                *ratio,
+                *rotation,
+            );
+            write_styled(out, &outline, style, bounds);
+        }
+        ElementDoc::Arrow {
+            start,
+            end,
+            head,
+            style,
+        } => {
+            let start = pt2(start[0], start[1]);
+            let end = pt2(end[0], end[1]);
+            for (outline, style) in arrow_outlines(start, end, *head, &style.into()) {
+                write_styled(out, &outline, &(&style).into(), bounds);
+            }
+        }
+    }
+}
+
+pub fn to_svg_string(layers: &[Layer], bounds: Rect) -> Result<String, Box<dyn Error>> {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = bounds.w(),
+        h = bounds.h()
+    );
+    for layer in layers.iter() {
+        let _ = writeln!(
+            out,
+            r#"  <g id="{}" opacity="{}"{}>"#,
+            escape(&layer.name),
+            layer.opacity,
+            if layer.visible {
+                ""
+            } else {
+                r#" display="none""#
+            }
+        );
+        for elem in layer.elements.iter() {
+            let doc = elem.to_doc()?;
+            let blend = doc.get_blend();
+            if blend.is_normal() {
+                write_element(&mut out, &doc, bounds);
+                continue;
+            }
+            // svg has no paint that takes away what is under it
+            let mode = blend_mode(blend).ok_or_else(|| {
+                format!(
+                    "svg can not erase, layer \"{}\" has erasing elements",
+                    layer.name
+                )
+            })?;
+            let _ = writeln!(out, r#"    <g style="mix-blend-mode:{}">"#, mode);
+            write_element(&mut out, &doc, bounds);
+            let _ = writeln!(out, "    </g>");
+        }
+        let _ = writeln!(out, "  </g>");
+    }
+    let _ = writeln!(out, "</svg>");
+    Ok(out)
+}
+
+pub fn export_svg(layers: &[Layer], bounds: Rect, path: &Path) -> Result<(), Box<dyn Error>> {
+    fs::write(path, to_svg_string(layers, bounds)?)?;
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// reading
+
+// affine transform stored as [a, b, c, d, e, f] like the svg matrix()
+type Transform = [f32; 6];
+
+const IDENTITY: Transform = [1., 0., 0., 1., 0., 0.];
+
+fn multiply(m: Transform, n: Transform) -> Transform {
+    [
+        m[0] * n[0] + m[2] * n[1],
+        m[1] * n[0] + m[3] * n[1],
+        m[0] * n[2] + m[2] * n[3],
+        m[1] * n[2] + m[3] * n[3],
+        m[0] * n[4] + m[2] * n[5] + m[4],
+        m[1] * n[4] + m[3] * n[5] + m[5],
+    ]
+}
+
+fn apply(m: Transform, p: Point2) -> Point2 {
+    pt2(
+        m[0] * p.x + m[2] * p.y + m[4],
+        m[1] * p.x + m[3] * p.y + m[5],
+    )
+}
+
+// how much the transform scales lengths such as stroke widths or radii
+fn transform_scale(m: Transform) -> f32 {
+    (m[0] * m[3] - m[1] * m[2]).abs().sqrt()
+}
+
+fn numbers(text: &str) -> Vec<f32> {
+    let mut out = Vec::new();
+    let mut current = String::new();
+    for c in text.chars() {
+        let exponent = current.ends_with('e') || current.ends_with('E');
+        let sign = (c == '-' || c == '+') && exponent;
+        if c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || sign {
+            // a second dot starts a new number, "1.5.5" is 1.5 and .5
+            if c == '.' && current.contains('.') && !exponent {
+                out.extend(current.parse::<f32>().ok());
+                current.clear();
+            }
+            current.push(c);
+        } else {
+            out.extend(current.parse::<f32>().ok());
+            current.clear();
+            if c == '-' || c == '+' {
+                current.push(c);
+            }
+        }
+    }
+    out.extend(current.parse::<f32>().ok());
+    out
+}
+
+fn parse_transform(text: &str) -> Transform {
+    let mut m = IDENTITY;
+    for part in text.split(')') {
+        let mut split = part.splitn(2, '(');
+        let name = split
+            .next()
+            .unwrap_or("")
+            .trim()
+            .trim_start_matches(',')
+            .trim();
+        let args = numbers(split.next().unwrap_or(""));
+        let arg = |i: usize, default: f32| args.get(i).copied().unwrap_or(default);
+        let t = match name {
+            "matrix" if args.len() == 6 => [args[0], args[1], args[2], args[3], args[4], args[5]],
+            "translate" => [1., 0., 0., 1., arg(0, 0.), arg(1, 0.)],
+            "scale" => [arg(0, 1.), 0., 0., arg(1, arg(0, 1.)), 0., 0.],
+            "rotate" => {
+                let (sin, cos) = arg(0, 0.).to_radians().sin_cos();
+                let (cx, cy) = (arg(1, 0.), arg(2, 0.));
+                let r = [cos, sin, -sin, cos, 0., 0.];
+                multiply(
+                    [1., 0., 0., 1., cx, cy],
+                    multiply(r, [1., 0., 0., 1., -cx, -cy]),
+                )
+            }
+            _ => continue,
+        };
+        m = multiply(m, t);
+    }
+    m
+}
+
+fn parse_color(text: &str) -> Option<ColorDoc> {
+    let text = text.trim();
+    let rgb = if let Some(hex) = text.strip_prefix('#') {
+        let digits: Vec<u32> = hex.chars().filter_map(|c| c.to_digit(16)).collect();
+        let byte = |i: usize| digits[i] * 16 + digits[i + 1];
+        match digits.len() {
+            3 => [digits[0] * 17, digits[1] * 17, digits[2] * 17],
+            6 => [byte(0), byte(2), byte(4)],
+            8 => {
+                let mut color = rgb_to_doc(
+                    byte(0) as f32 / 255.,
+                    byte(2) as f32 / 255.,
+                    byte(4) as f32 / 255.,
+                );
+                color.alpha = byte(6) as f32 / 255.;
+                return Some(color);
+            }
+            _ => return None,
+        }
+    } else if let Some(args) = text.strip_prefix("rgb(") {
+        let n = numbers(args);
+        if n.len() < 3 {
+            return None;
+        }
+        let channel = |v: f32| {
+            if args.contains('%') {
+                (v * 2.55).round() as u32
+            } else {
+                v.round() as u32
+            }
+        };
+        [channel(n[0]), channel(n[1]), channel(n[2])]
+    } else {
+        match text {
+            "black" => [0, 0, 0],
+            "white" => [255, 255, 255],
+            "red" => [255, 0, 0],
+            "green" => [0, 128, 0],
+            "blue" => [0, 0, 255],
+            "yellow" => [255, 255, 0],
+            "gray" | "grey" => [128, 128, 128],
+            _ => return None,
+        }
+    };
+    Some(rgb_to_doc(
+        rgb[0].min(255) as f32 / 255.,
+        rgb[1].min(255) as f32 / 255.,
+        rgb[2].min(255) as f32 / 255.,
+    ))
+}
+
+pub fn rgb_to_doc(r: f32, g: f32, b: f32) -> ColorDoc {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    let hue = if delta <= f32::EPSILON {
+        0.
+    } else if max == r {
+        60. * ((g - b) / delta).rem_euclid(6.)
+    } else if max == g {
+        60. * ((b - r) / delta + 2.)
+    } else {
+        60. * ((r - g) / delta + 4.)
+    };
+    ColorDoc {
+        hue,
+        saturation: if max <= 0. { 0. } else { delta / max },
+        value: max,
+        alpha: 1.,
+    }
+}
+
+// presentation attribute or inline style of the node itself, the style
+// wins like in browsers
+fn declared<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    if let Some(style) = node.attribute("style") {
+        for decl in style.split(';') {
+            let mut kv = decl.splitn(2, ':');
+            if kv.next().map(str::trim) == Some(name) {
+                if let Some(value) = kv.next() {
+                    return Some(value.trim());
+                }
+            }
+        }
+    }
+    node.attribute(name)
+}
+
+// the value the node inherits from itself or the closest group declaring it
+fn style<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.ancestors()
+        .filter(|n| n.is_element())
+        .find_map(|n| declared(n, name))
+}
+
+// a fill or stroke color with its fill-opacity or stroke-opacity
+fn paint(node: roxmltree::Node, name: &str) -> Option<ColorDoc> {
+    let mut color = style(node, name).and_then(parse_color)?;
+    color.alpha = style(node, &format!("{}-opacity", name))
+        .and_then(|v| numbers(v).first().copied())
+        .unwrap_or(color.alpha)
+        .clamp(0., 1.);
+    Some(color)
+}
+
+fn attr(node: roxmltree::Node, name: &str) -> f32 {
+    node.attribute(name)
+        .and_then(|v| numbers(v).first().copied())
+        .unwrap_or(0.)
+}
+
+fn node_transform(node: roxmltree::Node) -> Transform {
+    let mut chain: Vec<_> = node
+        .ancestors()
+        .filter_map(|n| n.attribute("transform"))
+        .collect();
+    chain.reverse();
+    chain
+        .into_iter()
+        .fold(IDENTITY, |m, t| multiply(m, parse_transform(t)))
+}
+
+fn line_doc(points: Vec<Point2>, weight: f32, color: ColorDoc) -> ElementDoc {
+    ElementDoc::Line {
+        pixels: points
+            .into_iter()
+            .map(|p| PixelDoc {
+                point: [p.x, p.y],
+                color,
+            })
+            .collect(),
+        weight,
+        widths: Vec::new(),
+        color,
+        brush: None,
+        blend: Blend::Normal,
+    }
+}
+
+fn cubic(p0: Point2, p1: Point2, p2: Point2, p3: Point2, t: f32) -> Point2 {
+    let u = 1. - t;
+    p0 * u * u * u + p1 * 3. * u * u * t + p2 * 3. * u * t * t + p3 * t * t * t
+}
+
+// splits path data into subpaths of points, curves are flattened and arcs
+// are replaced by their end point
+pub fn parse_path(d: &str) -> Vec<Vec<Point2>> {
+    let mut tokens: Vec<(char, Vec<f32>)> = Vec::new();
+    let mut start = None;
+    for (i, c) in d.char_indices() {
+        if c.is_ascii_alphabetic() && c != 'e' && c != 'E' {
+            if let Some(s) = start {
+                let cmd = d[s..].chars().next().unwrap();
+                tokens.push((cmd, numbers(&d[s + 1..i])));
+            }
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        let cmd = d[s..].chars().next().unwrap();
+        tokens.push((cmd, numbers(&d[s + 1..])));
+    }
+
+    let mut paths = Vec::new();
+    let mut current: Vec<Point2> = Vec::new();
+    let mut pos = pt2(0., 0.);
+    let mut subpath_start = pos;
+    let mut last_control: Option<Point2> = None;
+    for (cmd, args) in tokens {
+        let relative = cmd.is_ascii_lowercase();
+        let base = |pos: Point2| if relative { pos } else { pt2(0., 0.) };
+        match cmd.to_ascii_uppercase() {
+            'M' => {
+                if current.len() > 1 {
+                    paths.push(std::mem::take(&mut current));
+                }
+                current.clear();
+                for (i, xy) in args.chunks_exact(2).enumerate() {
+                    pos = base(pos) + pt2(xy[0], xy[1]);
+                    if i == 0 {
+                        subpath_start = pos;
+                    }
+                    current.push(pos);
+                }
+                last_control = None;
+            }
+            'L' | 'T' => {
+                for xy in args.chunks_exact(2) {
+                    pos = base(pos) + pt2(xy[0], xy[1]);
+                    current.push(pos);
+                }
+                last_control = None;
+            }
+            'H' => {
+                for x in args {
+                    pos = pt2(if relative { pos.x + x } else { x }, pos.y);
+                    current.push(pos);
+                }
+                last_control = None;
+            }
+            'V' => {
+                for y in args {
+                    pos = pt2(pos.x, if relative { pos.y + y } else { y });
+                    current.push(pos);
+                }
+                last_control = None;
+            }
+            'C' | 'S' | 'Q' => {
+                let upper = cmd.to_ascii_uppercase();
+                let size = if upper == 'C' { 6 } else { 4 };
+                for c in args.chunks_exact(size) {
+                    let b = base(pos);
+                    let (c1, c2, end) = match upper {
+                        'C' => (
+                            b + pt2(c[0], c[1]),
+                            b + pt2(c[2], c[3]),
+                            b + pt2(c[4], c[5]),
+                        ),
+                        'S' => (
+                            last_control.map_or(pos, |lc| pos * 2. - lc),
+                            b + pt2(c[0], c[1]),
+                            b + pt2(c[2], c[3]),
+                        ),
+                        _ => {
+                            // a quadratic is a cubic with both controls at 2/3
+                            let q = b + pt2(c[0], c[1]);
+                            let end = b + pt2(c[2], c[3]);
+                            (
+                                pos + (q - pos) * (2. / 3.),
+                                end + (q - end) * (2. / 3.),
+                                end,
+                            )
+                        }
+                    };
+                    for step in 1..=CURVE_STEPS {
+                        current.push(cubic(pos, c1, c2, end, step as f32 / CURVE_STEPS as f32));
+                    }
+                    last_control = Some(c2);
+                    pos = end;
+                }
+            }
+            'A' => {
+                for a in args.chunks_exact(7) {
+                    pos = base(pos) + pt2(a[5], a[6]);
+                    current.push(pos);
+                }
+                last_control = None;
+            }
+            'Z' => {
+                current.push(subpath_start);
+                pos = subpath_start;
+                if current.len() > 1 {
+                    paths.push(std::mem::take(&mut current));
+                }
+                current.push(pos);
+                last_control = None;
+            }
+            _ => {}
+        }
+    }
+    if current.len() > 1 {
+        paths.push(current);
+    }
+    paths
+}
+
+// fill and stroke of a shape, a missing fill is black like in browsers
+fn read_style(node: roxmltree::Node, width: f32, scale: f32) -> StyleDoc {
+    let black = ColorDoc {
+        hue: 0.,
+        saturation: 0.,
+        value: 0.,
+        alpha: 1.,
+    };
+    let fill = match style(node, "fill") {
+        Some(_) => paint(node, "fill"),
+        None => Some(black),
+    };
+    let stroke = paint(node, "stroke");
+    let width = if stroke.is_some() { width } else { 0. };
+    let dash = match style(node, "stroke-dasharray") {
+        Some(v) if width > 0. => numbers(v).iter().map(|d| d * scale / width).collect(),
+        _ => Vec::new(),
+    };
+    StyleDoc {
+        fill,
+        stroke: stroke.unwrap_or(black),
+        width,
+        dash,
+        join: match style(node, "stroke-linejoin") {
+            Some("round") => StrokeJoin::Round,
+            Some("bevel") => StrokeJoin::Bevel,
+            _ => StrokeJoin::Miter,
+        },
+        cap: match style(node, "stroke-linecap") {
+            Some("round") => StrokeCap::Round,
+            Some("square") => StrokeCap::Square,
+            _ => StrokeCap::Butt,
+        },
+        blend: Blend::Normal,
+    }
+}
+
+fn read_node(node: roxmltree::Node, bounds: Rect, out: &mut Vec<ElementDoc>) {
+    let m = node_transform(node);
+    let to_canvas = |p: Point2| from_svg(apply(m, p), bounds);
+    let scale = transform_scale(m);
+    let stroke = paint(node, "stroke");
+    let fill = paint(node, "fill");
+    let stroke_width = style(node, "stroke-width")
+        .and_then(|v| numbers(v).first().copied())
+        .unwrap_or(1.)
+        * scale;
+    let color = stroke.or(fill).unwrap_or(ColorDoc {
+        hue: 0.,
+        saturation: 0.,
+        value: 0.,
+        alpha: 1.,
+    });
+    match node.tag_name().name() {
+        "g" if node.attribute("class") == Some(STROKE_CLASS) => {
+            let n = numbers(node.attribute("data-points").unwrap_or(""));
+            let points: Vec<Point2> = n
+                .chunks_exact(2)
+                .map(|xy| to_canvas(pt2(xy[0], xy[1])))
+                .collect();
+            let colors: Vec<ColorDoc> = node
+                .attribute("data-colors")
+                .unwrap_or("")
+                .split_whitespace()
+                .filter_map(parse_color)
+                .collect();
+            if points.is_empty() {
+                return;
+            }
+            let weight = attr(node, "data-weight") * scale;
+            let shares = numbers(node.attribute("data-widths").unwrap_or(""));
+            let mut doc = line_doc(points, weight, color);
+            if let ElementDoc::Line {
+                pixels,
+                color,
+                widths,
+                ..
+            } = &mut doc
+            {
+                if shares.len() == pixels.len() {
+                    *widths = shares;
+                }
+                if colors.len() == pixels.len() && !colors.is_empty() {
+                    pixels
+                        .iter_mut()
+                        .zip(&colors)
+                        .for_each(|(p, &c)| p.color = c);
+                    *color = colors[0];
+                }
+            }
+            out.push(doc);
+        }
+        "rect" => {
+            let (x, y, w, h) = (
+                attr(node, "x"),
+                attr(node, "y"),
+                attr(node, "width"),
+                attr(node, "height"),
+            );
+            let center = to_canvas(pt2(x + w / 2., y + h / 2.));
+            let wh = [w * scale, h * scale];
+            if node.attribute("class") == Some(CUSTOM_RECT_CLASS) {
+                out.push(ElementDoc::RectangleCustom {
+                    center: [center.x, center.y],
+                    wh,
+                    weight: 0.,
+                    color: fill.unwrap_or(color),
+                    blend: Blend::Normal,
+                });
+            } else {
+                out.push(ElementDoc::Rectangle {
+                    center: [center.x, center.y],
+                    wh,
+                    rotation: -m[1].atan2(m[0]),
+                    style: read_style(node, stroke_width, scale),
+                });
+            }
+        }
+        "circle" | "ellipse" => {
+            let (rx, ry) = if node.tag_name().name() == "circle" {
+                (attr(node, "r"), attr(node, "r"))
+            } else {
+                (attr(node, "rx"), attr(node, "ry"))
+            };
+            let center = to_canvas(pt2(attr(node, "cx"), attr(node, "cy")));
+            out.push(ElementDoc::Ellipse {
+                center: [center.x, center.y],
+                radii: [rx * scale, ry * scale],
+                rotation: -m[1].atan2(m[0]),
+                style: read_style(node, stroke_width, scale),
+            });
+        }
+        "line" => {
+            let points = vec![
+                to_canvas(pt2(attr(node, "x1"), attr(node, "y1"))),
+                to_canvas(pt2(attr(node, "x2"), attr(node, "y2"))),
+            ];
+            out.push(line_doc(points, stroke_width, color));
+        }
+        "polyline" | "polygon" => {
+            let n = numbers(node.attribute("points").unwrap_or(""));
+            let mut points: Vec<Point2> = n
+                .chunks_exact(2)
+                .map(|xy| to_canvas(pt2(xy[0], xy[1])))
+                .collect();
+            if node.tag_name().name() == "polygon" && !points.is_empty() {
+                points.push(points[0]);
+            }
+            if points.len() > 1 {
+                out.push(line_doc(points, stroke_width, color));
+            }
+        }
+        "image" => {
+            let href = node
+                .attribute("href")
+                .or_else(|| node.attribute(("http://www.w3.org/1999/xlink", "href")))
+                .unwrap_or("");
+            let image = if href.starts_with("data:") {
+                decode_image(href).ok()
+            } else {
+                nannou::image::open(href).ok()
+            };
+            let png = match image.as_ref().map(encode_png) {
+                Some(Ok(png)) => png,
+                _ => return,
+            };
+            let (x, y, w, h) = (
+                attr(node, "x"),
+                attr(node, "y"),
+                attr(node, "width"),
+                attr(node, "height"),
+            );
+            let center = to_canvas(pt2(x + w / 2., y + h / 2.));
+            // opacity is not inherited, the one of a layer group stays there
+            let opacity = declared(node, "opacity")
+                .and_then(|v| numbers(v).first().copied())
+                .unwrap_or(1.);
+            out.push(ElementDoc::Image {
+                png,
+                center: [center.x, center.y],
+                wh: [w * scale, h * scale],
+                rotation: -m[1].atan2(m[0]),
+                opacity,
+                blend: Blend::Normal,
+            });
+        }
+        "path" => {
+            for path in parse_path(node.attribute("d").unwrap_or("")) {
+                let points = path.into_iter().map(to_canvas).collect();
+                out.push(line_doc(points, stroke_width, color));
+            }
+        }
+        _ => {}
+    }
+}
+
+// whether the node or a group around it below `top` is not drawn
+fn hidden(node: roxmltree::Node, top: Option<roxmltree::Node>) -> bool {
+    node.ancestors()
+        .take_while(|&n| Some(n) != top)
+        .any(|n| declared(n, "display") == Some("none") || n.tag_name().name() == "defs")
+}
+
+// reads the drawable elements in `node`, the group `top` is left out of
+// the hidden check
+fn read_tree(
+    node: roxmltree::Node,
+    top: Option<roxmltree::Node>,
+    bounds: Rect,
+    out: &mut Vec<ElementDoc>,
+) {
+    for node in node.descendants().filter(|n| n.is_element()) {
+        let in_stroke = node
+            .ancestors()
+            .skip(1)
+            .any(|n| n.attribute("class") == Some(STROKE_CLASS));
+        if !hidden(node, top) && !in_stroke {
+            let start = out.len();
+            read_node(node, bounds, out);
+            // the blend of the node or a group around it
+            let blend = style(node, "mix-blend-mode")
+                .and_then(parse_blend)
+                .unwrap_or_default();
+            out[start..].iter_mut().for_each(|doc| doc.set_blend(blend));
+        }
+    }
+}
+
+// what an svg holds, the groups at the top are layers like the ones export
+// writes or inkscape makes, the elements outside of them are loose
+#[derive(Debug, Default)]
+pub struct SvgDoc {
+    pub loose: Vec<ElementDoc>,
+    pub layers: Vec<LayerDoc>,
+}
+
+fn read_layer(group: roxmltree::Node, bounds: Rect, number: usize) -> LayerDoc {
+    let label = group
+        .attribute(("http://www.inkscape.org/namespaces/inkscape", "label"))
+        .or_else(|| group.attribute("id"));
+    let mut elements = Vec::new();
+    read_tree(group, Some(group), bounds, &mut elements);
+    LayerDoc {
+        name: label.map_or_else(|| format!("Layer {}", number), String::from),
+        visible: declared(group, "display") != Some("none"),
+        opacity: declared(group, "opacity")
+            .and_then(|v| numbers(v).first().copied())
+            .unwrap_or(1.)
+            .clamp(0., 1.),
+        locked: false,
+        elements,
+    }
+}
+
+pub fn from_svg_string(text: &str) -> Result<SvgDoc, Box<dyn Error>> {
+    let doc = roxmltree::Document::parse(text)?;
+    let root = doc.root_element();
+    let view_box = numbers(root.attribute("viewBox").unwrap_or(""));
+    let (w, h) = if view_box.len() == 4 {
+        (view_box[2], view_box[3])
+    } else {
+        (attr(root, "width"), attr(root, "height"))
+    };
+    // the viewport is centered on the canvas origin
+    let origin = if view_box.len() == 4 {
+        pt2(view_box[0], view_box[1])
+    } else {
+        pt2(0., 0.)
+    };
+    let bounds = Rect::from_x_y_w_h(-origin.x, origin.y, w, h);
+    let mut svg = SvgDoc::default();
+    for node in root.children().filter(|n| n.is_element()) {
+        if node.tag_name().name() == "g" {
+            let layer = read_layer(node, bounds, svg.layers.len() + 1);
+            svg.layers.push(layer);
+        } else {
+            read_tree(node, None, bounds, &mut svg.loose);
+        }
+    }
+    Ok(svg)
+}
+
+// the loose elements and the layers of an svg file
+pub fn import_svg(path: &Path) -> Result<(Vec<Elements>, Vec<Layer>), Box<dyn Error>> {
+    let svg = from_svg_string(&fs::read_to_string(path)?)?;
+    let loose = svg
+        .loose
+        .iter()
+        .map(ElementDoc::to_element)
+        .collect::<Result<_, _>>()?;
+    let layers = svg
+        .layers
+        .iter()
+        .map(LayerDoc::to_layer)
+        .collect::<Result<_, _>>()?;
+    Ok((loose, layers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(hue: f32) -> ColorDoc {
+        ColorDoc {
+            hue,
+            saturation: 1.,
+            value: 1.,
+            alpha: 1.,
+        }
+    }
+
+    fn close(a: &[f32], b: &[f32]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() < 0.01)
+    }
+
+    fn style() -> StyleDoc {
+        StyleDoc {
+            fill: Some(rgb(0.)),
+            stroke: rgb(240.),
+            width: 2.,
+            dash: Vec::new(),
+            join: StrokeJoin::Round,
+            cap: StrokeCap::Square,
+            blend: Blend::Normal,
+        }
+    }
+
+    fn layer(name: &str, elements: Vec<ElementDoc>) -> LayerDoc {
+        LayerDoc {
+            name: String::from(name),
+            visible: true,
+            opacity: 1.,
+            locked: false,
+            elements,
+        }
+    }
+
+    // writes the layers out and reads them back
+    fn round_trip(layers: Vec<LayerDoc>) -> SvgDoc {
+        let layers: Vec<Layer> = layers.iter().map(|l| l.to_layer().unwrap()).collect();
+        let text = to_svg_string(&layers, Rect::from_w_h(200., 100.)).unwrap();
+        from_svg_string(&text).unwrap()
+    }
+
+    #[test]
+    fn shapes_come_back() {
+        let rect = ElementDoc::Rectangle {
+            center: [10., 20.],
+            wh: [30., 40.],
+            rotation: 0.5,
+            style: style(),
+        };
+        let ellipse = ElementDoc::Ellipse {
+            center: [-40., 5.],
+            radii: [15., 5.],
+            rotation: -0.3,
+            style: style(),
+        };
+        let svg = round_trip(vec![layer("Layer 1", vec![rect, ellipse])]);
+        assert!(svg.loose.is_empty());
+        match &svg.layers[0].elements[..] {
+            [ElementDoc::Rectangle {
+                center,
+                wh,
+                rotation,
+                style: read,
+            }, ElementDoc::Ellipse {
+                center: ellipse_center,
+                radii,
+                rotation: ellipse_rotation,
+                ..
+            }] => {
+                assert!(close(center, &[10., 20.]) && close(wh, &[30., 40.]));
+                assert!(close(&[*rotation, *ellipse_rotation], &[0.5, -0.3]));
+                assert!(close(ellipse_center, &[-40., 5.]) && close(radii, &[15., 5.]));
+                assert_eq!(*read, style());
+            }
+            other => panic!("read {:?}", other),
+        }
+    }
+
+    #[test]
+    fn strokes_keep_their_point_colors() {
+        let mut translucent = rgb(120.);
+        translucent.alpha = 0.5;
+        let pixels: Vec<PixelDoc> = [
+            ([0., 0.], rgb(0.)),
+            ([10., 5.], rgb(0.)),
+            ([20., -5.], rgb(240.)),
+            ([30., 0.], translucent),
+        ]
+        .iter()
+        .map(|&(point, color)| PixelDoc { point, color })
+        .collect();
+        let line = ElementDoc::Line {
+            pixels: pixels.clone(),
+            weight: 3.,
+            widths: Vec::new(),
+            color: rgb(0.),
+            brush: None,
+            blend: Blend::Normal,
+        };
+        let svg = round_trip(vec![layer("Layer 1", vec![line])]);
+        // the polylines drawn inside the group are not read again
+        assert_eq!(svg.layers[0].elements.len(), 1);
+        match &svg.layers[0].elements[0] {
+            ElementDoc::Line {
+                pixels: read,
+                weight,
+                ..
+            } => {
+                assert_eq!(*weight, 3.);
+                assert_eq!(read.len(), pixels.len());
+                for (read, pixel) in read.iter().zip(&pixels) {
+                    assert!(close(&read.point, &pixel.point));
+                    assert_eq!(read.color.hue, pixel.color.hue);
+                    assert!((read.color.alpha - pixel.color.alpha).abs() < 0.01);
+                }
+            }
+            other => panic!("read {:?}", other),
+        }
+    }
+
+    #[test]
+    fn strokes_keep_their_widths() {
+        let pixels: Vec<PixelDoc> = [[0., 0.], [10., 5.], [20., -5.]]
+            .iter()
+            .map(|&point| PixelDoc {
+                point,
+                color: rgb(0.),
+            })
+            .collect();
+        let line = ElementDoc::Line {
+            pixels: pixels.clone(),
+            weight: 4.,
+            widths: vec![0.3, 1., 0.5],
+            color: rgb(0.),
+            brush: None,
+            blend: Blend::Normal,
+        };
+        let svg = round_trip(vec![layer("Layer 1", vec![line])]);
+        // the outline drawn inside the group is not read again
+        assert_eq!(svg.layers[0].elements.len(), 1);
+        match &svg.layers[0].elements[0] {
+            ElementDoc::Line {
+                pixels: read,
+                weight,
+                widths,
+                ..
+            } => {
+                assert_eq!(*weight, 4.);
+                assert_eq!(*widths, vec![0.3, 1., 0.5]);
+                let points: Vec<f32> = read.iter().flat_map(|p| p.point).collect();
+                let expected: Vec<f32> = pixels.iter().flat_map(|p| p.point).collect();
+                assert!(close(&points, &expected));
+            }
+            other => panic!("read {:?}", other),
+        }
+    }
+
+    #[test]
+    fn blend_modes_come_back_and_erasing_is_refused() {
+        let mut rect = ElementDoc::Rectangle {
+            center: [0., 0.],
+            wh: [10., 10.],
+            rotation: 0.,
+            style: style(),
+        };
+        rect.set_blend(Blend::Overlay);
+        let svg = round_trip(vec![layer("Layer 1", vec![rect.clone()])]);
+        assert_eq!(svg.layers[0].elements[0].get_blend(), Blend::Overlay);
+
+        rect.set_blend(Blend::Erase);
+        let layers = vec![layer("Layer 1", vec![rect]).to_layer().unwrap()];
+        assert!(to_svg_string(&layers, Rect::from_w_h(200., 100.)).is_err());
+    }
+
+    #[test]
+    fn hidden_layers_come_back_hidden() {
+        let mut hidden = layer("Sketch & notes", Vec::new());
+        hidden.visible = false;
+        hidden.opacity = 0.4;
+        let svg = round_trip(vec![layer("Layer 1", Vec::new()), hidden]);
+        let read: Vec<_> = svg
+            .layers
+            .iter()
+            .map(|l| (l.name.as_str(), l.visible, l.opacity))
+            .collect();
+        assert_eq!(
+            read,
+            vec![("Layer 1", true, 1.), ("Sketch & notes", false, 0.4)]
+        );
+    }
+
+    #[test]
+    fn elements_outside_groups_are_loose() {
+        let text = r##"<svg xmlns="http://www.w3.org/2000/svg"
+            xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape"
+            width="100" height="100">
+          <circle cx="50" cy="50" r="10" fill="red"/>
+          <defs><rect width="5" height="5"/></defs>
+          <g inkscape:label="Inked" style="display:none;opacity:0.5">
+            <line x1="0" y1="0" x2="10" y2="0" stroke="blue"/>
+            <g display="none"><rect width="5" height="5"/></g>
+          </g>
+        </svg>"##;
+        let svg = from_svg_string(text).unwrap();
+        assert_eq!(svg.loose.len(), 1);
+        assert!(matches!(svg.loose[0], ElementDoc::Ellipse { .. }));
+        let inked = &svg.layers[0];
+        assert_eq!((inked.name.as_str(), inked.visible), ("Inked", false));
+        assert_eq!(inked.opacity, 0.5);
+        assert_eq!(inked.elements.len(), 1);
+    }
+
+    #[test]
+    fn polylines_and_polygons_read_as_lines() {
+        let text = r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 100 100">
+          <polyline points="50,50 60,50 60,40" fill="none" stroke="#00f" stroke-width="3"/>
+          <polygon points="50 50 60 50 60 40" fill="#f00"/>
+        </svg>"##;
+        let svg = from_svg_string(text).unwrap();
+        let lines: Vec<(Vec<f32>, f32, f32)> = svg
+            .loose
+            .iter()
+            .map(|doc| match doc {
+                ElementDoc::Line {
+                    pixels,
+                    weight,
+                    color,
+                    ..
+                } => (
+                    pixels.iter().flat_map(|p| p.point).collect(),
+                    *weight,
+                    color.hue,
+                ),
+                other => panic!("read {:?}", other),
+            })
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert!(close(&lines[0].0, &[0., 0., 10., 0., 10., 10.]));
+        assert_eq!((lines[0].1, lines[0].2), (3., 240.));
+        // polygons close back to their first point
+        assert!(close(&lines[1].0, &[0., 0., 10., 0., 10., 10., 0., 0.]));
+        assert_eq!((lines[1].1, lines[1].2), (1., 0.));
+    }
+
+    #[test]
+    fn paths_follow_relative_and_curve_commands() {
+        let square = parse_path("M 10 10 l 10 0 v 10 h -10 z");
+        assert_eq!(
+            square,
+            vec![vec![
+                pt2(10., 10.),
+                pt2(20., 10.),
+                pt2(20., 20.),
+                pt2(10., 20.),
+                pt2(10., 10.)
+            ]]
+        );
+        // a curve and its smooth continuation, relative to where they start
+        let curves = parse_path("m0 0 c0 10 10 10 10 0 s10 -10 10 0");
+        let points = &curves[0];
+        assert_eq!(points.len(), 1 + 2 * CURVE_STEPS);
+        let half = CURVE_STEPS / 2;
+        assert!(close(&points[half].to_array(), &[5., 7.5]));
+        assert!(close(&points[CURVE_STEPS + half].to_array(), &[15., -7.5]));
+        assert!(close(&points[2 * CURVE_STEPS].to_array(), &[20., 0.]));
+        let quadratic = parse_path("M0 0 Q 10 20 20 0");
+        assert!(close(&quadratic[0][half].to_array(), &[10., 10.]));
+        // two subpaths
+        assert_eq!(parse_path("M0 0 L1 1 M5 5 L6 6").len(), 2);
+    }
+
+    #[test]
+    fn path_elements_take_their_transform() {
+        let text = r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 100 100">
+          <g transform="translate(50 50)">
+            <path d="M0 0 L10 0" stroke="black" stroke-width="2" transform="scale(2)"/>
+          </g>
+        </svg>"#;
+        let svg = from_svg_string(text).unwrap();
+        match &svg.layers[0].elements[0] {
+            ElementDoc::Line { pixels, weight, .. } => {
+                // the middle of the viewBox is the canvas origin, y goes up
+                assert!(close(&pixels[0].point, &[0., 0.]));
+                assert!(close(&pixels[1].point, &[20., 0.]));
+                assert_eq!(*weight, 4.);
+            }
+            other => panic!("read {:?}", other),
+        }
+    }
+
+    #[test]
+    fn transforms_compose_left_to_right() {
+        let m = parse_transform("translate(10 20) scale(2)");
+        assert!(close(&apply(m, pt2(1., 1.)).to_array(), &[12., 22.]));
+        let m = parse_transform("rotate(90 10 10)");
+        assert!(close(&apply(m, pt2(20., 10.)).to_array(), &[10., 20.]));
+        let m = parse_transform("matrix(1,0,0,1,5,-5)");
+        assert!(close(&apply(m, pt2(0., 0.)).to_array(), &[5., -5.]));
+        assert_eq!(transform_scale(parse_transform("scale(3)")), 3.);
+        assert_eq!(parse_transform("skewX(10)"), IDENTITY);
+    }
+
+    #[test]
+    fn colors_parse() {
+        assert_eq!(parse_color("#f00"), Some(rgb(0.)));
+        assert_eq!(parse_color("#0000ff"), Some(rgb(240.)));
+        assert_eq!(parse_color("rgb(0, 255, 0)"), Some(rgb(120.)));
+        assert_eq!(parse_color(" rgb(100%, 0%, 0%) "), Some(rgb(0.)));
+        assert_eq!(parse_color("white").map(|c| c.value), Some(1.));
+        let translucent = parse_color("#ff000080").unwrap();
+        assert!((translucent.alpha - 0.5).abs() < 0.01);
+        assert_eq!(parse_color("#ff00"), None);
+        assert_eq!(parse_color("chartreuse"), None);
+    }
+}
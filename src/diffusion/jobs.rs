@@ -0,0 +1,246 @@
+use super::{interrupt, progress};
+use nannou::{image::DynamicImage, prelude::*};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+pub type JobWork = Box<dyn FnOnce() -> Result<DynamicImage, String> + Send>;
+
+// where a finished image goes once update picks it up
+#[derive(Clone, Debug, PartialEq)]
+pub enum JobTarget {
+    // a new picture on the active layer
+    NewImage {
+        center: Vec2,
+    },
+    // the frame of the Custom Rect the image replaces
+    Region {
+        center: Vec2,
+        wh: Vec2,
+    },
+    // a picture over the frame on a new layer above the active one
+    NewLayer {
+        center: Vec2,
+        wh: Vec2,
+    },
+    // new pixels for the picture with this id
+    Picture {
+        id: u64,
+    },
+    // the picture with this id grown to a new frame, the Custom Rect at
+    // `region` is removed
+    Extend {
+        id: u64,
+        center: Vec2,
+        wh: Vec2,
+        region: (Vec2, Vec2),
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum JobState {
+    Queued,
+    Running,
+    Done,
+    Failed(String),
+    Cancelled,
+}
+
+pub struct JobStatus {
+    pub id: usize,
+    pub label: String,
+    pub state: JobState,
+    pub progress: f32,
+    // id of the board the image goes to
+    board: u64,
+    target: JobTarget,
+    cancel: Arc<AtomicBool>,
+}
+
+impl JobStatus {
+    pub fn is_active(&self) -> bool {
+        matches!(self.state, JobState::Queued | JobState::Running)
+    }
+}
+
+struct Job {
+    id: usize,
+    // server to ask for progress and to interrupt, if the work talks to one
+    url: Option<String>,
+    work: JobWork,
+    cancel: Arc<AtomicBool>,
+}
+
+enum JobEvent {
+    State(usize, JobState),
+    Progress(usize, f32),
+    Finished(usize, DynamicImage),
+}
+
+// runs generation requests one at a time on a worker thread, update polls
+// the events so the ui never waits on the network
+pub struct Jobs {
+    requests: Sender<Job>,
+    events: Receiver<JobEvent>,
+    statuses: Vec<JobStatus>,
+    next_id: usize,
+    // board new jobs are submitted for
+    board: u64,
+}
+
+impl Jobs {
+    pub fn new() -> Self {
+        let (requests, jobs) = channel::<Job>();
+        let (events_tx, events) = channel();
+        thread::spawn(move || {
+            for job in jobs {
+                run_job(job, &events_tx);
+            }
+        });
+        Self {
+            requests,
+            events,
+            statuses: Vec::new(),
+            next_id: 0,
+            board: 0,
+        }
+    }
+
+    pub fn set_board(&mut self, board: u64) {
+        self.board = board
+    }
+
+    pub fn submit(
+        &mut self,
+        label: String,
+        target: JobTarget,
+        url: Option<String>,
+        work: JobWork,
+    ) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        let cancel = Arc::new(AtomicBool::new(false));
+        let state = match self.requests.send(Job {
+            id,
+            url,
+            work,
+            cancel: cancel.clone(),
+        }) {
+            Ok(()) => JobState::Queued,
+            Err(_) => JobState::Failed(String::from("job worker stopped")),
+        };
+        self.statuses.push(JobStatus {
+            id,
+            label,
+            state,
+            progress: 0.,
+            board: self.board,
+            target,
+            cancel,
+        });
+        id
+    }
+
+    pub fn cancel(&mut self, id: usize) {
+        if let Some(status) = self.statuses.iter_mut().find(|s| s.id == id) {
+            status.cancel.store(true, Ordering::SeqCst);
+            // queued jobs are skipped by the worker without reporting back
+            if status.state == JobState::Queued {
+                status.state = JobState::Cancelled;
+            }
+        }
+    }
+
+    pub fn clear_finished(&mut self) {
+        self.statuses.retain(JobStatus::is_active);
+    }
+
+    pub fn get_statuses(&self) -> &Vec<JobStatus> {
+        &self.statuses
+    }
+
+    // drains the worker events and returns the images that are ready with
+    // the board they belong to
+    pub fn poll(&mut self) -> Vec<(u64, JobTarget, DynamicImage)> {
+        let mut finished = Vec::new();
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                JobEvent::State(id, state) => {
+                    if let Some(status) = self.statuses.iter_mut().find(|s| s.id == id) {
+                        status.state = state;
+                    }
+                }
+                JobEvent::Progress(id, progress) => {
+                    if let Some(status) = self.statuses.iter_mut().find(|s| s.id == id) {
+                        status.progress = progress;
+                    }
+                }
+                JobEvent::Finished(id, image) => {
+                    if let Some(status) = self.statuses.iter_mut().find(|s| s.id == id) {
+                        status.state = JobState::Done;
+                        status.progress = 1.;
+                        finished.push((status.board, status.target.clone(), image));
+                    }
+                }
+            }
+        }
+        finished
+    }
+}
+
+fn run_job(job: Job, events: &Sender<JobEvent>) {
+    if job.cancel.load(Ordering::SeqCst) {
+        return;
+    }
+    let _ = events.send(JobEvent::State(job.id, JobState::Running));
+    let (result_tx, result_rx) = channel();
+    let work = job.work;
+    thread::spawn(move || {
+        let _ = result_tx.send(work());
+    });
+    let mut interrupted = false;
+    loop {
+        match result_rx.recv_timeout(PROGRESS_INTERVAL) {
+            Ok(result) => {
+                let event = match result {
+                    _ if job.cancel.load(Ordering::SeqCst) => {
+                        JobEvent::State(job.id, JobState::Cancelled)
+                    }
+                    Ok(image) => JobEvent::Finished(job.id, image),
+                    Err(err) => JobEvent::State(job.id, JobState::Failed(err)),
+                };
+                let _ = events.send(event);
+                return;
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                let url = match &job.url {
+                    Some(url) => url,
+                    None => continue,
+                };
+                if job.cancel.load(Ordering::SeqCst) {
+                    if !interrupted {
+                        interrupted = true;
+                        let _ = interrupt(url);
+                    }
+                } else if let Ok(p) = progress(url) {
+                    let _ = events.send(JobEvent::Progress(job.id, p));
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                let _ = events.send(JobEvent::State(
+                    job.id,
+                    JobState::Failed(String::from("job stopped without a result")),
+                ));
+                return;
+            }
+        }
+    }
+}
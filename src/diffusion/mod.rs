@@ -1,16 +1,598 @@
-// use std::{sync::Arc, error::Error};
+use crate::{
+    canvas::raster::{self, Raster},
+    model::{
+        board::{next_board_id, Board},
+        camera::Camera,
+        document::encode_png,
+        elements::Line,
+        history::History,
+        layers::Layer,
+        picture::Picture,
+    },
+};
+pub(crate) mod jobs;
+use jobs::{JobTarget, Jobs};
+use nannou::{
+    image::{
+        self,
+        imageops::{self, FilterType},
+        DynamicImage, GenericImageView, RgbaImage,
+    },
+    prelude::*,
+    wgpu::Texture,
+};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, time::Duration};
 
-// pub fn text_2_image (prompt: &str) -> Result<(), Box<dyn Error>> {
-//     let environment = Arc::new(OrtEnvironment::builder().build()?);
-//     let mut scheduler = EulerDiscreteScheduler::stable_diffusion_v1_optimized_default()?;
-//     let pipeline = StableDiffusionPipeline::new(&environment, "./pyke-diffusers-sd15", StableDiffusionOptions::default()).expect("pipeline error");
+// settings for a txt2img server speaking the AUTOMATIC1111 web ui api,
+// project files keep all but the server address and timeout
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiffusionConfig {
+    #[serde(skip)]
+    pub url: String,
+    pub negative_prompt: String,
+    pub steps: u32,
+    pub cfg_scale: f32,
+    pub width: u32,
+    pub height: u32,
+    pub seed: i64,
+    // region sizes are rounded to a multiple of this, the server needs 8
+    // and the model works best with 64
+    pub snap: u32,
+    // how much img2img may change the pixels it is given, 0 to 1
+    pub denoising_strength: f32,
+    // feathering of the inpaint mask in pixels
+    pub mask_blur: u32,
+    #[serde(skip)]
+    pub timeout: Duration,
+}
 
-//     print!("after pipeline");
-    
-//     let imgs = pipeline.txt2img(prompt, &mut scheduler, StableDiffusionTxt2ImgOptions::default()).expect("file error");
-//     println!("imags {:?}", imgs);
-//     // imgs[0].clone().into_rgb8().save("result.png")?;
+impl Default for DiffusionConfig {
+    fn default() -> Self {
+        Self {
+            url: String::from("http://127.0.0.1:7860"),
+            negative_prompt: String::new(),
+            steps: 20,
+            cfg_scale: 7.,
+            width: 512,
+            height: 512,
+            seed: -1,
+            snap: 64,
+            denoising_strength: 0.75,
+            mask_blur: 4,
+            timeout: Duration::from_secs(300),
+        }
+    }
+}
 
-//     Ok(())
-// }
+#[derive(Serialize)]
+struct Txt2ImgRequest<'a> {
+    prompt: &'a str,
+    negative_prompt: &'a str,
+    steps: u32,
+    cfg_scale: f32,
+    width: u32,
+    height: u32,
+    seed: i64,
+    batch_size: u32,
+}
 
+#[derive(Deserialize)]
+struct ImagesResponse {
+    images: Vec<String>,
+}
+
+pub fn text_2_image(
+    config: &DiffusionConfig,
+    prompt: &str,
+) -> Result<DynamicImage, Box<dyn Error>> {
+    let request = Txt2ImgRequest {
+        prompt,
+        negative_prompt: &config.negative_prompt,
+        steps: config.steps,
+        cfg_scale: config.cfg_scale,
+        width: config.width,
+        height: config.height,
+        seed: config.seed,
+        batch_size: 1,
+    };
+    let response: ImagesResponse = reqwest::blocking::Client::builder()
+        .timeout(config.timeout)
+        .build()?
+        .post(format!(
+            "{}/sdapi/v1/txt2img",
+            config.url.trim_end_matches('/')
+        ))
+        .json(&request)
+        .send()?
+        .error_for_status()?
+        .json()?;
+    let data = response.images.first().ok_or("no image in the response")?;
+    decode_image(data)
+}
+
+#[derive(Serialize)]
+struct Img2ImgRequest<'a> {
+    init_images: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mask: Option<String>,
+    mask_blur: u32,
+    inpainting_fill: u32,
+    inpaint_full_res: bool,
+    denoising_strength: f32,
+    prompt: &'a str,
+    negative_prompt: &'a str,
+    steps: u32,
+    cfg_scale: f32,
+    width: u32,
+    height: u32,
+    seed: i64,
+    batch_size: u32,
+}
+
+// what the server starts from under the mask
+#[derive(Clone, Copy, PartialEq)]
+pub enum MaskFill {
+    // the pixels of the init image, to rework what is there
+    Original,
+    // noise, for areas that have nothing in them yet
+    Noise,
+}
+
+// reworks `init`, only where `mask` is white when there is one, the result
+// has the size of `init`
+pub fn image_2_image(
+    config: &DiffusionConfig,
+    prompt: &str,
+    init: &DynamicImage,
+    mask: Option<&DynamicImage>,
+    fill: MaskFill,
+) -> Result<DynamicImage, Box<dyn Error>> {
+    let (width, height) = init.dimensions();
+    let request = Img2ImgRequest {
+        init_images: vec![encode_png(init)?],
+        mask: mask.map(encode_png).transpose()?,
+        mask_blur: config.mask_blur,
+        inpainting_fill: match fill {
+            MaskFill::Original => 1,
+            MaskFill::Noise => 2,
+        },
+        inpaint_full_res: false,
+        denoising_strength: config.denoising_strength,
+        prompt,
+        negative_prompt: &config.negative_prompt,
+        steps: config.steps,
+        cfg_scale: config.cfg_scale,
+        width: snap_size(width as f32, 8),
+        height: snap_size(height as f32, 8),
+        seed: config.seed,
+        batch_size: 1,
+    };
+    let response: ImagesResponse = reqwest::blocking::Client::builder()
+        .timeout(config.timeout)
+        .build()?
+        .post(format!(
+            "{}/sdapi/v1/img2img",
+            config.url.trim_end_matches('/')
+        ))
+        .json(&request)
+        .send()?
+        .error_for_status()?
+        .json()?;
+    let data = response.images.first().ok_or("no image in the response")?;
+    let image = decode_image(data)?;
+    if image.dimensions() == (width, height) {
+        Ok(image)
+    } else {
+        Ok(image.resize_exact(width, height, FilterType::Lanczos3))
+    }
+}
+
+#[derive(Deserialize)]
+struct ProgressResponse {
+    progress: f32,
+}
+
+// how far the server is with the current image, 0 to 1
+pub fn progress(url: &str) -> Result<f32, Box<dyn Error>> {
+    let response: ProgressResponse = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()?
+        .get(format!(
+            "{}/sdapi/v1/progress?skip_current_image=true",
+            url.trim_end_matches('/')
+        ))
+        .send()?
+        .error_for_status()?
+        .json()?;
+    Ok(response.progress)
+}
+
+// stops the image the server is working on
+pub fn interrupt(url: &str) -> Result<(), Box<dyn Error>> {
+    reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()?
+        .post(format!("{}/sdapi/v1/interrupt", url.trim_end_matches('/')))
+        .send()?
+        .error_for_status()?;
+    Ok(())
+}
+
+// the api sends png files as base64, sometimes as a data url
+pub fn decode_image(data: &str) -> Result<DynamicImage, Box<dyn Error>> {
+    let data = match data.find("base64,") {
+        Some(i) => &data[i + "base64,".len()..],
+        None => data,
+    };
+    Ok(image::load_from_memory(&base64::decode(data.trim())?)?)
+}
+
+// the largest width or height the server generates
+pub const MAX_SIZE: u32 = 2048;
+
+pub fn snap_size(size: f32, step: u32) -> u32 {
+    ((size.abs() / step as f32).round() as u32).max(1) * step
+}
+
+// generates an image for the frame of a Custom Rect, the result is placed
+// over the frame and the rect is removed once it arrives
+pub fn add_diffusion_segment(
+    jobs: &mut Jobs,
+    config: &DiffusionConfig,
+    prompt: &str,
+    center: Vec2,
+    wh: Vec2,
+) -> usize {
+    let mut config = config.clone();
+    config.width = snap_size(wh.x, config.snap);
+    config.height = snap_size(wh.y, config.snap);
+    let prompt = prompt.to_string();
+    let url = config.url.clone();
+    jobs.submit(
+        format!(
+            "region {}x{}: {}",
+            config.width,
+            config.height,
+            prompt.trim()
+        ),
+        JobTarget::Region { center, wh },
+        Some(url),
+        Box::new(move || text_2_image(&config, &prompt).map_err(|err| err.to_string())),
+    )
+}
+
+// renders a sketch of the canvas into a finished image, the layers are
+// drawn inside the frame on the job thread and the result goes on a new
+// layer over it
+pub fn add_sketch_segment(
+    jobs: &mut Jobs,
+    config: &DiffusionConfig,
+    prompt: &str,
+    layers: Vec<Layer>,
+    background: DynamicImage,
+    center: Vec2,
+    wh: Vec2,
+) -> usize {
+    let width = snap_size(wh.x, config.snap);
+    let height = snap_size(wh.y, config.snap);
+    let config = config.clone();
+    let prompt = prompt.to_string();
+    let url = config.url.clone();
+    jobs.submit(
+        format!("img2img {}x{}: {}", width, height, prompt.trim()),
+        JobTarget::NewLayer { center, wh },
+        Some(url),
+        Box::new(move || {
+            let sketch = raster::render(
+                &layers,
+                Some(&background),
+                geom::Rect::from_xy_wh(center, wh.abs()),
+                1.,
+            );
+            let init =
+                DynamicImage::ImageRgba8(sketch).resize_exact(width, height, FilterType::Lanczos3);
+            image_2_image(&config, &prompt, &init, None, MaskFill::Original)
+                .map_err(|err| err.to_string())
+        }),
+    )
+}
+
+// white where the strokes cross the picture, black elsewhere, at the size
+// of its pixels
+pub fn paint_mask(picture: &Picture, strokes: &[Line]) -> DynamicImage {
+    let (w, h) = picture.get_image().dimensions();
+    let mut raster = Raster::new(
+        geom::Rect::from_w_h(w as f32, h as f32),
+        1.,
+        [0., 0., 0., 1.],
+    );
+    // strokes are as wide in pixels as they look on the canvas
+    let wh = picture.get_wh().abs();
+    let scale = (w as f32 / wh.x.max(1.) + h as f32 / wh.y.max(1.)) / 2.;
+    for stroke in strokes {
+        let points: Vec<_> = stroke
+            .get_line()
+            .iter()
+            .map(|(p, _)| (picture.to_pixels(*p), [1., 1., 1., 1.]))
+            .collect();
+        raster.stroke_polyline(
+            &points,
+            stroke.get_weight() * scale,
+            stroke.get_widths(),
+            1.,
+        );
+    }
+    DynamicImage::ImageRgba8(raster.to_image())
+}
+
+// regenerates the parts of a picture covered by the mask strokes, the
+// result replaces the pixels of the same picture once it arrives
+pub fn erase_from_canvas(
+    jobs: &mut Jobs,
+    config: &DiffusionConfig,
+    prompt: &str,
+    picture: &Picture,
+    strokes: &[Line],
+) -> usize {
+    let mask = paint_mask(picture, strokes);
+    let init = picture.get_image().clone();
+    let config = config.clone();
+    let prompt = prompt.to_string();
+    let url = config.url.clone();
+    jobs.submit(
+        format!("inpaint: {}", prompt.trim()),
+        JobTarget::Picture {
+            id: picture.get_id(),
+        },
+        Some(url),
+        Box::new(move || {
+            image_2_image(&config, &prompt, &init, Some(&mask), MaskFill::Original)
+                .map_err(|err| err.to_string())
+        }),
+    )
+}
+
+// grows a picture to cover the Custom Rect frame at `region` as well, the
+// new area is generated to match the old pixels which are kept as they are
+pub fn add_outpaint_segment(
+    jobs: &mut Jobs,
+    config: &DiffusionConfig,
+    prompt: &str,
+    picture: &Picture,
+    region: (Vec2, Vec2),
+) -> Result<usize, Box<dyn Error>> {
+    if picture.get_rotation() != 0. {
+        return Err("can not extend a rotated image".into());
+    }
+    let (old_min, old_max) = corners(picture.get_center(), picture.get_wh());
+    let (frame_min, frame_max) = corners(region.0, region.1);
+    let (min, max) = (old_min.min(frame_min), old_max.max(frame_max));
+    let overlaps = frame_min.cmplt(old_max).all() && frame_max.cmpgt(old_min).all();
+    if !overlaps {
+        return Err("the frame has to overlap the image".into());
+    }
+    if min == old_min && max == old_max {
+        return Err("the frame does not reach past the image".into());
+    }
+    // image pixels per point, kept for the whole extended image
+    let (w, h) = picture.get_image().dimensions();
+    let ppu = vec2(w as f32, h as f32) / (old_max - old_min);
+    let width = ((max.x - min.x) * ppu.x).round() as u32;
+    let height = ((max.y - min.y) * ppu.y).round() as u32;
+    if width > MAX_SIZE || height > MAX_SIZE {
+        return Err(format!(
+            "the extended image would be {}x{}, the server makes at most {}x{}",
+            width, height, MAX_SIZE, MAX_SIZE
+        )
+        .into());
+    }
+    let x = ((old_min.x - min.x) * ppu.x).round() as u32;
+    let y = ((max.y - old_max.y) * ppu.y).round() as u32;
+    let original = picture.get_image().to_rgba8();
+    let mut init = RgbaImage::from_pixel(width, height, image::Rgba([127, 127, 127, 255]));
+    imageops::overlay(&mut init, &original, x, y);
+    let mut mask = RgbaImage::from_pixel(width, height, image::Rgba([255, 255, 255, 255]));
+    imageops::overlay(
+        &mut mask,
+        &RgbaImage::from_pixel(w, h, image::Rgba([0, 0, 0, 255])),
+        x,
+        y,
+    );
+    let (init, mask) = (
+        DynamicImage::ImageRgba8(init),
+        DynamicImage::ImageRgba8(mask),
+    );
+    let config = config.clone();
+    let prompt = prompt.to_string();
+    let url = config.url.clone();
+    Ok(jobs.submit(
+        format!("outpaint {}x{}: {}", width, height, prompt.trim()),
+        JobTarget::Extend {
+            id: picture.get_id(),
+            center: (min + max) / 2.,
+            wh: max - min,
+            region,
+        },
+        Some(url),
+        Box::new(move || {
+            let mut image = image_2_image(&config, &prompt, &init, Some(&mask), MaskFill::Noise)
+                .map_err(|err| err.to_string())?
+                .to_rgba8();
+            // the seam may move a little, the old pixels may not
+            imageops::overlay(&mut image, &original, x, y);
+            Ok(DynamicImage::ImageRgba8(image))
+        }),
+    ))
+}
+
+fn corners(center: Vec2, wh: Vec2) -> (Vec2, Vec2) {
+    (center - wh.abs() / 2., center + wh.abs() / 2.)
+}
+
+// puts a new empty layer above the active one and returns its index
+pub fn add_layer(layers: &mut Vec<Layer>, active: usize) -> usize {
+    let index = (active + 1).min(layers.len());
+    layers.insert(index, Layer::new(format!("Layer {}", layers.len() + 1)));
+    index
+}
+
+// an empty board with a transparent background
+pub fn create_new_board(app: &App, name: String, project_path: String, depth: usize) -> Board {
+    let background = DynamicImage::new_rgba8(1, 1);
+    Board {
+        id: next_board_id(),
+        project_path,
+        name,
+        layers: vec![Layer::new(String::from("Layer 1"))],
+        active_layer: 0,
+        texture: Texture::from_image(app, &background),
+        background,
+        prompt: String::from("Enter Prompt"),
+        history: History::new(depth),
+        camera: Camera::default(),
+        mask: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::document::encode_png;
+    use nannou::image::{GenericImageView, RgbaImage};
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread::{self, JoinHandle},
+    };
+
+    // answers one request with `status` and a json `body`, gives the url to
+    // send it to and the request that came in
+    fn serve(status: &str, body: String) -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let status = status.to_string();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            // the headers, then as much body as they announce
+            loop {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let length = text
+                        .lines()
+                        .find_map(|line| {
+                            line.to_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|value| value.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if request.len() >= end + 4 + length {
+                        break;
+                    }
+                }
+                if n == 0 {
+                    break;
+                }
+            }
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (url, handle)
+    }
+
+    fn config(url: String) -> DiffusionConfig {
+        DiffusionConfig {
+            url,
+            timeout: Duration::from_secs(5),
+            ..DiffusionConfig::default()
+        }
+    }
+
+    fn png(width: u32, height: u32) -> String {
+        encode_png(&DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+            width,
+            height,
+            image::Rgba([10, 20, 30, 255]),
+        )))
+        .unwrap()
+    }
+
+    #[test]
+    fn txt2img_decodes_the_first_image() {
+        let body = format!(
+            r#"{{"images":["{}","{}"],"info":"{{}}"}}"#,
+            png(3, 2),
+            png(1, 1)
+        );
+        let (url, server) = serve("200 OK", body);
+        let img = text_2_image(&config(url), "a cat").unwrap();
+        assert_eq!(img.dimensions(), (3, 2));
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /sdapi/v1/txt2img "));
+        assert!(request.contains(r#""prompt":"a cat""#));
+        assert!(request.contains(r#""width":512"#));
+        assert!(request.contains(r#""batch_size":1"#));
+    }
+
+    #[test]
+    fn txt2img_reports_server_errors() {
+        let (url, server) = serve("500 Internal Server Error", String::from("{}"));
+        assert!(text_2_image(&config(url), "a cat").is_err());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn txt2img_needs_an_image() {
+        let (url, server) = serve("200 OK", String::from(r#"{"images":[]}"#));
+        let err = text_2_image(&config(url), "a cat").unwrap_err();
+        assert_eq!(err.to_string(), "no image in the response");
+        server.join().unwrap();
+
+        let (url, server) = serve("200 OK", String::from(r#"{"detail":"Not Found"}"#));
+        assert!(text_2_image(&config(url), "a cat").is_err());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn txt2img_refuses_broken_images() {
+        let (url, server) = serve("200 OK", String::from(r#"{"images":["not an image"]}"#));
+        assert!(text_2_image(&config(url), "a cat").is_err());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn decode_image_skips_data_url_headers() {
+        let plain = decode_image(&png(4, 3)).unwrap();
+        assert_eq!(plain.dimensions(), (4, 3));
+        let url = format!("data:image/png;base64,{}\n", png(2, 5));
+        assert_eq!(decode_image(&url).unwrap().dimensions(), (2, 5));
+
+        assert!(decode_image("%%%").is_err());
+        assert!(decode_image(&base64::encode(b"not a png")).is_err());
+    }
+
+    #[test]
+    fn outpaint_stays_within_the_server_size() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(512, 512));
+        let picture = Picture::new(image, pt2(0., 0.), vec2(512., 512.));
+        let mut jobs = Jobs::new();
+        let config = config(String::from("http://127.0.0.1:1/"));
+        let region = (pt2(1000., 0.), vec2(2000., 100.));
+        let err = add_outpaint_segment(&mut jobs, &config, "sky", &picture, region).unwrap_err();
+        assert!(err.to_string().contains("at most 2048x2048"));
+        assert!(jobs.get_statuses().is_empty());
+    }
+}
@@ -1,8 +1,4 @@
-use crate::model::{
-    elements::{Ellipse, Forms, Pencil, Rectangle, Rectangle_Custom, Tool},
-    Settings,
-};
-use nannou::prelude::*;
+use crate::model::Settings;
 
 pub fn open_shapes(settings: &mut Settings) {
     if settings.get_shapes() {
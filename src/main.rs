@@ -1,17 +1,55 @@
 // use diffusion::text_2_image;
-use crate::egui::Event::Text;
 use nannou::{color, prelude::*};
 use nannou_egui::{egui, Egui};
+use std::path::Path;
+mod canvas;
 mod diffusion;
 mod helpers;
 mod model;
+use diffusion::{jobs::JobState, MAX_SIZE};
 use helpers::open_shapes;
 use model::{
-    elements::{Elements, Ellipse, Forms, Line, Pencil, Rectangle, Rectangle_Custom, Tool},
+    blend::Blend,
+    board::BoardAction,
+    elements::{Element, Ellipse, Line, Rectangle, RectangleCustom, Tool},
+    history::History,
+    layers::{Layer, LayerAction},
+    shapes::{Arrow, ArrowHead, Polygon, Segment, Star},
+    stroke::Smoothing,
+    style::{StrokeCap, StrokeJoin},
     Model, Settings,
 };
 
+// trackpads scroll in pixels, this many of them zoom like one wheel line
+const PIXELS_PER_LINE: f32 = 40.;
+
+enum FileAction {
+    Save,
+    Open,
+    Export,
+    ExportSvg,
+    ImportSvg,
+}
+
 fn main() {
+    // canvas export <project.json> <out.png> <width> <height> <scale>
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() == 7 && args[1] == "export" {
+        let number = |i: usize| {
+            args[i]
+                .parse::<f32>()
+                .expect("export size must be a number")
+        };
+        canvas::raster::export_project(
+            Path::new(&args[2]),
+            Path::new(&args[3]),
+            number(4),
+            number(5),
+            number(6),
+        )
+        .expect("export error");
+        return;
+    }
     nannou::app(model).update(update).run();
 }
 
@@ -31,42 +69,64 @@ fn model(app: &App) -> Model {
     let window = app.window(window_id).unwrap();
     app.set_exit_on_escape(false);
 
-    let texture = wgpu::Texture::from_path(app, "image.png").expect("load file error");
+    let background = nannou::image::open("image.png").expect("load file error");
+    let texture = wgpu::Texture::from_image(app, &background);
 
-    Model::new(
+    let mut model = Model::new(
         Egui::from_window(&window),
-        Settings::new(hsv(10.0, 0.5, 1.0), 1., false),
+        Settings::new(hsva(10.0, 0.5, 1.0, 1.0), 1., false),
         Line::new(),
         Ellipse::default(),
         Rectangle::default(),
-        Rectangle_Custom::default(),
-        Vec::new(),
+        RectangleCustom::default(),
+        vec![Layer::new(String::from("Layer 1"))],
         Tool::Pencil,
         false,
         texture,
-        window_id,
+        background,
         String::from("Enter Prompt"),
-    )
+        History::new(100),
+    );
+    model.load_brushes();
+    model
 }
 
 fn update(app: &App, model: &mut Model, update: Update) {
     model.update(app);
+    let board_names = model.get_board_names();
 
     let Model {
         ref mut egui,
         ref mut settings,
-        ref mut line,
-        ref mut elements,
-        ref mut ellipse,
-        ref mut rect,
-        ref mut rect_custom,
+        ref mut layers,
+        ref mut active_layer,
         ref mut tool,
-        ref mut drawing,
-        ref mut texture,
-        ref mut window_id,
         ref mut prompt,
+        ref mut history,
+        ref mut project_path,
+        ref mut export,
+        ref mut status,
+        ref mut diffusion,
+        ref mut jobs,
+        ref mut mask,
+        ref mut board_name,
+        ref brushes,
+        active_board,
+        ..
     } = *model;
 
+    let mut recolor = false;
+    let mut undo = false;
+    let mut redo = false;
+    let mut file_action = None;
+    let mut synth = false;
+    let mut synth_region = false;
+    let mut inpaint = false;
+    let mut sketch = false;
+    let mut outpaint = false;
+    let mut cancel_job = None;
+    let mut clear_jobs = false;
+    let mut reload_brushes = false;
     egui.set_elapsed_time(update.since_start);
     let ctx = egui.begin_frame();
     egui::Window::new("Settings")
@@ -77,11 +137,46 @@ fn update(app: &App, model: &mut Model, update: Update) {
             ui.horizontal(|ui| {
                 ui.separator();
                 ui.label("Choose a Color");
-                edit_hsv(ui, &mut settings.color);
+                edit_hsva(ui, &mut settings.color);
+                ui.button("Recolor").clicked().then(|| recolor = true);
                 ui.add_space(10.);
                 ui.separator();
                 ui.label("Brush Size");
                 ui.add(egui::Slider::new(&mut settings.weight, 1.0..=100.0));
+                ui.checkbox(&mut settings.variable_width, "Ink");
+                ui.add_space(10.);
+                ui.separator();
+                ui.button("Rubber").clicked().then(|| {
+                    if *tool == Tool::Rubber {
+                        *tool = Tool::Pencil;
+                    } else {
+                        *tool = Tool::Rubber;
+                    }
+                });
+                ui.add_space(5.);
+                ui.button("Select").clicked().then(|| {
+                    if *tool == Tool::Select {
+                        *tool = Tool::Pencil;
+                    } else {
+                        *tool = Tool::Select;
+                    }
+                });
+                ui.add_space(5.);
+                ui.button("Mask").clicked().then(|| {
+                    if *tool == Tool::Mask {
+                        *tool = Tool::Pencil;
+                    } else {
+                        *tool = Tool::Mask;
+                    }
+                });
+                ui.add_space(5.);
+                ui.button("Fill").clicked().then(|| {
+                    if *tool == Tool::Fill {
+                        *tool = Tool::Pencil;
+                    } else {
+                        *tool = Tool::Fill;
+                    }
+                });
                 ui.add_space(10.);
                 ui.separator();
                 ui.add(egui::Button::new("Add Shape").fill(egui::Color32::BLACK))
@@ -90,32 +185,58 @@ fn update(app: &App, model: &mut Model, update: Update) {
                 ui.add_space(5.);
                 if settings.get_shapes() {
                     ui.button("Ellipse").clicked().then(|| {
-                        if ellipse.get_clicked() {
+                        if *tool == Tool::Ellipse {
                             *tool = Tool::Pencil;
-                            ellipse.set_clicked(false);
                         } else {
                             *tool = Tool::Ellipse;
-                            ellipse.set_clicked(true);
                         }
                     });
                     ui.add_space(5.);
                     ui.button("Rectangle").clicked().then(|| {
-                        if rect.get_clicked() {
+                        if *tool == Tool::Rect {
                             *tool = Tool::Pencil;
-                            rect.set_clicked(false);
                         } else {
                             *tool = Tool::Rect;
-                            rect.set_clicked(true);
                         }
                     });
                     ui.add_space(5.);
                     ui.button("Custom Rect").clicked().then(|| {
-                        if rect_custom.get_clicked() {
+                        if *tool == Tool::RectCustom {
+                            *tool = Tool::Pencil;
+                        } else {
+                            *tool = Tool::RectCustom;
+                        }
+                    });
+                    ui.add_space(5.);
+                    ui.button("Line").clicked().then(|| {
+                        if *tool == Tool::Segment {
+                            *tool = Tool::Pencil;
+                        } else {
+                            *tool = Tool::Segment;
+                        }
+                    });
+                    ui.add_space(5.);
+                    ui.button("Polygon").clicked().then(|| {
+                        if *tool == Tool::Polygon {
                             *tool = Tool::Pencil;
-                            rect_custom.set_clicked(false);
                         } else {
-                            *tool = Tool::Rect_Custom;
-                            rect_custom.set_clicked(true);
+                            *tool = Tool::Polygon;
+                        }
+                    });
+                    ui.add_space(5.);
+                    ui.button("Star").clicked().then(|| {
+                        if *tool == Tool::Star {
+                            *tool = Tool::Pencil;
+                        } else {
+                            *tool = Tool::Star;
+                        }
+                    });
+                    ui.add_space(5.);
+                    ui.button("Arrow").clicked().then(|| {
+                        if *tool == Tool::Arrow {
+                            *tool = Tool::Pencil;
+                        } else {
+                            *tool = Tool::Arrow;
                         }
                     });
                 }
@@ -129,83 +250,551 @@ fn update(app: &App, model: &mut Model, update: Update) {
                             .cursor_at_end(true)
                             .desired_width(300.),
                     );
-                    ui.add(egui::Button::new("Synth").fill(egui::Color32::BLACK));
-                })
+                    ui.add(egui::Button::new("Synth").fill(egui::Color32::BLACK))
+                        .clicked()
+                        .then(|| synth = true);
+                    ui.button("Synth in Rect")
+                        .clicked()
+                        .then(|| synth_region = true);
+                    ui.button("Sketch").clicked().then(|| sketch = true);
+                    ui.button("Outpaint").clicked().then(|| outpaint = true);
+                    ui.add(
+                        egui::Slider::new(&mut diffusion.denoising_strength, 0.0..=1.0)
+                            .text("Denoise"),
+                    );
+                    ui.add_enabled(!mask.is_empty(), egui::Button::new("Inpaint"))
+                        .clicked()
+                        .then(|| inpaint = true);
+                    ui.add_enabled(!mask.is_empty(), egui::Button::new("Clear Mask"))
+                        .clicked()
+                        .then(|| mask.clear());
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Server");
+                    ui.add(egui::TextEdit::singleline(&mut diffusion.url).desired_width(180.));
+                    ui.add(
+                        egui::DragValue::new(&mut diffusion.steps)
+                            .prefix("steps ")
+                            .clamp_range(1..=150),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut diffusion.cfg_scale)
+                            .prefix("cfg ")
+                            .speed(0.1)
+                            .clamp_range(1.0..=30.0),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut diffusion.width)
+                            .prefix("w ")
+                            .speed(8)
+                            .clamp_range(64..=MAX_SIZE),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut diffusion.height)
+                            .prefix("h ")
+                            .speed(8)
+                            .clamp_range(64..=MAX_SIZE),
+                    );
+                    ui.label("Snap");
+                    ui.selectable_value(&mut diffusion.snap, 8, "8");
+                    ui.selectable_value(&mut diffusion.snap, 64, "64");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Brush");
+                    for brush in brushes.iter() {
+                        ui.selectable_label(settings.brush == *brush, &brush.name)
+                            .clicked()
+                            .then(|| settings.brush = brush.clone());
+                    }
+                    ui.button("Load Brushes")
+                        .clicked()
+                        .then(|| reload_brushes = true);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Smoothing");
+                    ui.selectable_value(&mut settings.smoothing, Smoothing::Off, "Off");
+                    ui.selectable_value(&mut settings.smoothing, Smoothing::Chaikin, "Chaikin");
+                    ui.selectable_value(
+                        &mut settings.smoothing,
+                        Smoothing::CatmullRom,
+                        "Catmull-Rom",
+                    );
+                    ui.selectable_value(&mut settings.smoothing, Smoothing::Decimate, "Decimate");
+                    ui.selectable_value(&mut settings.smoothing, Smoothing::Bezier, "Bézier");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.tolerance)
+                            .prefix("tolerance ")
+                            .speed(0.1)
+                            .clamp_range(0.1..=20.0),
+                    );
+                    ui.add_enabled(
+                        settings.smoothing == Smoothing::Chaikin,
+                        egui::DragValue::new(&mut settings.passes)
+                            .prefix("passes ")
+                            .clamp_range(1..=5),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Fill");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.fill_tolerance)
+                            .prefix("tolerance ")
+                            .speed(0.01)
+                            .clamp_range(0.0..=1.0),
+                    );
+                    ui.checkbox(&mut settings.fill_antialias, "Smooth edges");
+                });
+                if settings.get_shapes() {
+                    ui.horizontal(|ui| {
+                        ui.label("Shapes");
+                        ui.add(
+                            egui::DragValue::new(&mut settings.sides)
+                                .prefix("sides ")
+                                .clamp_range(3..=24),
+                        );
+                        ui.add(
+                            egui::DragValue::new(&mut settings.star_points)
+                                .prefix("star points ")
+                                .clamp_range(2..=24),
+                        );
+                        ui.add(
+                            egui::DragValue::new(&mut settings.star_ratio)
+                                .prefix("inner ")
+                                .speed(0.01)
+                                .clamp_range(0.05..=1.0),
+                        );
+                        ui.label("Arrow");
+                        ui.selectable_value(&mut settings.arrow_head, ArrowHead::Open, "Open");
+                        ui.selectable_value(&mut settings.arrow_head, ArrowHead::Filled, "Filled");
+                        ui.selectable_value(&mut settings.arrow_head, ArrowHead::Double, "Double");
+                    });
+                    ui.horizontal(|ui| {
+                        // the outline takes the color above, the fill has its own
+                        let style = &mut settings.style;
+                        ui.label("Stroke");
+                        ui.add(
+                            egui::DragValue::new(&mut style.width)
+                                .prefix("width ")
+                                .speed(0.1)
+                                .clamp_range(0.0..=100.0),
+                        );
+                        let mut filled = style.fill.is_some();
+                        ui.checkbox(&mut filled, "Fill");
+                        match (filled, style.fill.is_some()) {
+                            (true, false) => style.fill = Some(settings.color),
+                            (false, true) => style.fill = None,
+                            _ => {}
+                        }
+                        if let Some(fill) = style.fill.as_mut() {
+                            edit_hsva(ui, fill);
+                        }
+                        ui.separator();
+                        ui.selectable_value(&mut style.dash, Vec::new(), "Solid");
+                        ui.selectable_value(&mut style.dash, vec![4., 2.], "Dashed");
+                        ui.selectable_value(&mut style.dash, vec![1., 1.5], "Dotted");
+                        ui.separator();
+                        ui.selectable_value(&mut style.join, StrokeJoin::Miter, "Miter");
+                        ui.selectable_value(&mut style.join, StrokeJoin::Round, "Round");
+                        ui.selectable_value(&mut style.join, StrokeJoin::Bevel, "Bevel");
+                        ui.separator();
+                        ui.selectable_value(&mut style.cap, StrokeCap::Butt, "Butt");
+                        ui.selectable_value(&mut style.cap, StrokeCap::Round, "Round");
+                        ui.selectable_value(&mut style.cap, StrokeCap::Square, "Square");
+                    });
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Blend");
+                    for blend in Blend::ALL {
+                        ui.selectable_value(&mut settings.blend, blend, blend.name());
+                    }
+                });
+                ui.add_space(15.);
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.add_enabled(history.can_undo(), egui::Button::new("Undo"))
+                        .clicked()
+                        .then(|| undo = true);
+                    ui.add_space(5.);
+                    ui.add_enabled(history.can_redo(), egui::Button::new("Redo"))
+                        .clicked()
+                        .then(|| redo = true);
+                    ui.add_space(5.);
+                    ui.button("Clear")
+                        .clicked()
+                        .then(|| history.clear_elements(layers, *active_layer));
+                    ui.add_space(10.);
+                    ui.label("History Depth");
+                    let mut depth = history.get_depth();
+                    if ui.add(egui::Slider::new(&mut depth, 1..=500)).changed() {
+                        history.set_depth(depth);
+                    }
+                });
+                ui.add_space(15.);
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("Project");
+                    ui.add(egui::TextEdit::singleline(&mut *project_path).desired_width(200.));
+                    ui.button("Save")
+                        .clicked()
+                        .then(|| file_action = Some(FileAction::Save));
+                    ui.button("Open")
+                        .clicked()
+                        .then(|| file_action = Some(FileAction::Open));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Export");
+                    ui.add(egui::TextEdit::singleline(&mut export.path).desired_width(200.));
+                    ui.add(egui::DragValue::new(&mut export.width).prefix("w "));
+                    ui.add(egui::DragValue::new(&mut export.height).prefix("h "));
+                    ui.add(
+                        egui::DragValue::new(&mut export.scale)
+                            .prefix("x ")
+                            .speed(0.1)
+                            .clamp_range(0.1..=8.0),
+                    );
+                    ui.button("Export PNG")
+                        .clicked()
+                        .then(|| file_action = Some(FileAction::Export));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("SVG");
+                    ui.add(egui::TextEdit::singleline(&mut export.svg_path).desired_width(200.));
+                    ui.button("Export SVG")
+                        .clicked()
+                        .then(|| file_action = Some(FileAction::ExportSvg));
+                    ui.button("Import SVG")
+                        .clicked()
+                        .then(|| file_action = Some(FileAction::ImportSvg));
+                });
+                if let Some(message) = status.clone() {
+                    ui.horizontal(|ui| {
+                        ui.colored_label(egui::Color32::RED, message);
+                        ui.button("Dismiss").clicked().then(|| *status = None);
+                    });
+                }
             });
         });
+
+    let mut board_action = None;
+    let mut rename_board = false;
+    egui::Window::new("Boards")
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0., 10.))
+        .resizable(false)
+        .show(&ctx, |ui| {
+            ui.horizontal(|ui| {
+                for (index, name) in board_names.iter().enumerate() {
+                    ui.selectable_label(active_board == index, name)
+                        .clicked()
+                        .then(|| board_action = Some(BoardAction::Select(index)));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut *board_name).desired_width(100.))
+                    .changed()
+                    .then(|| rename_board = true);
+                ui.button("New")
+                    .clicked()
+                    .then(|| board_action = Some(BoardAction::New));
+                ui.button("Duplicate")
+                    .clicked()
+                    .then(|| board_action = Some(BoardAction::Duplicate));
+                ui.add_enabled(board_names.len() > 1, egui::Button::new("Close"))
+                    .clicked()
+                    .then(|| board_action = Some(BoardAction::Close(active_board)));
+            });
+        });
+
+    let mut layer_action = None;
+    let mut layer_edit = None;
+    let mut editing_layer = false;
+    egui::Window::new("Layers")
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-10., 10.))
+        .resizable(false)
+        .show(&ctx, |ui| {
+            ui.button("Add Layer")
+                .clicked()
+                .then(|| layer_action = Some(LayerAction::Add));
+            ui.separator();
+            // the top of the stack is listed first
+            for (index, layer) in layers.iter_mut().enumerate().rev() {
+                let props = layer.get_props();
+                ui.horizontal(|ui| {
+                    ui.selectable_label(*active_layer == index, format!("{}", index + 1))
+                        .clicked()
+                        .then(|| *active_layer = index);
+                    let name =
+                        ui.add(egui::TextEdit::singleline(&mut layer.name).desired_width(100.));
+                    editing_layer |= name.has_focus();
+                    ui.checkbox(&mut layer.visible, "Visible");
+                    ui.checkbox(&mut layer.locked, "Lock");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Opacity");
+                    let opacity = ui.add(egui::Slider::new(&mut layer.opacity, 0.0..=1.0));
+                    editing_layer |= opacity.dragged();
+                    ui.button("Up")
+                        .clicked()
+                        .then(|| layer_action = Some(LayerAction::Raise(index)));
+                    ui.button("Down")
+                        .clicked()
+                        .then(|| layer_action = Some(LayerAction::Lower(index)));
+                    ui.button("Merge Down")
+                        .clicked()
+                        .then(|| layer_action = Some(LayerAction::MergeDown(index)));
+                    ui.button("Delete")
+                        .clicked()
+                        .then(|| layer_action = Some(LayerAction::Delete(index)));
+                });
+                if layer.get_props() != props {
+                    layer_edit = Some((index, props));
+                }
+                ui.separator();
+            }
+        });
+
+    if !jobs.get_statuses().is_empty() {
+        egui::Window::new("Jobs")
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10., -10.))
+            .resizable(false)
+            .show(&ctx, |ui| {
+                for status in jobs.get_statuses().iter() {
+                    ui.horizontal(|ui| {
+                        ui.label(&status.label);
+                        match &status.state {
+                            JobState::Queued => {
+                                ui.label("queued");
+                            }
+                            JobState::Running => {
+                                ui.add(
+                                    egui::ProgressBar::new(status.progress)
+                                        .desired_width(120.)
+                                        .show_percentage(),
+                                );
+                            }
+                            JobState::Done => {
+                                ui.label("done");
+                            }
+                            JobState::Failed(err) => {
+                                ui.colored_label(egui::Color32::RED, format!("failed: {}", err));
+                            }
+                            JobState::Cancelled => {
+                                ui.label("cancelled");
+                            }
+                        }
+                        if status.is_active() {
+                            ui.button("Cancel")
+                                .clicked()
+                                .then(|| cancel_job = Some(status.id));
+                        }
+                    });
+                }
+                ui.separator();
+                ui.button("Clear Finished")
+                    .clicked()
+                    .then(|| clear_jobs = true);
+            });
+    }
+
+    // the frame has to end before the model can be borrowed as a whole
+    drop(ctx);
+    model.edit_layer(layer_edit, editing_layer);
+    if undo {
+        model.undo();
+    }
+    if redo {
+        model.redo();
+    }
+    if let Some(action) = layer_action {
+        model.apply_layer_action(action);
+    }
+    let result = match file_action {
+        Some(FileAction::Save) => model.save().map_err(|err| ("save error", err)),
+        Some(FileAction::Open) => model.open(app).map_err(|err| ("open error", err)),
+        Some(FileAction::Export) => model.export_png().map_err(|err| ("png export error", err)),
+        Some(FileAction::ExportSvg) => model.export_svg().map_err(|err| ("svg export error", err)),
+        Some(FileAction::ImportSvg) => model.import_svg().map_err(|err| ("svg import error", err)),
+        None => Ok(()),
+    };
+    if let Err((action, err)) = result {
+        model.status = Some(format!("{}: {}", action, err));
+    }
+    if recolor {
+        model.recolor_selection();
+    }
+    if synth {
+        model.synth();
+    }
+    if synth_region {
+        model.synth_region();
+    }
+    if sketch {
+        model.sketch();
+    }
+    if outpaint {
+        if let Err(err) = model.outpaint() {
+            model.status = Some(format!("outpaint error: {}", err));
+        }
+    }
+    if inpaint {
+        model.inpaint();
+    }
+    if let Some(id) = cancel_job {
+        model.jobs.cancel(id);
+    }
+    if clear_jobs {
+        model.jobs.clear_finished();
+    }
+    if reload_brushes {
+        model.load_brushes();
+    }
+    if rename_board {
+        model.rename_board();
+    }
+    // last, the other actions of this frame were made on the old board
+    if let Some(action) = board_action {
+        model.apply_board_action(app, action);
+    }
 }
 
 fn raw_window_event(_app: &App, model: &mut Model, event: &nannou::winit::event::WindowEvent) {
     model.egui.handle_raw_event(event);
+    // tablets report pen pressure as touches with a force
+    use nannou::winit::event::{TouchPhase, WindowEvent};
+    if let WindowEvent::Touch(touch) = event {
+        model.pressure = match touch.phase {
+            TouchPhase::Ended | TouchPhase::Cancelled => None,
+            _ => touch.force.map(|force| force.normalized() as f32),
+        };
+    }
 }
 
 fn view(app: &App, model: &Model, frame: Frame) {
     let draw = app.draw();
     frame.clear(BLACK);
     // text_2_image("mountain and flowers");
-
-    // 1. when we call sd we give it the w/h in the dimensions of the rectangle
-    // 2. when the image is synthed draw the image in the texture with width and height of the same dimensions
-    // 3. delete rectangle
-
-    draw.texture(&model.texture)
-        .height(400.)
-        .width(400.)
-        .xy(pt2(0., 0.));
-
-    for elem in model.elements.iter() {
-        match elem {
-            Elements::L(line) => line.draw_elem(&draw),
-            Elements::F(form) => form.draw_elem(&draw),
-        }
-    }
-    model.display(&draw, &app);
+    let world = model.camera.apply(&draw);
+    model.draw_canvas(&world, app);
+    model.textures.end_frame();
+    model.display(&world, app);
     draw.to_frame(app, &frame).unwrap();
     model.egui.draw_to_frame(&frame).unwrap();
 }
 
-fn edit_hsv(ui: &mut egui::Ui, color: &mut Hsv) {
-    let mut egui_hsv = egui::color::Hsva::new(
-        color.hue.to_positive_radians() as f32 / (std::f32::consts::PI * 2.0),
+fn edit_hsva(ui: &mut egui::Ui, color: &mut Hsva) {
+    let mut egui_hsva = egui::color::Hsva::new(
+        color.hue.to_positive_radians() / (std::f32::consts::PI * 2.0),
         color.saturation,
         color.value,
-        1.0,
+        color.alpha,
     );
 
     if egui::color_picker::color_edit_button_hsva(
         ui,
-        &mut egui_hsv,
-        egui::color_picker::Alpha::Opaque,
+        &mut egui_hsva,
+        egui::color_picker::Alpha::OnlyBlend,
     )
     .changed()
     {
-        *color = color::hsv(egui_hsv.h, egui_hsv.s, egui_hsv.v);
+        *color = color::hsva(egui_hsva.h, egui_hsva.s, egui_hsva.v, egui_hsva.a);
     }
 }
 
 fn event(app: &App, model: &mut Model, event: WindowEvent) {
+    let screen = pt2(app.mouse.x, app.mouse.y);
+    let mouse = model.get_mouse(app);
     match event {
+        DroppedFile(path) => {
+            if let Err(err) = model.add_image_file(&path, mouse) {
+                model.status = Some(format!("image file error: {}", err));
+            }
+        }
+        KeyPressed(Key::Z) => {
+            if model.egui.ctx().wants_keyboard_input() {
+                return;
+            }
+            let mods = &app.keys.mods;
+            if mods.ctrl() || mods.logo() {
+                if mods.shift() {
+                    model.redo();
+                } else {
+                    model.undo();
+                }
+            }
+        }
+        // arrow keys nudge the selection by a screen pixel
+        KeyPressed(key @ (Key::Left | Key::Right | Key::Up | Key::Down)) => {
+            if model.tool != Tool::Select || model.egui.ctx().wants_keyboard_input() {
+                return;
+            }
+            let step = match key {
+                Key::Left => vec2(-1., 0.),
+                Key::Right => vec2(1., 0.),
+                Key::Up => vec2(0., 1.),
+                _ => vec2(0., -1.),
+            };
+            model.move_selection(step / model.camera.zoom);
+        }
+        KeyPressed(Key::Space) if !model.egui.ctx().wants_keyboard_input() => {
+            model.camera.space_held = true
+        }
+        KeyReleased(Key::Space) => model.camera.space_held = false,
+        MouseWheel(delta, _) => {
+            if model.egui.ctx().is_pointer_over_area() {
+                return;
+            }
+            let lines = match delta {
+                MouseScrollDelta::LineDelta(_, y) => y,
+                MouseScrollDelta::PixelDelta(p) => p.y as f32 / PIXELS_PER_LINE,
+            };
+            model.camera.zoom_at(screen, lines);
+        }
         MousePressed(pos) => match pos {
+            MouseButton::Left if model.camera.space_held => model.camera.start_pan(screen),
+            MouseButton::Middle => model.camera.start_pan(screen),
             MouseButton::Left => {
                 model.set_drawing(true);
+                model.drag_start = mouse;
                 match model.get_tool() {
                     Tool::Pencil => {
                         model.line.set_color(model.get_settings().get_color());
-                        model.line.set_weight(model.get_settings().get_weight());
+                        model.line.set_blend(model.get_settings().blend);
+                        model.line.set_weight(model.get_brush_weight());
+                        let brush = model.get_settings().brush.clone();
+                        model.line.set_brush(brush);
                     }
+                    Tool::Mask => model.line.set_weight(model.get_brush_weight()),
+                    Tool::Select => model.start_select(mouse),
+                    Tool::Fill => model.fill(app, mouse),
                     Tool::Rect => {
-                        model.rect.set_center(pt2(app.mouse.x, app.mouse.y));
-                        model.rect.set_color(model.get_settings().get_color())
+                        model.rect = Rectangle::new(mouse, Vec2::ZERO, model.get_shape_style())
                     }
-                    Tool::Rect_Custom => {
-                        model.rect_custom.set_center(pt2(app.mouse.x, app.mouse.y));
+                    Tool::RectCustom => {
+                        model.rect_custom.set_center(mouse);
                         model
                             .rect_custom
                             .set_color(model.get_settings().get_color());
+                        model.rect_custom.set_blend(model.get_settings().blend);
                     }
                     Tool::Ellipse => {
-                        model.ellipse.set_center(pt2(app.mouse.x, app.mouse.y));
-                        model.ellipse.set_color(model.get_settings().get_color())
+                        model.ellipse = Ellipse::new(mouse, Vec2::ZERO, model.get_shape_style())
+                    }
+                    Tool::Segment => {
+                        model.segment = Segment::new(mouse, mouse, model.get_shape_style())
+                    }
+                    Tool::Polygon => {
+                        let sides = model.get_settings().sides;
+                        model.polygon = Polygon::new(mouse, 0., sides, model.get_shape_style())
+                    }
+                    Tool::Star => {
+                        let (points, ratio) = (
+                            model.get_settings().star_points,
+                            model.get_settings().star_ratio,
+                        );
+                        model.star = Star::new(mouse, 0., points, ratio, model.get_shape_style())
+                    }
+                    Tool::Arrow => {
+                        let head = model.get_settings().arrow_head;
+                        model.arrow = Arrow::new(mouse, mouse, head, model.get_shape_style())
                     }
                     _ => {}
                 }
@@ -214,29 +803,32 @@ fn event(app: &App, model: &mut Model, event: WindowEvent) {
             _ => {}
         },
         MouseReleased(pos) => match pos {
+            MouseButton::Left | MouseButton::Middle if model.camera.is_panning() => {
+                model.camera.end_pan()
+            }
             MouseButton::Left => {
                 model.set_drawing(false);
                 match model.get_tool() {
                     Tool::Pencil => {
+                        model.smooth_line();
                         model.set_line();
                         model.get_mut_line().clear_line();
                         app.set_loop_mode(LoopMode::RefreshSync)
                     }
-                    Tool::Ellipse => model
-                        .elements
-                        .push(Elements::F(Box::new(model.ellipse.clone()))),
-                    Tool::Rect => model
-                        .elements
-                        .push(Elements::F(Box::new(model.rect.clone()))),
-                    Tool::Rect_Custom => {
-                        model
-                            .rect_custom
-                            .rect_to_pixels(pt2(app.mouse.x, app.mouse.y));
+                    Tool::Ellipse => model.add_element(Box::new(model.ellipse.clone())),
+                    Tool::Rect => model.add_element(Box::new(model.rect.clone())),
+                    Tool::Segment => model.add_element(Box::new(model.segment.clone())),
+                    Tool::Polygon => model.add_element(Box::new(model.polygon.clone())),
+                    Tool::Star => model.add_element(Box::new(model.star.clone())),
+                    Tool::Arrow => model.add_element(Box::new(model.arrow.clone())),
+                    Tool::RectCustom => {
                         model.set_rect_line();
-                        model.get_mut_rect_line().clear_line();
                         app.set_loop_mode(LoopMode::RefreshSync)
                     }
-                    Tool::Rubber => {}
+                    Tool::Rubber => model.end_erase(),
+                    Tool::Mask => model.add_mask_stroke(),
+                    Tool::Select => model.end_select(mouse),
+                    Tool::Fill => {}
                 }
             }
             _ => {}
@@ -0,0 +1,97 @@
+use nannou::{prelude::*, wgpu};
+use serde::{Deserialize, Serialize};
+
+// how an element mixes with what is drawn under it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Blend {
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Add,
+    // takes the alpha of the element away from what is under it
+    Erase,
+}
+
+impl Blend {
+    pub const ALL: [Blend; 6] = [
+        Blend::Normal,
+        Blend::Multiply,
+        Blend::Screen,
+        Blend::Overlay,
+        Blend::Add,
+        Blend::Erase,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Blend::Normal => "Normal",
+            Blend::Multiply => "Multiply",
+            Blend::Screen => "Screen",
+            Blend::Overlay => "Overlay",
+            Blend::Add => "Add",
+            Blend::Erase => "Erase",
+        }
+    }
+
+    pub fn is_normal(&self) -> bool {
+        *self == Blend::Normal
+    }
+
+    // `draw` with this mode for everything drawn through it, the window
+    // blends with fixed factors so translucent multiply and screen are close
+    // rather than exact, overlay needs the color under it to pick its half
+    // so only its dark half, 2 * src * dst, is drawn here and the view
+    // shows canvases with overlay elements as exports render them
+    pub fn draw(&self, draw: &Draw) -> Draw {
+        use wgpu::{BlendFactor as F, BlendOperation};
+        let (src_factor, dst_factor) = match self {
+            Blend::Normal => return draw.clone(),
+            Blend::Multiply => (F::Dst, F::OneMinusSrcAlpha),
+            Blend::Screen => (F::SrcAlpha, F::OneMinusSrc),
+            Blend::Overlay => (F::Dst, F::Src),
+            Blend::Add => (F::SrcAlpha, F::One),
+            // dst-out, the alpha goes as well as the color
+            Blend::Erase => (F::Zero, F::OneMinusSrcAlpha),
+        };
+        let component = wgpu::BlendComponent {
+            src_factor,
+            dst_factor,
+            operation: BlendOperation::Add,
+        };
+        match self {
+            Blend::Erase => draw.color_blend(component).alpha_blend(component),
+            _ => draw.color_blend(component),
+        }
+    }
+
+    // `src` over `dst`, both straight alpha rgba in 0..1, with the separable
+    // modes of the w3c compositing spec
+    pub fn mix(&self, dst: [f32; 4], src: [f32; 4]) -> [f32; 4] {
+        let (sa, da) = (src[3], dst[3]);
+        if *self == Blend::Erase {
+            return [dst[0], dst[1], dst[2], da * (1. - sa)];
+        }
+        let out_a = sa + da * (1. - sa);
+        if out_a <= 0. {
+            return [0.; 4];
+        }
+        let mut out = [0., 0., 0., out_a];
+        for i in 0..3 {
+            let (cs, cb) = (src[i], dst[i]);
+            let mixed = match self {
+                Blend::Multiply => cs * cb,
+                Blend::Screen => cs + cb - cs * cb,
+                Blend::Overlay if cb <= 0.5 => 2. * cs * cb,
+                Blend::Overlay => 1. - 2. * (1. - cs) * (1. - cb),
+                Blend::Add => (cs + cb).min(1.),
+                Blend::Normal | Blend::Erase => cs,
+            };
+            // where there is nothing under it the element keeps its color
+            let cs = (1. - da) * cs + da * mixed;
+            out[i] = (cs * sa + cb * da * (1. - sa)) / out_a;
+        }
+        out
+    }
+}
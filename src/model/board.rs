@@ -0,0 +1,57 @@
+use super::{camera::Camera, elements::Line, history::History, layers::Layer};
+use nannou::{image::DynamicImage, wgpu::Texture};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_BOARD_ID: AtomicU64 = AtomicU64::new(0);
+
+pub fn next_board_id() -> u64 {
+    NEXT_BOARD_ID.fetch_add(1, Ordering::SeqCst)
+}
+
+// a file named after the board that none of `taken` saves to
+pub fn board_path(name: &str, taken: &[&str]) -> String {
+    let stem = name.trim().to_lowercase().replace(' ', "_");
+    std::iter::once(format!("{}.json", stem))
+        .chain((2..).map(|n| format!("{}_{}.json", stem, n)))
+        .find(|path| !taken.contains(&path.as_str()))
+        .unwrap()
+}
+
+// a canvas waiting in its tab while another board is the active one, the
+// active board lives in the fields of the model
+pub struct Board {
+    pub id: u64,
+    pub name: String,
+    pub layers: Vec<Layer>,
+    pub active_layer: usize,
+    pub texture: Texture,
+    pub background: DynamicImage,
+    pub prompt: String,
+    pub history: History,
+    pub project_path: String,
+    pub camera: Camera,
+    pub mask: Vec<Line>,
+}
+
+// tab indices count every board, the active one included
+pub enum BoardAction {
+    New,
+    Duplicate,
+    Select(usize),
+    Close(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn board_paths_follow_the_name_and_skip_taken_files() {
+        assert_eq!(board_path("Board 2", &[]), "board_2.json");
+        assert_eq!(board_path(" My Board ", &["canvas.json"]), "my_board.json");
+        assert_eq!(
+            board_path("Board 2", &["board_2.json", "board_2_2.json"]),
+            "board_2_3.json"
+        );
+    }
+}
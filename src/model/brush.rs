@@ -0,0 +1,300 @@
+use super::picture::tint;
+use nannou::{
+    image::{self, GrayImage, Luma, RgbaImage},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs, io,
+    path::Path,
+    sync::{Arc, OnceLock},
+};
+
+// user brushes are the json files in this folder
+pub const BRUSH_DIR: &str = "brushes";
+// size of the grain stamp texture brushes use when they have no image
+const GRAIN_SIZE: u32 = 64;
+// closest two dabs get, as a share of the weight
+const MIN_SPACING: f32 = 0.02;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum BrushShape {
+    Round,
+    // an ellipse squashed by the roundness and turned by the angle
+    Flat,
+    // the stamp image
+    Texture,
+}
+
+// a brush preset, strokes keep a copy of the one they were drawn with
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Brush {
+    pub name: String,
+    pub shape: BrushShape,
+    // distance between dabs as a share of the weight
+    pub spacing: f32,
+    // 1 gives a sharp edge, 0 fades out from the center
+    pub hardness: f32,
+    // degrees, turns flat and texture tips
+    pub angle: f32,
+    // how far dabs wander off the stroke, as a share of the weight
+    pub scatter: f32,
+    pub opacity: f32,
+    // height of flat tips as a share of their width
+    pub roundness: f32,
+    // grayscale image for texture tips, white is paint
+    pub texture: Option<String>,
+    #[serde(skip)]
+    stamp: StampCache,
+}
+
+// the stamp of a brush once it was loaded, shared by the strokes it drew,
+// brushes compare equal whether it is loaded or not
+#[derive(Clone, Default)]
+struct StampCache(OnceLock<Arc<GrayImage>>);
+
+impl PartialEq for StampCache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl fmt::Debug for StampCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("StampCache")
+    }
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self {
+            name: String::from("Hard Round"),
+            shape: BrushShape::Round,
+            spacing: 0.1,
+            hardness: 1.,
+            angle: 0.,
+            scatter: 0.,
+            opacity: 1.,
+            roundness: 1.,
+            texture: None,
+            stamp: StampCache::default(),
+        }
+    }
+}
+
+// one print of the tip along a stroke
+pub struct Dab {
+    pub center: Point2,
+    pub size: f32,
+    pub angle: f32,
+    pub color: Hsva,
+}
+
+impl Brush {
+    pub fn presets() -> Vec<Brush> {
+        vec![
+            Brush::default(),
+            Brush {
+                name: String::from("Soft Airbrush"),
+                spacing: 0.15,
+                hardness: 0.,
+                opacity: 0.15,
+                ..Brush::default()
+            },
+            Brush {
+                name: String::from("Calligraphy"),
+                shape: BrushShape::Flat,
+                spacing: 0.05,
+                angle: 45.,
+                roundness: 0.2,
+                ..Brush::default()
+            },
+            Brush {
+                name: String::from("Charcoal"),
+                shape: BrushShape::Texture,
+                spacing: 0.25,
+                scatter: 0.1,
+                opacity: 0.6,
+                ..Brush::default()
+            },
+        ]
+    }
+
+    // solid brushes draw as one continuous stroke instead of dabs
+    pub fn is_solid(&self) -> bool {
+        self.shape == BrushShape::Round
+            && self.hardness >= 1.
+            && self.scatter <= 0.
+            && self.opacity >= 1.
+    }
+
+    // the image of texture tips, grain when it has none or it does not load,
+    // read from disk the first time only
+    pub fn load_stamp(&self) -> Arc<GrayImage> {
+        self.stamp
+            .0
+            .get_or_init(|| {
+                let stamp = self
+                    .texture
+                    .as_ref()
+                    .and_then(|path| image::open(path).ok())
+                    .map(|img| img.to_luma8())
+                    .unwrap_or_else(grain);
+                Arc::new(stamp)
+            })
+            .clone()
+    }
+
+    // the stamp in one color, its gray level as the alpha
+    pub fn tint(&self, color: Hsva) -> RgbaImage {
+        tint(&self.load_stamp(), color)
+    }
+
+    // where the tip lands along a stroke, the points carry their color and
+    // width as a share of the weight
+    pub fn dabs(&self, points: &[(Point2, (Hsva, f32))], weight: f32) -> Vec<Dab> {
+        let (first, (color, width)) = match points.first() {
+            Some(point) => *point,
+            None => return Vec::new(),
+        };
+        let mut dabs = Vec::new();
+        let mut dab = |p: Point2, color: Hsva, width: f32| {
+            let size = weight * width;
+            let i = dabs.len() as u32;
+            let offset = vec2(noise(i * 3), noise(i * 3 + 1)) * self.scatter * size;
+            // texture tips turn at random so the grain does not repeat
+            let turn = match self.shape {
+                BrushShape::Texture => noise(i * 3 + 2) * PI,
+                _ => 0.,
+            };
+            dabs.push(Dab {
+                center: p + offset,
+                size,
+                angle: self.angle.to_radians() + turn,
+                color,
+            });
+            (self.spacing.max(MIN_SPACING) * size).max(f32::EPSILON)
+        };
+        let mut next = dab(first, color, width);
+        // distance along the stroke to the start of the current segment
+        let mut travelled = 0.;
+        for w in points.windows(2) {
+            let ((a, (ca, wa)), (b, (_, wb))) = (w[0], w[1]);
+            let length = a.distance(b);
+            while next <= travelled + length {
+                let t = (next - travelled) / length;
+                next += dab(a.lerp(b, t), ca, wa + (wb - wa) * t);
+            }
+            travelled += length;
+        }
+        dabs
+    }
+}
+
+// built-in presets followed by the brushes found in `dir`, texture paths in
+// the files are relative to it. files that do not load are skipped and come
+// back as messages, a missing folder just has no brushes
+pub fn load_brushes(dir: &Path) -> (Vec<Brush>, Vec<String>) {
+    let mut brushes = Brush::presets();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return (brushes, Vec::new()),
+        Err(err) => return (brushes, vec![format!("{}: {}", dir.display(), err)]),
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    let mut errors = Vec::new();
+    for path in paths {
+        let brush = fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|json| serde_json::from_str::<Brush>(&json).map_err(|err| err.to_string()));
+        match brush {
+            Ok(mut brush) => {
+                brush.texture = brush
+                    .texture
+                    .map(|texture| dir.join(texture).to_string_lossy().into_owned());
+                brushes.push(brush);
+            }
+            Err(err) => errors.push(format!("{}: {}", path.display(), err)),
+        }
+    }
+    (brushes, errors)
+}
+
+// a round speckled tip, the same every time
+fn grain() -> GrayImage {
+    let half = GRAIN_SIZE as f32 / 2.;
+    GrayImage::from_fn(GRAIN_SIZE, GRAIN_SIZE, |x, y| {
+        let d = vec2(x as f32 + 0.5 - half, y as f32 + 0.5 - half).length() / half;
+        let speck = (noise(y * GRAIN_SIZE + x) + 1.) / 2.;
+        let level = if d < 1. {
+            speck * speck * (1. - d * d)
+        } else {
+            0.
+        };
+        Luma([(level * 255.).round() as u8])
+    })
+}
+
+// hash of `i` as a number from -1 to 1, so strokes look the same every frame
+fn noise(i: u32) -> f32 {
+    let mut x = i.wrapping_mul(0x9e37_79b9);
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x85eb_ca6b);
+    x ^= x >> 13;
+    x = x.wrapping_mul(0xc2b2_ae35);
+    x ^= x >> 16;
+    x as f32 / u32::MAX as f32 * 2. - 1.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_brush_files_are_skipped_and_reported() {
+        let dir = std::env::temp_dir().join(format!("brushes_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("a.json"),
+            r#"{"name": "Mine", "texture": "tip.png"}"#,
+        )
+        .unwrap();
+        fs::write(dir.join("b.json"), r#"{"name": 3}"#).unwrap();
+        fs::write(dir.join("notes.txt"), "not a brush").unwrap();
+        let (brushes, errors) = load_brushes(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(brushes.len(), Brush::presets().len() + 1);
+        let mine = brushes.last().unwrap();
+        assert_eq!(mine.name, "Mine");
+        assert_eq!(mine.spacing, Brush::default().spacing);
+        let texture = dir.join("tip.png").to_string_lossy().into_owned();
+        assert_eq!(mine.texture, Some(texture));
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("b.json"));
+    }
+
+    #[test]
+    fn a_missing_brush_folder_has_no_brushes() {
+        let (brushes, errors) = load_brushes(Path::new("no/such/folder"));
+        assert_eq!(brushes, Brush::presets());
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn stamps_load_once_and_stay_out_of_files() {
+        let brush = Brush::presets()[3].clone();
+        let stamp = brush.load_stamp();
+        assert!(Arc::ptr_eq(&stamp, &brush.load_stamp()));
+        assert!(Arc::ptr_eq(&stamp, &brush.clone().load_stamp()));
+        assert_eq!(brush, Brush::presets()[3]);
+        let json = serde_json::to_string(&brush).unwrap();
+        assert!(!json.contains("stamp"));
+        assert_eq!(serde_json::from_str::<Brush>(&json).unwrap(), brush);
+    }
+}
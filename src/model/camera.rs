@@ -0,0 +1,66 @@
+use nannou::prelude::*;
+
+const MIN_ZOOM: f32 = 0.05;
+const MAX_ZOOM: f32 = 20.;
+// zoom change for one line of the scroll wheel
+const ZOOM_STEP: f32 = 1.1;
+
+// maps the world the elements live in onto the window:
+// screen = world * zoom + offset
+#[derive(Clone)]
+pub struct Camera {
+    pub offset: Vec2,
+    pub zoom: f32,
+    // space turns left drags into pans
+    pub space_held: bool,
+    // screen position of the last pan step while panning
+    grab: Option<Vec2>,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            offset: Vec2::ZERO,
+            zoom: 1.,
+            space_held: false,
+            grab: None,
+        }
+    }
+}
+
+impl Camera {
+    pub fn to_world(&self, screen: Vec2) -> Vec2 {
+        (screen - self.offset) / self.zoom
+    }
+
+    // draw whose coordinates are world coordinates
+    pub fn apply(&self, draw: &Draw) -> Draw {
+        draw.translate(self.offset.extend(0.)).scale(self.zoom)
+    }
+
+    // zooms by `lines` wheel steps, keeping the world point under `screen`
+    // where it is
+    pub fn zoom_at(&mut self, screen: Vec2, lines: f32) {
+        let world = self.to_world(screen);
+        self.zoom = (self.zoom * ZOOM_STEP.powf(lines)).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.offset = screen - world * self.zoom;
+    }
+
+    pub fn start_pan(&mut self, screen: Vec2) {
+        self.grab = Some(screen);
+    }
+
+    pub fn pan_to(&mut self, screen: Vec2) {
+        if let Some(grab) = self.grab.replace(screen) {
+            self.offset += screen - grab;
+        }
+    }
+
+    pub fn end_pan(&mut self) {
+        self.grab = None;
+    }
+
+    pub fn is_panning(&self) -> bool {
+        self.grab.is_some()
+    }
+}
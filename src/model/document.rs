@@ -0,0 +1,996 @@
+use super::{
+    blend::Blend,
+    brush::Brush,
+    elements::{Elements, Ellipse, Line, Rectangle, RectangleCustom, Tool},
+    fill::Fill,
+    layers::Layer,
+    picture::Picture,
+    shapes::{Arrow, ArrowHead, Polygon, Segment, Star},
+    stroke::Smoothing,
+    style::{StrokeCap, StrokeJoin},
+    Settings,
+};
+use crate::diffusion::DiffusionConfig;
+use nannou::{image, prelude::*};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{error::Error, fs, path::Path};
+
+pub const VERSION: u32 = 3;
+// the version of the first files written
+const FIRST_VERSION: u32 = 1;
+
+// MIGRATIONS[n] turns a version FIRST_VERSION + n file into the next version
+const MIGRATIONS: &[fn(Value) -> Value] = &[migrate_v1_shape_styles, migrate_v2_ellipse_radii];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ColorDoc {
+    pub hue: f32,
+    pub saturation: f32,
+    pub value: f32,
+    // older files have opaque colors only
+    #[serde(default = "opaque")]
+    pub alpha: f32,
+}
+
+fn opaque() -> f32 {
+    1.
+}
+
+impl From<Hsva> for ColorDoc {
+    fn from(color: Hsva) -> Self {
+        Self {
+            hue: color.hue.to_positive_degrees(),
+            saturation: color.saturation,
+            value: color.value,
+            alpha: color.alpha,
+        }
+    }
+}
+
+impl From<ColorDoc> for Hsva {
+    fn from(color: ColorDoc) -> Self {
+        hsva(color.hue / 360., color.saturation, color.value, color.alpha)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PixelDoc {
+    pub point: [f32; 2],
+    pub color: ColorDoc,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StyleDoc {
+    pub fill: Option<ColorDoc>,
+    pub stroke: ColorDoc,
+    pub width: f32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dash: Vec<f32>,
+    pub join: StrokeJoin,
+    pub cap: StrokeCap,
+    #[serde(default, skip_serializing_if = "Blend::is_normal")]
+    pub blend: Blend,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind")]
+pub enum ElementDoc {
+    Line {
+        pixels: Vec<PixelDoc>,
+        weight: f32,
+        // share of the weight at each point, empty for strokes of one width
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        widths: Vec<f32>,
+        color: ColorDoc,
+        // none for strokes of solid brushes
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        brush: Option<Brush>,
+        #[serde(default, skip_serializing_if = "Blend::is_normal")]
+        blend: Blend,
+    },
+    Rectangle {
+        center: [f32; 2],
+        wh: [f32; 2],
+        // radians, older files have none
+        #[serde(default)]
+        rotation: f32,
+        style: StyleDoc,
+    },
+    Ellipse {
+        center: [f32; 2],
+        radii: [f32; 2],
+        rotation: f32,
+        style: StyleDoc,
+    },
+    RectangleCustom {
+        center: [f32; 2],
+        wh: [f32; 2],
+        weight: f32,
+        color: ColorDoc,
+        #[serde(default, skip_serializing_if = "Blend::is_normal")]
+        blend: Blend,
+    },
+    Image {
+        // base64 encoded png
+        png: String,
+        center: [f32; 2],
+        wh: [f32; 2],
+        rotation: f32,
+        opacity: f32,
+        #[serde(default, skip_serializing_if = "Blend::is_normal")]
+        blend: Blend,
+    },
+    Fill {
+        // base64 encoded grayscale png of the coverage
+        mask: String,
+        center: [f32; 2],
+        wh: [f32; 2],
+        rotation: f32,
+        color: ColorDoc,
+        #[serde(default, skip_serializing_if = "Blend::is_normal")]
+        blend: Blend,
+    },
+    Segment {
+        start: [f32; 2],
+        end: [f32; 2],
+        style: StyleDoc,
+    },
+    Polygon {
+        center: [f32; 2],
+        radius: f32,
+        sides: u32,
+        rotation: f32,
+        style: StyleDoc,
+    },
+    Star {
+        center: [f32; 2],
+        radius: f32,
+        points: u32,
+        ratio: f32,
+        rotation: f32,
+        style: StyleDoc,
+    },
+    Arrow {
+        start: [f32; 2],
+        end: [f32; 2],
+        head: ArrowHead,
+        style: StyleDoc,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LayerDoc {
+    pub name: String,
+    pub visible: bool,
+    pub opacity: f32,
+    pub locked: bool,
+    pub elements: Vec<ElementDoc>,
+}
+
+// the tool settings, files from before a field was kept open with its
+// default, whether the Shapes window is open is left out
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SettingsDoc {
+    pub color: ColorDoc,
+    pub weight: f32,
+    #[serde(skip_serializing_if = "Blend::is_normal")]
+    pub blend: Blend,
+    pub tool: Tool,
+    pub smoothing: Smoothing,
+    pub tolerance: f32,
+    pub passes: u32,
+    pub variable_width: bool,
+    pub brush: Brush,
+    pub fill_tolerance: f32,
+    pub fill_antialias: bool,
+    pub sides: u32,
+    pub star_points: u32,
+    pub star_ratio: f32,
+    pub arrow_head: ArrowHead,
+    pub style: StyleDoc,
+    pub diffusion: DiffusionConfig,
+}
+
+impl SettingsDoc {
+    pub fn new(settings: &Settings, tool: &Tool, diffusion: &DiffusionConfig) -> Self {
+        Self {
+            color: settings.get_color().into(),
+            weight: settings.get_weight(),
+            blend: settings.blend,
+            tool: tool.clone(),
+            smoothing: settings.smoothing,
+            tolerance: settings.tolerance,
+            passes: settings.passes,
+            variable_width: settings.variable_width,
+            brush: settings.brush.clone(),
+            fill_tolerance: settings.fill_tolerance,
+            fill_antialias: settings.fill_antialias,
+            sides: settings.sides,
+            star_points: settings.star_points,
+            star_ratio: settings.star_ratio,
+            arrow_head: settings.arrow_head,
+            style: (&settings.style).into(),
+            diffusion: diffusion.clone(),
+        }
+    }
+
+    // `shapes` is whether the Shapes window is open
+    pub fn to_settings(&self, shapes: bool) -> Settings {
+        Settings {
+            color: self.color.into(),
+            weight: self.weight,
+            shapes,
+            smoothing: self.smoothing,
+            tolerance: self.tolerance,
+            passes: self.passes,
+            variable_width: self.variable_width,
+            brush: self.brush.clone(),
+            fill_tolerance: self.fill_tolerance,
+            fill_antialias: self.fill_antialias,
+            sides: self.sides,
+            star_points: self.star_points,
+            star_ratio: self.star_ratio,
+            arrow_head: self.arrow_head,
+            style: (&self.style).into(),
+            blend: self.blend,
+        }
+    }
+}
+
+impl Default for SettingsDoc {
+    fn default() -> Self {
+        let settings = Settings::new(Hsva::default(), 1., false);
+        Self::new(&settings, &Tool::Pencil, &DiffusionConfig::default())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Document {
+    pub version: u32,
+    pub settings: SettingsDoc,
+    pub prompt: String,
+    // base64 encoded png
+    pub background: Option<String>,
+    pub layers: Vec<LayerDoc>,
+}
+
+pub fn pixels_to_doc(pixels: &[(Point2, Hsva)]) -> Vec<PixelDoc> {
+    pixels
+        .iter()
+        .map(|&(p, c)| PixelDoc {
+            point: [p.x, p.y],
+            color: c.into(),
+        })
+        .collect()
+}
+
+fn pixels_from_doc(pixels: &[PixelDoc]) -> Vec<(Point2, Hsva)> {
+    pixels
+        .iter()
+        .map(|p| (pt2(p.point[0], p.point[1]), p.color.into()))
+        .collect()
+}
+
+impl ElementDoc {
+    pub fn to_element(&self) -> Result<Elements, Box<dyn Error>> {
+        let mut elem: Elements = match self {
+            ElementDoc::Line {
+                pixels,
+                weight,
+                widths,
+                color,
+                brush,
+                ..
+            } => {
+                let mut line = Line::new_param(pixels_from_doc(pixels), *weight, (*color).into());
+                line.set_widths(widths.clone());
+                if let Some(brush) = brush {
+                    line.set_brush(brush.clone());
+                }
+                Box::new(line)
+            }
+            ElementDoc::Rectangle {
+                center,
+                wh,
+                rotation,
+                style,
+            } => {
+                let mut rect =
+                    Rectangle::new(pt2(center[0], center[1]), vec2(wh[0], wh[1]), style.into());
+                rect.set_rotation(*rotation);
+                Box::new(rect)
+            }
+            ElementDoc::Ellipse {
+                center,
+                radii,
+                rotation,
+                style,
+            } => {
+                let mut ellipse = Ellipse::new(
+                    pt2(center[0], center[1]),
+                    vec2(radii[0], radii[1]),
+                    style.into(),
+                );
+                ellipse.set_rotation(*rotation);
+                Box::new(ellipse)
+            }
+            ElementDoc::RectangleCustom {
+                center,
+                wh,
+                weight,
+                color,
+                ..
+            } => Box::new(RectangleCustom::new(
+                pt2(center[0], center[1]),
+                vec2(wh[0], wh[1]),
+                (*color).into(),
+                *weight,
+            )),
+            ElementDoc::Image {
+                png,
+                center,
+                wh,
+                rotation,
+                opacity,
+                ..
+            } => {
+                let image = decode_png(png)?;
+                let mut picture =
+                    Picture::new(image, pt2(center[0], center[1]), vec2(wh[0], wh[1]));
+                picture.set_rotation(*rotation);
+                picture.set_opacity(*opacity);
+                Box::new(picture)
+            }
+            ElementDoc::Fill {
+                mask,
+                center,
+                wh,
+                rotation,
+                color,
+                ..
+            } => {
+                let mask = decode_png(mask)?.to_luma8();
+                let mut fill = Fill::new(
+                    mask,
+                    (*color).into(),
+                    pt2(center[0], center[1]),
+                    vec2(wh[0], wh[1]),
+                );
+                fill.set_rotation(*rotation);
+                Box::new(fill)
+            }
+            ElementDoc::Segment { start, end, style } => Box::new(Segment::new(
+                pt2(start[0], start[1]),
+                pt2(end[0], end[1]),
+                style.into(),
+            )),
+            ElementDoc::Polygon {
+                center,
+                radius,
+                sides,
+                rotation,
+                style,
+            } => {
+                let mut polygon =
+                    Polygon::new(pt2(center[0], center[1]), *radius, *sides, style.into());
+                polygon.set_rotation(*rotation);
+                Box::new(polygon)
+            }
+            ElementDoc::Star {
+                center,
+                radius,
+                points,
+                ratio,
+                rotation,
+                style,
+            } => {
+                let mut star = Star::new(
+                    pt2(center[0], center[1]),
+                    *radius,
+                    *points,
+                    *ratio,
+                    style.into(),
+                );
+                star.set_rotation(*rotation);
+                Box::new(star)
+            }
+            ElementDoc::Arrow {
+                start,
+                end,
+                head,
+                style,
+            } => Box::new(Arrow::new(
+                pt2(start[0], start[1]),
+                pt2(end[0], end[1]),
+                *head,
+                style.into(),
+            )),
+        };
+        elem.set_blend(self.get_blend());
+        Ok(elem)
+    }
+
+    pub fn get_blend(&self) -> Blend {
+        match self {
+            ElementDoc::Line { blend, .. }
+            | ElementDoc::RectangleCustom { blend, .. }
+            | ElementDoc::Image { blend, .. }
+            | ElementDoc::Fill { blend, .. } => *blend,
+            ElementDoc::Rectangle { style, .. }
+            | ElementDoc::Ellipse { style, .. }
+            | ElementDoc::Segment { style, .. }
+            | ElementDoc::Polygon { style, .. }
+            | ElementDoc::Star { style, .. }
+            | ElementDoc::Arrow { style, .. } => style.blend,
+        }
+    }
+
+    pub fn set_blend(&mut self, to: Blend) {
+        match self {
+            ElementDoc::Line { blend, .. }
+            | ElementDoc::RectangleCustom { blend, .. }
+            | ElementDoc::Image { blend, .. }
+            | ElementDoc::Fill { blend, .. } => *blend = to,
+            ElementDoc::Rectangle { style, .. }
+            | ElementDoc::Ellipse { style, .. }
+            | ElementDoc::Segment { style, .. }
+            | ElementDoc::Polygon { style, .. }
+            | ElementDoc::Star { style, .. }
+            | ElementDoc::Arrow { style, .. } => style.blend = to,
+        }
+    }
+}
+
+impl LayerDoc {
+    pub fn from_layer(layer: &Layer) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            name: layer.name.clone(),
+            visible: layer.visible,
+            opacity: layer.opacity,
+            locked: layer.locked,
+            elements: layer
+                .elements
+                .iter()
+                .map(|elem| elem.to_doc())
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn to_layer(&self) -> Result<Layer, Box<dyn Error>> {
+        let mut layer = Layer::new(self.name.clone());
+        layer.visible = self.visible;
+        layer.opacity = self.opacity;
+        layer.locked = self.locked;
+        layer.elements = self
+            .elements
+            .iter()
+            .map(ElementDoc::to_element)
+            .collect::<Result<_, _>>()?;
+        Ok(layer)
+    }
+}
+
+impl Document {
+    pub fn new(
+        settings: SettingsDoc,
+        prompt: &str,
+        background: Option<&image::DynamicImage>,
+        layers: &[Layer],
+    ) -> Result<Self, Box<dyn Error>> {
+        let background = match background {
+            Some(img) => Some(encode_png(img)?),
+            None => None,
+        };
+        Ok(Self {
+            version: VERSION,
+            settings,
+            prompt: prompt.to_string(),
+            background,
+            layers: layers
+                .iter()
+                .map(LayerDoc::from_layer)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn Error>> {
+        let value = migrate(serde_json::from_str(json)?)?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn get_layers(&self) -> Result<Vec<Layer>, Box<dyn Error>> {
+        self.layers.iter().map(LayerDoc::to_layer).collect()
+    }
+
+    pub fn get_background(&self) -> Result<Option<image::DynamicImage>, Box<dyn Error>> {
+        match &self.background {
+            Some(data) => Ok(Some(decode_png(data)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+pub fn encode_png(img: &image::DynamicImage) -> Result<String, Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    img.write_to(&mut bytes, image::ImageOutputFormat::Png)?;
+    Ok(base64::encode(bytes))
+}
+
+pub fn decode_png(data: &str) -> Result<image::DynamicImage, Box<dyn Error>> {
+    Ok(image::load_from_memory(&base64::decode(data)?)?)
+}
+
+// brings a file of any known version up to VERSION
+fn migrate(mut value: Value) -> Result<Value, Box<dyn Error>> {
+    let mut version = value
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or("the file has no version")? as usize;
+    if version > VERSION as usize {
+        return Err(format!("file version {} is newer than {}", version, VERSION).into());
+    }
+    if version < FIRST_VERSION as usize {
+        return Err(format!("file version {} is unknown", version).into());
+    }
+    while version < VERSION as usize {
+        value = MIGRATIONS[version - FIRST_VERSION as usize](value);
+        version += 1;
+        value["version"] = Value::from(version);
+    }
+    Ok(value)
+}
+
+// version 1 shapes had a single color and weight, rectangles were outlines
+// of width 1 and ellipses were solid
+fn migrate_v1_shape_styles(mut value: Value) -> Value {
+    let elements = value
+        .get_mut("layers")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(|layer| layer.get_mut("elements").and_then(Value::as_array_mut))
+        .flatten();
+    for elem in elements {
+        let kind = elem.get("kind").and_then(Value::as_str).unwrap_or("");
+        let (filled, rounded) = match kind {
+            "Rectangle" => (false, false),
+            "Ellipse" => (true, false),
+            "Segment" | "Polygon" | "Star" | "Arrow" => (false, true),
+            _ => continue,
+        };
+        let doc = match elem.as_object_mut() {
+            Some(doc) => doc,
+            None => continue,
+        };
+        let color = doc.remove("color").unwrap_or(Value::Null);
+        let weight = doc.remove("weight").unwrap_or_else(|| Value::from(1.));
+        let (join, cap) = if rounded {
+            ("Round", "Round")
+        } else {
+            ("Miter", "Butt")
+        };
+        doc.insert(
+            String::from("style"),
+            serde_json::json!({
+                "fill": if filled { color.clone() } else { Value::Null },
+                "stroke": color,
+                "width": if filled { Value::from(0.) } else { weight },
+                "join": join,
+                "cap": cap,
+            }),
+        );
+    }
+    value
+}
+
+// version 2 ellipses were circles with one radius
+fn migrate_v2_ellipse_radii(mut value: Value) -> Value {
+    let elements = value
+        .get_mut("layers")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(|layer| layer.get_mut("elements").and_then(Value::as_array_mut))
+        .flatten()
+        .filter(|elem| elem.get("kind").and_then(Value::as_str) == Some("Ellipse"));
+    for elem in elements {
+        if let Some(doc) = elem.as_object_mut() {
+            let radius = doc.remove("radius").unwrap_or_else(|| Value::from(0.));
+            doc.insert(String::from("radii"), serde_json::json!([radius, radius]));
+            doc.insert(String::from("rotation"), Value::from(0.));
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, GrayImage, RgbaImage};
+
+    fn color(hue: f32) -> ColorDoc {
+        ColorDoc {
+            hue,
+            saturation: 0.5,
+            value: 1.,
+            alpha: 1.,
+        }
+    }
+
+    fn style() -> StyleDoc {
+        StyleDoc {
+            fill: Some(color(90.)),
+            stroke: color(180.),
+            width: 2.,
+            dash: vec![4., 2.],
+            join: StrokeJoin::Round,
+            cap: StrokeCap::Square,
+            blend: Blend::Normal,
+        }
+    }
+
+    fn pixels() -> Vec<PixelDoc> {
+        [[0., 0.], [10., 5.], [20., -5.]]
+            .iter()
+            .map(|&point| PixelDoc {
+                point,
+                color: color(180.),
+            })
+            .collect()
+    }
+
+    fn every_element() -> Vec<ElementDoc> {
+        let png = encode_png(&DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+            4,
+            2,
+            image::Rgba([10, 20, 30, 255]),
+        )))
+        .unwrap();
+        let mask = encode_png(&DynamicImage::ImageLuma8(GrayImage::from_fn(
+            3,
+            3,
+            |x, _| image::Luma([x as u8 * 100]),
+        )))
+        .unwrap();
+        vec![
+            ElementDoc::Line {
+                pixels: pixels(),
+                weight: 3.,
+                widths: Vec::new(),
+                color: color(180.),
+                brush: None,
+                blend: Blend::Normal,
+            },
+            ElementDoc::Line {
+                pixels: pixels(),
+                weight: 3.,
+                widths: vec![0.5, 1., 0.25],
+                color: color(180.),
+                brush: Some(Brush::presets()[1].clone()),
+                blend: Blend::Multiply,
+            },
+            ElementDoc::Rectangle {
+                center: [1., 2.],
+                wh: [30., 20.],
+                rotation: 0.5,
+                style: style(),
+            },
+            ElementDoc::Ellipse {
+                center: [-4., 8.],
+                radii: [12., 6.],
+                rotation: 1.,
+                style: style(),
+            },
+            ElementDoc::RectangleCustom {
+                center: [5., 5.],
+                wh: [64., 32.],
+                weight: 1.,
+                color: color(90.),
+                blend: Blend::Screen,
+            },
+            ElementDoc::Image {
+                png,
+                center: [0., 0.],
+                wh: [40., 20.],
+                rotation: 0.25,
+                opacity: 0.5,
+                blend: Blend::Normal,
+            },
+            ElementDoc::Fill {
+                mask,
+                center: [3., 3.],
+                wh: [3., 3.],
+                rotation: 0.,
+                color: color(0.),
+                blend: Blend::Add,
+            },
+            ElementDoc::Segment {
+                start: [0., 0.],
+                end: [10., 10.],
+                style: style(),
+            },
+            ElementDoc::Polygon {
+                center: [0., 0.],
+                radius: 10.,
+                sides: 6,
+                rotation: 0.5,
+                style: style(),
+            },
+            ElementDoc::Star {
+                center: [0., 0.],
+                radius: 10.,
+                points: 5,
+                ratio: 0.4,
+                rotation: 0.,
+                style: style(),
+            },
+            ElementDoc::Arrow {
+                start: [0., 0.],
+                end: [30., 0.],
+                head: ArrowHead::Double,
+                style: style(),
+            },
+        ]
+    }
+
+    fn document(elements: Vec<ElementDoc>) -> Document {
+        Document {
+            version: VERSION,
+            settings: SettingsDoc {
+                color: color(180.),
+                weight: 4.,
+                ..SettingsDoc::default()
+            },
+            prompt: String::from("a cat"),
+            background: None,
+            layers: vec![LayerDoc {
+                name: String::from("Layer 1"),
+                visible: true,
+                opacity: 0.75,
+                locked: false,
+                elements,
+            }],
+        }
+    }
+
+    fn load(json: &str) -> Vec<ElementDoc> {
+        let doc = Document::from_json(json).unwrap();
+        assert_eq!(doc.version, VERSION);
+        assert_eq!(doc.layers.len(), 1);
+        doc.layers[0].elements.clone()
+    }
+
+    #[test]
+    fn every_element_survives_the_canvas() {
+        for doc in every_element() {
+            assert_eq!(doc.to_element().unwrap().to_doc().unwrap(), doc);
+        }
+    }
+
+    #[test]
+    fn every_element_survives_json() {
+        let doc = document(every_element());
+        let loaded = Document::from_json(&doc.to_json().unwrap()).unwrap();
+        assert_eq!(loaded, doc);
+        let layers = loaded.get_layers().unwrap();
+        assert_eq!(layers[0].elements.len(), every_element().len());
+        assert_eq!(layers[0].opacity, 0.75);
+    }
+
+    #[test]
+    fn broken_images_are_refused() {
+        let image = ElementDoc::Image {
+            png: String::from("bm90IGEgcG5n"),
+            center: [0., 0.],
+            wh: [40., 20.],
+            rotation: 0.,
+            opacity: 1.,
+            blend: Blend::Normal,
+        };
+        assert!(image.to_element().is_err());
+        assert!(document(vec![image]).get_layers().is_err());
+    }
+
+    #[test]
+    fn pictures_that_do_not_encode_fail_the_save() {
+        // png has no empty images
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(0, 0));
+        let mut layer = Layer::new(String::from("Layer 1"));
+        layer
+            .elements
+            .push(Box::new(Picture::new(image, pt2(0., 0.), vec2(2., 2.))));
+        let settings = SettingsDoc::default();
+        assert!(Document::new(settings, "", None, &[layer]).is_err());
+    }
+
+    #[test]
+    fn fill_masks_that_do_not_encode_fail_the_save() {
+        let fill = Fill::new(
+            GrayImage::new(0, 0),
+            hsva(0., 1., 1., 1.),
+            pt2(0., 0.),
+            vec2(2., 2.),
+        );
+        let mut layer = Layer::new(String::from("Layer 1"));
+        layer.elements.push(Box::new(fill));
+        let settings = SettingsDoc::default();
+        assert!(Document::new(settings, "", None, &[layer]).is_err());
+    }
+
+    #[test]
+    fn broken_fill_masks_are_refused() {
+        let fill = ElementDoc::Fill {
+            mask: String::from("%%%"),
+            center: [0., 0.],
+            wh: [3., 3.],
+            rotation: 0.,
+            color: color(0.),
+            blend: Blend::Normal,
+        };
+        assert!(fill.to_element().is_err());
+        assert!(document(vec![fill]).get_layers().is_err());
+    }
+
+    #[test]
+    fn newer_files_are_refused() {
+        let mut doc = document(Vec::new());
+        doc.version = VERSION + 1;
+        assert!(Document::from_json(&doc.to_json().unwrap()).is_err());
+    }
+
+    #[test]
+    fn files_without_a_known_version_are_refused() {
+        let mut doc = document(Vec::new());
+        doc.version = FIRST_VERSION - 1;
+        assert!(Document::from_json(&doc.to_json().unwrap()).is_err());
+        let json = r#"{"settings": {}, "prompt": "", "background": null, "layers": []}"#;
+        assert!(Document::from_json(json).is_err());
+    }
+
+    #[test]
+    fn settings_survive_json() {
+        let mut settings = Settings::new(hsva(0.5, 0.5, 1., 0.5), 6., true);
+        settings.smoothing = Smoothing::Bezier;
+        settings.passes = 3;
+        settings.variable_width = false;
+        settings.brush = Brush::presets()[2].clone();
+        settings.fill_antialias = false;
+        settings.star_points = 7;
+        settings.arrow_head = ArrowHead::Double;
+        settings.style.fill = Some(hsva(0.25, 1., 1., 1.));
+        settings.blend = Blend::Screen;
+        let diffusion = DiffusionConfig {
+            url: String::from("http://example.com"),
+            steps: 30,
+            seed: 42,
+            ..DiffusionConfig::default()
+        };
+        let mut doc = document(Vec::new());
+        doc.settings = SettingsDoc::new(&settings, &Tool::Star, &diffusion);
+        let loaded = Document::from_json(&doc.to_json().unwrap()).unwrap();
+        assert_eq!(loaded.settings.tool, Tool::Star);
+        assert_eq!(loaded.settings.diffusion.seed, 42);
+        // the server address is not the canvas'
+        assert_eq!(
+            loaded.settings.diffusion.url,
+            DiffusionConfig::default().url
+        );
+
+        let back = loaded.settings.to_settings(false);
+        assert!(!back.get_shapes());
+        let again = SettingsDoc::new(&back, &Tool::Star, &diffusion);
+        assert_eq!(again, doc.settings);
+    }
+
+    #[test]
+    fn version_1_shapes_get_a_style() {
+        let elements = load(
+            r#"{
+                "version": 1,
+                "settings": {"color": {"hue": 180, "saturation": 0.5, "value": 1}, "weight": 2},
+                "prompt": "",
+                "background": null,
+                "layers": [{"name": "Layer 1", "visible": true, "opacity": 1, "locked": false,
+                    "elements": [
+                        {"kind": "Segment", "start": [0, 0], "end": [10, 0], "weight": 3,
+                         "color": {"hue": 180, "saturation": 0.5, "value": 1}}
+                    ]}]
+            }"#,
+        );
+        assert_eq!(
+            elements,
+            vec![ElementDoc::Segment {
+                start: [0., 0.],
+                end: [10., 0.],
+                style: StyleDoc {
+                    fill: None,
+                    stroke: color(180.),
+                    width: 3.,
+                    dash: Vec::new(),
+                    join: StrokeJoin::Round,
+                    cap: StrokeCap::Round,
+                    blend: Blend::Normal,
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn version_2_ellipses_get_two_radii() {
+        let elements = load(
+            r#"{
+                "version": 2,
+                "settings": {"color": {"hue": 180, "saturation": 0.5, "value": 1}, "weight": 2},
+                "prompt": "",
+                "background": null,
+                "layers": [{"name": "Layer 1", "visible": true, "opacity": 1, "locked": false,
+                    "elements": [
+                        {"kind": "Ellipse", "center": [1, 1], "radius": 7, "style": {
+                            "fill": null, "stroke": {"hue": 90, "saturation": 0.5, "value": 1},
+                            "width": 2, "join": "Bevel", "cap": "Butt"}}
+                    ]}]
+            }"#,
+        );
+        assert_eq!(
+            elements,
+            vec![ElementDoc::Ellipse {
+                center: [1., 1.],
+                radii: [7., 7.],
+                rotation: 0.,
+                style: StyleDoc {
+                    fill: None,
+                    stroke: color(90.),
+                    width: 2.,
+                    dash: Vec::new(),
+                    join: StrokeJoin::Bevel,
+                    cap: StrokeCap::Butt,
+                    blend: Blend::Normal,
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn custom_rect_pixels_of_older_files_are_dropped() {
+        let elements = load(
+            r#"{
+                "version": 1,
+                "settings": {"color": {"hue": 180, "saturation": 0.5, "value": 1}, "weight": 2},
+                "prompt": "",
+                "background": null,
+                "layers": [{"name": "Layer 1", "visible": true, "opacity": 1, "locked": false,
+                    "elements": [
+                        {"kind": "RectangleCustom", "center": [5, 5], "wh": [2, 1], "weight": 1,
+                            "pixels": [{"point": [4, 5], "color": {"hue": 90, "saturation": 0.5, "value": 1}},
+                                {"point": [5, 5], "color": {"hue": 90, "saturation": 0.5, "value": 1}}],
+                            "color": {"hue": 90, "saturation": 0.5, "value": 1}}
+                    ]}]
+            }"#,
+        );
+        assert_eq!(
+            elements,
+            vec![ElementDoc::RectangleCustom {
+                center: [5., 5.],
+                wh: [2., 1.],
+                weight: 1.,
+                color: color(90.),
+                blend: Blend::Normal,
+            }]
+        );
+    }
+}
@@ -1,196 +1,387 @@
-use nannou::prelude::*;
-
-pub trait Pencil {
-    fn draw_elem(&self, draw: &Draw);
-    fn trait_iter_mut(&mut self) -> Box<dyn Iterator<Item = &mut (Point2, Hsv)> + '_>;
-    fn set_color(&mut self, color: Hsv);
-    fn get_color(&self) -> Hsv;
-    fn set_weight(&mut self, weight: f32);
-    fn get_weight(&self) -> f32;
-    fn get_pixels(&self) -> Vec<(Point2, Hsv)>;
-}
-pub trait Forms {
-    fn draw_elem(&self, draw: &Draw);
-    fn set_color(&mut self, color: Hsv);
-    fn get_color(&self) -> Hsv;
-    fn set_center(&mut self, center: Vec2);
-    fn get_center(&self) -> Vec2;
-    fn get_clicked(&self) -> bool;
-    fn set_clicked(&mut self, clicked: bool);
+use super::{
+    blend::Blend,
+    brush::{Brush, BrushShape},
+    document::{pixels_to_doc, ElementDoc},
+    picture::{Picture, TextureCache},
+    stroke,
+    style::ShapeStyle,
+};
+use nannou::{image::DynamicImage, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::{any::Any, error::Error};
+
+// soft brush tips are drawn as this many rings
+const SOFT_RINGS: usize = 4;
+// corners of the outline ellipses are drawn with
+const ELLIPSE_STEPS: usize = 64;
+
+// anything that can sit on a layer
+pub trait Element: Send {
+    fn draw_elem(&self, draw: &Draw, app: &App, textures: &TextureCache, alpha: f32);
+    fn get_color(&self) -> Hsva;
+    fn set_color(&mut self, color: Hsva);
+    fn get_blend(&self) -> Blend;
+    fn set_blend(&mut self, blend: Blend);
+    // smallest axis aligned box around the element, as min and max corners
+    fn bounds(&self) -> (Vec2, Vec2);
+    fn hit(&self, p: Vec2, r: f32) -> bool;
+    fn translate(&mut self, delta: Vec2);
+    // scales by `scale` and turns by `angle` radians around `pivot`
+    fn transform(&mut self, pivot: Vec2, scale: f32, angle: f32);
+    // what is left once the rubber touched it, None when it did not
+    fn erase(&self, mouse_pos: Vec2, r: f32) -> Option<Vec<Elements>>;
+    fn clone_box(&self) -> Elements;
+    fn to_doc(&self) -> Result<ElementDoc, Box<dyn Error>>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
 }
 
-pub enum Elements {
-    // pointer to the trait
-    L(Box<dyn Pencil>),
-    F(Box<dyn Forms>),
-}
+pub type Elements = Box<dyn Element>;
 
-trait Elems {
-    fn test_elem(&self) -> bool;
+impl Clone for Elements {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
 }
 
-impl<T: Pencil + Forms> Elems for T {
-    fn test_elem(&self) -> bool {
-        true
+impl dyn Element {
+    // the concrete element, for the few places that need one kind
+    pub fn downcast_ref<T: Element + 'static>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+
+    pub fn downcast_mut<T: Element + 'static>(&mut self) -> Option<&mut T> {
+        self.as_any_mut().downcast_mut()
     }
 }
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub enum Tool {
     Pencil,
     Rect,
-    Rect_Custom,
+    RectCustom,
     Ellipse,
     Rubber,
+    Mask,
+    Select,
+    Fill,
+    Segment,
+    Polygon,
+    Star,
+    Arrow,
 }
 
 #[derive(Clone, Default)]
 pub struct Rectangle {
     center: Point2,
     wh: Point2,
-    color: Hsv,
-    clicked: bool,
+    rotation: f32,
+    style: ShapeStyle,
 }
 
-impl Forms for Rectangle {
-    fn draw_elem(&self, draw: &Draw) {
-        draw.rect()
-            .xy(self.center)
-            .wh(self.wh)
-            .color(self.color)
-            .no_fill()
-            .stroke_color(self.get_color());
-
+impl Element for Rectangle {
+    fn draw_elem(&self, draw: &Draw, _app: &App, _textures: &TextureCache, alpha: f32) {
+        self.style.draw(draw, &self.outline(), alpha);
     }
-    fn set_color(&mut self, color: Hsv) {
-        self.color = color;
+    fn get_color(&self) -> Hsva {
+        self.style.get_color()
     }
-    fn get_color(&self) -> Hsv {
-        self.color
+    fn set_color(&mut self, color: Hsva) {
+        self.style.set_color(color);
     }
-    fn get_center(&self) -> Vec2 {
-        self.center
+    fn get_blend(&self) -> Blend {
+        self.style.blend
     }
-    fn get_clicked(&self) -> bool {
-        self.clicked
+    fn set_blend(&mut self, blend: Blend) {
+        self.style.blend = blend;
     }
-    fn set_center(&mut self, center: Vec2) {
-        self.center = center
+    fn bounds(&self) -> (Vec2, Vec2) {
+        points_bounds(&self.outline(), self.style.reach())
     }
-
-    fn set_clicked(&mut self, clicked: bool) {
-        self.clicked = clicked
+    fn hit(&self, p: Vec2, r: f32) -> bool {
+        // anywhere inside counts, the outline alone is hard to click
+        let local = transform_point(p, self.center, 1., -self.rotation) - self.center;
+        let reach = r + self.style.width / 2.;
+        let half = self.wh.abs() / 2. + vec2(reach, reach);
+        local.x.abs() <= half.x && local.y.abs() <= half.y
+    }
+    fn translate(&mut self, delta: Vec2) {
+        self.center += delta;
+    }
+    fn transform(&mut self, pivot: Vec2, scale: f32, angle: f32) {
+        self.center = transform_point(self.center, pivot, scale, angle);
+        self.wh *= scale;
+        self.rotation += angle;
+        self.style.width *= scale;
+    }
+    fn erase(&self, mouse_pos: Vec2, r: f32) -> Option<Vec<Elements>> {
+        self.style
+            .erase(&self.outline(), self.hit(mouse_pos, r), mouse_pos, r)
+    }
+    fn clone_box(&self) -> Elements {
+        Box::new(self.clone())
+    }
+    fn to_doc(&self) -> Result<ElementDoc, Box<dyn Error>> {
+        Ok(ElementDoc::Rectangle {
+            center: [self.center.x, self.center.y],
+            wh: [self.wh.x, self.wh.y],
+            rotation: self.rotation,
+            style: (&self.style).into(),
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
     }
 }
 
 impl Rectangle {
-    pub fn new(center: Vec2, wh: Vec2, color: Hsv, clicked: bool) -> Self {
+    pub fn new(center: Vec2, wh: Vec2, style: ShapeStyle) -> Self {
         Self {
             center,
             wh,
-            color,
-            clicked,
+            rotation: 0.,
+            style,
         }
     }
 
-    pub fn get_wh(&self) -> Vec2 {
-        self.wh
+    pub fn set_center(&mut self, center: Vec2) {
+        self.center = center
     }
 
     pub fn set_wh(&mut self, center: Vec2) {
         self.wh = center
     }
+
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.rotation = rotation
+    }
+
+    // corners from the top left going clockwise, back to the first
+    fn outline(&self) -> Vec<Vec2> {
+        let half = self.wh / 2.;
+        [
+            vec2(-half.x, half.y),
+            vec2(half.x, half.y),
+            vec2(half.x, -half.y),
+            vec2(-half.x, -half.y),
+            vec2(-half.x, half.y),
+        ]
+        .iter()
+        .map(|&c| transform_point(self.center + c, self.center, 1., self.rotation))
+        .collect()
+    }
 }
 
 #[derive(Clone, Default)]
 pub struct Ellipse {
     center: Point2,
-    color: Hsv,
-    radius: f32,
-    clicked: bool,
+    // half the width and height before the rotation
+    radii: Vec2,
+    rotation: f32,
+    style: ShapeStyle,
 }
 
-impl Forms for Ellipse {
-    fn draw_elem(&self, draw: &Draw) {
-        draw.ellipse()
-            .xy(self.center)
-            .radius(self.radius)
-            .color(self.color);
+impl Element for Ellipse {
+    fn draw_elem(&self, draw: &Draw, _app: &App, _textures: &TextureCache, alpha: f32) {
+        self.style.draw(draw, &self.outline(), alpha);
     }
-    fn set_color(&mut self, color: Hsv) {
-        self.color = color;
+    fn get_color(&self) -> Hsva {
+        self.style.get_color()
     }
-    fn get_color(&self) -> Hsv {
-        self.color
+    fn set_color(&mut self, color: Hsva) {
+        self.style.set_color(color);
     }
-    fn get_center(&self) -> Vec2 {
-        self.center
+    fn get_blend(&self) -> Blend {
+        self.style.blend
     }
-    fn get_clicked(&self) -> bool {
-        self.clicked
+    fn set_blend(&mut self, blend: Blend) {
+        self.style.blend = blend;
     }
-    fn set_center(&mut self, center: Vec2) {
-        self.center = center
+    fn bounds(&self) -> (Vec2, Vec2) {
+        points_bounds(&self.outline(), self.style.width / 2.)
     }
-    fn set_clicked(&mut self, clicked: bool) {
-        self.clicked = clicked
+    fn hit(&self, p: Vec2, r: f32) -> bool {
+        let local = transform_point(p, self.center, 1., -self.rotation) - self.center;
+        let reach = r + self.style.width / 2.;
+        let radii = self.radii.abs() + vec2(reach, reach);
+        (local / radii).length() <= 1.
+    }
+    fn translate(&mut self, delta: Vec2) {
+        self.center += delta;
+    }
+    fn transform(&mut self, pivot: Vec2, scale: f32, angle: f32) {
+        self.center = transform_point(self.center, pivot, scale, angle);
+        self.radii *= scale;
+        self.rotation += angle;
+        self.style.width *= scale;
+    }
+    fn erase(&self, mouse_pos: Vec2, r: f32) -> Option<Vec<Elements>> {
+        self.style
+            .erase(&self.outline(), self.hit(mouse_pos, r), mouse_pos, r)
+    }
+    fn clone_box(&self) -> Elements {
+        Box::new(self.clone())
+    }
+    fn to_doc(&self) -> Result<ElementDoc, Box<dyn Error>> {
+        Ok(ElementDoc::Ellipse {
+            center: [self.center.x, self.center.y],
+            radii: [self.radii.x, self.radii.y],
+            rotation: self.rotation,
+            style: (&self.style).into(),
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
     }
 }
 
 impl Ellipse {
-    pub fn new(center: Vec2, color: Hsv, radius: f32, clicked: bool) -> Self {
+    pub fn new(center: Vec2, radii: Vec2, style: ShapeStyle) -> Self {
         Self {
             center,
-            color,
-            radius,
-            clicked,
+            radii,
+            rotation: 0.,
+            style,
         }
     }
 
-    pub fn get_radius(&self) -> f32 {
-        self.radius
+    pub fn set_center(&mut self, center: Vec2) {
+        self.center = center
+    }
+
+    pub fn set_radii(&mut self, radii: Vec2) {
+        self.radii = radii
     }
 
-    pub fn set_radius(&mut self, radius: f32) {
-        self.radius = radius
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.rotation = rotation
+    }
+
+    fn outline(&self) -> Vec<Vec2> {
+        ellipse_points(self.center, self.radii, self.rotation)
     }
 }
 
 #[derive(Clone)]
 pub struct Line {
-    pub pixels: Vec<(Point2, Hsv)>,
+    pub pixels: Vec<(Point2, Hsva)>,
     weight: f32,
-    color: Hsv,
+    // width of each point as a share of the weight, empty when the whole
+    // stroke has the weight
+    widths: Vec<f32>,
+    color: Hsva,
+    // None for strokes of solid brushes
+    brush: Option<Brush>,
+    // the tip of texture brushes in the color of the stroke
+    stamp: Option<Picture>,
+    blend: Blend,
 }
 
-impl Pencil for Line {
-    fn draw_elem(&self, draw: &Draw) {
+impl Element for Line {
+    fn draw_elem(&self, draw: &Draw, app: &App, textures: &TextureCache, alpha: f32) {
+        let draw = &self.blend.draw(draw);
+        if let Some(brush) = &self.brush {
+            self.draw_dabs(brush, draw, app, textures, alpha);
+            return;
+        }
+        if !self.widths.is_empty() {
+            let halves: Vec<f32> = (0..self.pixels.len())
+                .map(|i| self.width_at(i) * self.weight / 2.)
+                .collect();
+            for (&(p, color), half) in self.pixels.iter().zip(&halves) {
+                draw.ellipse()
+                    .xy(p)
+                    .radius(*half)
+                    .color(with_alpha(color, alpha));
+            }
+            for quad in stroke::quads(&self.pixels, &halves) {
+                draw.polygon()
+                    .points_colored(pixels_with_alpha(&quad, alpha));
+            }
+            return;
+        }
         draw.polyline()
             .weight(self.get_weight())
-            .color(self.get_color())
+            .color(with_alpha(self.get_color(), alpha))
             .start_cap_round()
             .caps_round()
             .end_cap_round()
             .join_round()
-            .points_colored(self.get_line().clone());
-    }
-    fn trait_iter_mut(&mut self) -> Box<dyn Iterator<Item = &mut (Point2, Hsv)> + '_> {
-        Box::new(self.pixels.iter_mut())
-    }
-    fn set_color(&mut self, color: Hsv) {
-        self.color = color;
+            .points_colored(pixels_with_alpha(self.get_line(), alpha));
     }
-    fn get_color(&self) -> Hsv {
+    fn get_color(&self) -> Hsva {
         self.color
     }
-    fn set_weight(&mut self, weight: f32) {
-        self.weight = weight;
+    fn set_color(&mut self, color: Hsva) {
+        self.color = color;
+        self.pixels.iter_mut().for_each(|(_, c)| *c = color);
+        self.update_stamp();
+    }
+    fn get_blend(&self) -> Blend {
+        self.blend
+    }
+    fn set_blend(&mut self, blend: Blend) {
+        self.blend = blend;
+    }
+    fn bounds(&self) -> (Vec2, Vec2) {
+        pixel_bounds(&self.pixels, self.reach())
+    }
+    fn hit(&self, p: Vec2, r: f32) -> bool {
+        let reach = self.reach() + r;
+        match self.pixels.as_slice() {
+            [] => false,
+            [(a, _)] => p.distance(*a) <= reach,
+            pixels => pixels
+                .windows(2)
+                .any(|w| segment_distance(p, w[0].0, w[1].0) <= reach),
+        }
     }
-    fn get_weight(&self) -> f32 {
-        self.weight
+    fn translate(&mut self, delta: Vec2) {
+        self.pixels.iter_mut().for_each(|(p, _)| *p += delta);
     }
-    fn get_pixels(&self) -> Vec<(Point2, Hsv)> {
-        self.pixels.clone()
+    fn transform(&mut self, pivot: Vec2, scale: f32, angle: f32) {
+        for (p, _) in self.pixels.iter_mut() {
+            *p = transform_point(*p, pivot, scale, angle);
+        }
+        self.weight *= scale;
+    }
+    fn erase(&self, mouse_pos: Vec2, r: f32) -> Option<Vec<Elements>> {
+        let segments = split_pixels(&self.get_points(), mouse_pos, r + self.reach())?;
+        Some(
+            segments
+                .into_iter()
+                .map(|points| {
+                    let mut line = self.clone();
+                    line.set_points(points, !self.widths.is_empty());
+                    Box::new(line) as Elements
+                })
+                .collect(),
+        )
+    }
+    fn clone_box(&self) -> Elements {
+        Box::new(self.clone())
+    }
+    fn to_doc(&self) -> Result<ElementDoc, Box<dyn Error>> {
+        Ok(ElementDoc::Line {
+            pixels: pixels_to_doc(&self.pixels),
+            weight: self.weight,
+            widths: self.widths.clone(),
+            color: self.color.into(),
+            brush: self.brush.clone(),
+            blend: self.blend,
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
     }
 }
 
@@ -199,107 +390,211 @@ impl Line {
         Self {
             pixels: Vec::new(),
             weight: 0.,
-            color: hsv(10.0, 0.5, 1.0),
+            widths: Vec::new(),
+            color: hsva(10.0, 0.5, 1.0, 1.0),
+            brush: None,
+            stamp: None,
+            blend: Blend::Normal,
         }
     }
-    pub fn new_param(pixels: Vec<(Point2, Hsv)>, weight: f32, color: Hsv) -> Self {
+    pub fn new_param(pixels: Vec<(Point2, Hsva)>, weight: f32, color: Hsva) -> Self {
         Self {
             pixels,
             weight,
+            widths: Vec::new(),
             color,
+            brush: None,
+            stamp: None,
+            blend: Blend::Normal,
         }
     }
     pub fn clear_line(&mut self) {
-        self.pixels.clear()
+        self.pixels.clear();
+        self.widths.clear();
+    }
+    // adds a point `width` times as wide as the weight
+    pub fn push_point(&mut self, p: Point2, color: Hsva, width: f32) {
+        self.widths.resize(self.pixels.len(), 1.);
+        self.pixels.push((p, color));
+        self.widths.push(width);
+    }
+    // strokes of solid brushes keep no brush and draw as one polyline
+    pub fn set_brush(&mut self, brush: Brush) {
+        self.brush = (!brush.is_solid()).then_some(brush);
+        self.update_stamp();
+    }
+    fn update_stamp(&mut self) {
+        self.stamp = match &self.brush {
+            Some(brush) if brush.shape == BrushShape::Texture => Some(Picture::from_image(
+                DynamicImage::ImageRgba8(brush.tint(self.color)),
+                Vec2::ZERO,
+            )),
+            _ => None,
+        };
+    }
+    // how far paint gets from the points
+    fn reach(&self) -> f32 {
+        let scatter = self.brush.as_ref().map_or(0., |brush| brush.scatter);
+        self.weight * (0.5 + scatter)
+    }
+    fn draw_dabs(
+        &self,
+        brush: &Brush,
+        draw: &Draw,
+        app: &App,
+        textures: &TextureCache,
+        alpha: f32,
+    ) {
+        let opacity = brush.opacity * alpha;
+        let roundness = match brush.shape {
+            BrushShape::Flat => brush.roundness,
+            _ => 1.,
+        };
+        // soft tips are rings shrinking toward the middle, together they
+        // reach the opacity there
+        let rings = if brush.hardness >= 1. { 1 } else { SOFT_RINGS };
+        for dab in brush.dabs(&self.get_points(), self.weight) {
+            if let (BrushShape::Texture, Some(stamp)) = (brush.shape, &self.stamp) {
+                textures.with_texture(app, stamp, opacity, |texture| {
+                    draw.texture(texture)
+                        .xy(dab.center)
+                        .w_h(dab.size, dab.size)
+                        .rotate(dab.angle);
+                });
+                continue;
+            }
+            let ring_alpha = 1. - (1. - opacity * dab.color.alpha).powf(1. / rings as f32);
+            for k in 0..rings {
+                let s = 1. - k as f32 / rings as f32 * (1. - brush.hardness);
+                draw.ellipse()
+                    .xy(dab.center)
+                    .w_h(dab.size * s, dab.size * roundness * s)
+                    .rotate(dab.angle)
+                    .color(Hsva {
+                        alpha: ring_alpha,
+                        ..dab.color
+                    });
+            }
+        }
+    }
+    pub fn get_widths(&self) -> &Vec<f32> {
+        &self.widths
+    }
+    // ignored unless there is one width per point
+    pub fn set_widths(&mut self, widths: Vec<f32>) {
+        if widths.len() == self.pixels.len() {
+            self.widths = widths;
+        }
     }
-    pub fn get_line(&self) -> &Vec<(Point2, Hsv)> {
+    pub fn width_at(&self, index: usize) -> f32 {
+        self.widths.get(index).copied().unwrap_or(1.)
+    }
+    // the pixels with their widths, for changes that move or drop points
+    pub fn get_points(&self) -> Vec<(Point2, (Hsva, f32))> {
+        self.pixels
+            .iter()
+            .enumerate()
+            .map(|(i, &(p, c))| (p, (c, self.width_at(i))))
+            .collect()
+    }
+    // the widths are kept only for strokes that had their own
+    pub fn set_points(&mut self, points: Vec<(Point2, (Hsva, f32))>, variable: bool) {
+        self.pixels = points.iter().map(|&(p, (c, _))| (p, c)).collect();
+        self.widths = if variable {
+            points.iter().map(|&(_, (_, w))| w).collect()
+        } else {
+            Vec::new()
+        };
+    }
+    pub fn get_line(&self) -> &Vec<(Point2, Hsva)> {
         &self.pixels
     }
+    pub fn get_weight(&self) -> f32 {
+        self.weight
+    }
+    pub fn set_weight(&mut self, weight: f32) {
+        self.weight = weight;
+    }
 }
 
 #[derive(Clone, Default)]
-pub struct Rectangle_Custom {
+pub struct RectangleCustom {
     pub center: Point2,
     pub wh: Point2,
     pub weight: f32,
-    pub pixels: Vec<(Point2, Hsv)>,
-    pub color: Hsv,
-    pub clicked: bool,
+    pub color: Hsva,
+    pub blend: Blend,
 }
 
-impl Pencil for Rectangle_Custom {
-    fn draw_elem(&self, draw: &Draw) {
-        draw.polyline()
-            .color(self.get_color())
-            .start_cap_square()
-            .caps_square()
-            .end_cap_square()
-            // .stroke_weight(self.get_weight())
-            .points_colored(self.get_line().clone());
+impl Element for RectangleCustom {
+    fn draw_elem(&self, draw: &Draw, _app: &App, _textures: &TextureCache, alpha: f32) {
+        self.blend
+            .draw(draw)
+            .rect()
+            .xy(self.center)
+            .wh(self.wh.abs())
+            .color(with_alpha(self.get_color(), alpha));
     }
-    fn trait_iter_mut(&mut self) -> Box<dyn Iterator<Item = &mut (Point2, Hsv)> + '_> {
-        Box::new(self.pixels.iter_mut())
+    fn get_color(&self) -> Hsva {
+        self.color
     }
-    fn set_color(&mut self, color: Hsv) {
+    fn set_color(&mut self, color: Hsva) {
         self.color = color;
     }
-    fn get_color(&self) -> Hsv {
-        self.color
+    fn get_blend(&self) -> Blend {
+        self.blend
     }
-    fn set_weight(&mut self, weight: f32) {
-        self.weight = weight;
+    fn set_blend(&mut self, blend: Blend) {
+        self.blend = blend;
     }
-    fn get_weight(&self) -> f32 {
-        self.weight
+    fn bounds(&self) -> (Vec2, Vec2) {
+        let half = self.wh.abs() / 2.;
+        (self.center - half, self.center + half)
+    }
+    fn hit(&self, p: Vec2, r: f32) -> bool {
+        let (min, max) = self.bounds();
+        p.cmpge(min - vec2(r, r)).all() && p.cmple(max + vec2(r, r)).all()
+    }
+    fn translate(&mut self, delta: Vec2) {
+        self.center += delta;
+    }
+    // the frame used for generation stays axis aligned, turning only moves it
+    fn transform(&mut self, pivot: Vec2, scale: f32, angle: f32) {
+        self.center = transform_point(self.center, pivot, scale, angle);
+        self.wh *= scale;
+    }
+    // a frame with a hole can not be generated into, the rubber takes all of it
+    fn erase(&self, mouse_pos: Vec2, r: f32) -> Option<Vec<Elements>> {
+        self.hit(mouse_pos, r).then(Vec::new)
+    }
+    fn clone_box(&self) -> Elements {
+        Box::new(self.clone())
+    }
+    fn to_doc(&self) -> Result<ElementDoc, Box<dyn Error>> {
+        Ok(ElementDoc::RectangleCustom {
+            center: [self.center.x, self.center.y],
+            wh: [self.wh.x, self.wh.y],
+            weight: self.weight,
+            color: self.color.into(),
+            blend: self.blend,
+        })
     }
-    fn get_pixels(&self) -> Vec<(Point2, Hsv)> {
-        self.pixels.clone()
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
     }
 }
 
-impl Rectangle_Custom {
-    pub fn new(center: Vec2, wh: Vec2, color: Hsv, clicked: bool, weight: f32) -> Self {
+impl RectangleCustom {
+    pub fn new(center: Vec2, wh: Vec2, color: Hsva, weight: f32) -> Self {
         Self {
             center,
             wh,
             weight,
-            pixels: Vec::new(),
             color,
-            clicked,
-        }
-    }
-
-    pub fn rect_to_pixels(&mut self, rb_corner: Point2) {
-        // need to put this in a new thread, now it is blocking
-
-        let d = rb_corner - self.get_center();
-        let x1 = rb_corner.x - d.x * 2.;
-        let x2 = rb_corner.x;
-        let y1 = rb_corner.y - d.y * 2.;
-        let y2 = rb_corner.y;
-
-        let lt = pt2(x1, y1);
-        let lb = pt2(x1, y2);
-        let rt = pt2(x2, y1);
-
-        // create new lines
-        let height: i32 = ((lb.x - lt.x).pow(2) as f32 + (lb.y - lt.y).pow(2) as f32)
-            .sqrt()
-            .floor() as i32;
-        let width: i32 = ((rt.x - lt.x).pow(2) as f32 + (rt.y - lt.y).pow(2) as f32)
-            .sqrt()
-            .floor() as i32;
-        let mut height_counter = 0;
-        while height_counter < height {
-            let mut width_counter = 0;
-            while width_counter < width {
-                self.pixels.push((
-                    (lt.x + width_counter as f32, lt.y - height_counter as f32).into(),
-                    self.get_color(),
-                ));
-                width_counter += 1;
-            }
-            height_counter += 1;
+            blend: Blend::Normal,
         }
     }
 
@@ -311,24 +606,281 @@ impl Rectangle_Custom {
         self.wh = center
     }
 
-    pub fn clear_line(&mut self) {
-        self.pixels.clear()
-    }
-    pub fn get_line(&self) -> &Vec<(Point2, Hsv)> {
-        &self.pixels
-    }
-
     pub fn get_center(&self) -> Vec2 {
         self.center
     }
-    pub fn get_clicked(&self) -> bool {
-        self.clicked
-    }
     pub fn set_center(&mut self, center: Vec2) {
         self.center = center
     }
+}
+
+// the color with its alpha scaled by the layer opacity
+pub fn with_alpha(color: Hsva, alpha: f32) -> Hsva {
+    hsva(
+        color.hue.to_positive_degrees() / 360.,
+        color.saturation,
+        color.value,
+        color.alpha * alpha,
+    )
+}
+
+pub fn transform_point(p: Vec2, pivot: Vec2, scale: f32, angle: f32) -> Vec2 {
+    let (sin, cos) = angle.sin_cos();
+    let d = (p - pivot) * scale;
+    pivot + vec2(d.x * cos - d.y * sin, d.x * sin + d.y * cos)
+}
+
+// pixels about one unit apart along the path through `corners`, so the
+// rubber can cut outlines like strokes
+pub fn walk_pixels(corners: &[Vec2], color: Hsva) -> Vec<(Point2, Hsva)> {
+    let mut pixels = Vec::new();
+    for side in corners.windows(2) {
+        let steps = side[0].distance(side[1]).ceil().max(1.) as i32;
+        for step in 0..steps {
+            let p = side[0].lerp(side[1], step as f32 / steps as f32);
+            pixels.push((p, color));
+        }
+    }
+    if let Some(last) = corners.last() {
+        pixels.push((*last, color));
+    }
+    pixels
+}
+
+// points around an ellipse turned by `rotation`, closed like the other
+// outlines
+pub fn ellipse_points(center: Vec2, radii: Vec2, rotation: f32) -> Vec<Vec2> {
+    let mut points: Vec<Vec2> = (0..ELLIPSE_STEPS)
+        .map(|i| {
+            let angle = i as f32 * 2. * PI / ELLIPSE_STEPS as f32;
+            let p = center + vec2(angle.cos(), angle.sin()) * radii;
+            transform_point(p, center, 1., rotation)
+        })
+        .collect();
+    points.push(points[0]);
+    points
+}
+
+// center and size of the box dragged from `anchor` to `p`, the anchor is a
+// corner unless `from_center`, `square` evens out the sides
+pub fn drag_box(anchor: Vec2, p: Vec2, from_center: bool, square: bool) -> (Vec2, Vec2) {
+    let mut d = p - anchor;
+    if square {
+        let side = d.x.abs().max(d.y.abs());
+        d = vec2(side.copysign(d.x), side.copysign(d.y));
+    }
+    if from_center {
+        (anchor, d.abs() * 2.)
+    } else {
+        (anchor + d / 2., d.abs())
+    }
+}
+
+pub fn points_bounds(points: &[Vec2], pad: f32) -> (Vec2, Vec2) {
+    let first = points.first().copied().unwrap_or(Vec2::ZERO);
+    let (min, max) = points
+        .iter()
+        .fold((first, first), |(min, max), p| (min.min(*p), max.max(*p)));
+    (min - vec2(pad, pad), max + vec2(pad, pad))
+}
+
+fn pixel_bounds(pixels: &[(Point2, Hsva)], pad: f32) -> (Vec2, Vec2) {
+    let first = pixels.first().map_or(Vec2::ZERO, |(p, _)| *p);
+    let (min, max) = pixels.iter().fold((first, first), |(min, max), (p, _)| {
+        (min.min(*p), max.max(*p))
+    });
+    (min - vec2(pad, pad), max + vec2(pad, pad))
+}
+
+pub fn segment_distance(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let t = if ab.length_squared() > 0. {
+        ((p - a).dot(ab) / ab.length_squared()).clamp(0., 1.)
+    } else {
+        0.
+    };
+    p.distance(a + ab * t)
+}
+
+fn pixels_with_alpha(pixels: &[(Point2, Hsva)], alpha: f32) -> Vec<(Point2, Hsva)> {
+    pixels
+        .iter()
+        .map(|&(p, c)| (p, with_alpha(c, alpha)))
+        .collect()
+}
+
+// cuts the part of the polyline inside the rubber out and returns the runs
+// left on either side, None when the rubber did not touch it
+pub fn split_pixels<T: Copy>(
+    pixels: &[(Point2, T)],
+    mouse_pos: Vec2,
+    r: f32,
+) -> Option<Vec<Vec<(Point2, T)>>> {
+    let touched = match pixels {
+        [] => false,
+        [(p, _)] => p.distance(mouse_pos) < r,
+        pixels => pixels
+            .windows(2)
+            .any(|w| segment_distance(mouse_pos, w[0].0, w[1].0) < r),
+    };
+    if !touched {
+        return None;
+    }
+    let mut segments = Vec::new();
+    let mut current = Vec::new();
+    if let Some(&(p, c)) = pixels.first() {
+        if p.distance(mouse_pos) >= r {
+            current.push((p, c));
+        }
+    }
+    for w in pixels.windows(2) {
+        let ((a, ca), (b, cb)) = (w[0], w[1]);
+        match circle_span(a, b, mouse_pos, r) {
+            None => current.push((b, cb)),
+            Some((t0, t1)) => {
+                // the stroke runs into the rubber here and out again later
+                if t0 > 0. {
+                    current.push((a.lerp(b, t0), ca));
+                }
+                if !current.is_empty() {
+                    segments.push(std::mem::take(&mut current));
+                }
+                if t1 < 1. {
+                    current.push((a.lerp(b, t1), cb));
+                    current.push((b, cb));
+                }
+            }
+        }
+    }
+    if !current.is_empty() {
+        segments.push(current);
+    }
+    Some(segments)
+}
 
-    pub fn set_clicked(&mut self, clicked: bool) {
-        self.clicked = clicked
+// the part of the segment from `a` to `b` inside the circle, as shares of
+// its length, None when it stays outside
+fn circle_span(a: Vec2, b: Vec2, center: Vec2, r: f32) -> Option<(f32, f32)> {
+    let d = b - a;
+    let f = a - center;
+    let (qa, qb, qc) = (
+        d.length_squared(),
+        2. * f.dot(d),
+        f.length_squared() - r * r,
+    );
+    if qa <= f32::EPSILON {
+        return (qc < 0.).then_some((0., 1.));
+    }
+    let disc = qb * qb - 4. * qa * qc;
+    if disc <= 0. {
+        return None;
+    }
+    let root = disc.sqrt();
+    let t0 = ((-qb - root) / (2. * qa)).max(0.);
+    let t1 = ((-qb + root) / (2. * qa)).min(1.);
+    (t0 < t1).then_some((t0, t1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(pixels: &[(Point2, Hsv)]) -> Vec<Point2> {
+        pixels.iter().map(|&(p, _)| p).collect()
+    }
+
+    fn pixels(points: &[Point2]) -> Vec<(Point2, Hsv)> {
+        points.iter().map(|&p| (p, hsv(0., 1., 1.))).collect()
+    }
+
+    #[test]
+    fn split_cuts_between_far_points() {
+        let pixels = pixels(&[pt2(0., 0.), pt2(100., 0.)]);
+        let runs = split_pixels(&pixels, pt2(50., 0.), 5.).unwrap();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(points(&runs[0]), vec![pt2(0., 0.), pt2(45., 0.)]);
+        assert_eq!(points(&runs[1]), vec![pt2(55., 0.), pt2(100., 0.)]);
+    }
+
+    #[test]
+    fn split_misses_the_stroke() {
+        let pixels = pixels(&[pt2(0., 0.), pt2(100., 0.)]);
+        assert!(split_pixels(&pixels, pt2(50., 20.), 5.).is_none());
+    }
+
+    #[test]
+    fn split_ends_runs_at_the_rubber() {
+        let line = pixels(&[pt2(0., 0.), pt2(10., 0.), pt2(20., 0.)]);
+        let runs = split_pixels(&line, pt2(20., 0.), 5.).unwrap();
+        assert_eq!(
+            points(&runs[0]),
+            vec![pt2(0., 0.), pt2(10., 0.), pt2(15., 0.)]
+        );
+        let dot = pixels(&[pt2(0., 0.)]);
+        assert_eq!(split_pixels(&dot, pt2(0., 0.), 5.).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn drag_box_spans_from_the_corner() {
+        let anchor = pt2(0., 0.);
+        assert_eq!(
+            drag_box(anchor, pt2(10., -4.), false, false),
+            (pt2(5., -2.), vec2(10., 4.))
+        );
+        // dragging up and to the left keeps the size positive
+        assert_eq!(
+            drag_box(anchor, pt2(-10., 6.), false, false),
+            (pt2(-5., 3.), vec2(10., 6.))
+        );
+    }
+
+    #[test]
+    fn drag_box_grows_around_the_center() {
+        assert_eq!(
+            drag_box(pt2(1., 1.), pt2(4., -1.), true, false),
+            (pt2(1., 1.), vec2(6., 4.))
+        );
+        assert_eq!(
+            drag_box(pt2(1., 1.), pt2(-2., 3.), true, true),
+            (pt2(1., 1.), vec2(6., 6.))
+        );
+    }
+
+    #[test]
+    fn drag_box_squares_toward_the_drag() {
+        let anchor = pt2(0., 0.);
+        assert_eq!(
+            drag_box(anchor, pt2(10., -4.), false, true),
+            (pt2(5., -5.), vec2(10., 10.))
+        );
+        assert_eq!(
+            drag_box(anchor, pt2(-3., 8.), false, true),
+            (pt2(-4., 4.), vec2(8., 8.))
+        );
+    }
+
+    // ellipses used to take the square root of dx² - dy² as their radius,
+    // which is NaN for drags more vertical than wide
+    #[test]
+    fn drag_box_handles_vertical_drags() {
+        let anchor = pt2(0., 0.);
+        for p in [pt2(1., 10.), pt2(0., 10.), pt2(1e-7, -10.), anchor] {
+            for (from_center, square) in [(false, false), (true, false), (false, true)] {
+                let (center, size) = drag_box(anchor, p, from_center, square);
+                assert!(center.is_finite() && size.is_finite());
+            }
+        }
+        assert_eq!(
+            drag_box(anchor, pt2(1., 10.), false, false),
+            (pt2(0.5, 5.), vec2(1., 10.))
+        );
+        assert_eq!(
+            drag_box(anchor, pt2(1e-7, 10.), false, true),
+            (pt2(5., 5.), vec2(10., 10.))
+        );
+        assert_eq!(
+            drag_box(anchor, pt2(0., -10.), false, true),
+            (pt2(5., -5.), vec2(10., 10.))
+        );
     }
 }
@@ -0,0 +1,97 @@
+use super::{
+    blend::Blend,
+    document::{encode_png, ElementDoc},
+    elements::{Element, Elements},
+    picture::{tint, Picture, TextureCache},
+};
+use nannou::{
+    image::{DynamicImage, GrayImage},
+    prelude::*,
+};
+use std::{any::Any, error::Error, sync::Arc};
+
+// area painted by the Fill tool, kept as a coverage mask so it can take
+// another color later
+#[derive(Clone)]
+pub struct Fill {
+    mask: Arc<GrayImage>,
+    color: Hsva,
+    // the mask in its color, also where it sits on the canvas
+    picture: Picture,
+}
+
+impl Fill {
+    pub fn new(mask: GrayImage, color: Hsva, center: Vec2, wh: Vec2) -> Self {
+        let picture = Picture::new(DynamicImage::ImageRgba8(tint(&mask, color)), center, wh);
+        Self {
+            mask: Arc::new(mask),
+            color,
+            picture,
+        }
+    }
+
+    pub fn get_picture(&self) -> &Picture {
+        &self.picture
+    }
+
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.picture.set_rotation(rotation)
+    }
+}
+
+impl Element for Fill {
+    fn draw_elem(&self, draw: &Draw, app: &App, textures: &TextureCache, alpha: f32) {
+        self.picture.draw_elem(draw, app, textures, alpha);
+    }
+    fn get_color(&self) -> Hsva {
+        self.color
+    }
+    fn set_color(&mut self, color: Hsva) {
+        self.color = color;
+        self.picture
+            .set_image(DynamicImage::ImageRgba8(tint(&self.mask, color)));
+    }
+    fn get_blend(&self) -> Blend {
+        self.picture.get_blend()
+    }
+    fn set_blend(&mut self, blend: Blend) {
+        self.picture.set_blend(blend);
+    }
+    fn bounds(&self) -> (Vec2, Vec2) {
+        self.picture.bounds()
+    }
+    fn hit(&self, p: Vec2, r: f32) -> bool {
+        self.picture.hit(p, r)
+    }
+    fn translate(&mut self, delta: Vec2) {
+        self.picture.translate(delta);
+    }
+    fn transform(&mut self, pivot: Vec2, scale: f32, angle: f32) {
+        self.picture.transform(pivot, scale, angle);
+    }
+    // the rubber leaves fills alone, undo or Clear takes them away
+    fn erase(&self, _mouse_pos: Vec2, _r: f32) -> Option<Vec<Elements>> {
+        None
+    }
+    fn clone_box(&self) -> Elements {
+        Box::new(self.clone())
+    }
+    fn to_doc(&self) -> Result<ElementDoc, Box<dyn Error>> {
+        let center = self.picture.get_center();
+        let wh = self.picture.get_wh();
+        Ok(ElementDoc::Fill {
+            mask: encode_png(&DynamicImage::ImageLuma8((*self.mask).clone()))?,
+            center: [center.x, center.y],
+            wh: [wh.x, wh.y],
+            rotation: self.picture.get_rotation(),
+            color: self.color.into(),
+            blend: self.picture.get_blend(),
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -0,0 +1,581 @@
+use super::{
+    elements::Elements,
+    layers::{Layer, LayerProps},
+};
+use nannou::prelude::*;
+use std::collections::BTreeMap;
+
+// elements at `index` of a layer swapped for others, the index is counted
+// before the change
+#[derive(Clone)]
+pub struct Splice {
+    pub layer: usize,
+    pub index: usize,
+    pub before: Vec<Elements>,
+    pub after: Vec<Elements>,
+}
+
+pub enum Command {
+    Add {
+        layer: usize,
+        index: usize,
+        element: Elements,
+    },
+    // the elements the rubber cut, sorted by layer and index
+    Erase {
+        splices: Vec<Splice>,
+    },
+    // elements changed in place, also sorted by layer and index
+    Replace {
+        splices: Vec<Splice>,
+    },
+    // layer, index and the element as it was before
+    Recolor {
+        items: Vec<(usize, usize, Elements)>,
+        after: Hsva,
+    },
+    Move {
+        items: Vec<(usize, usize)>,
+        delta: Vec2,
+    },
+    Clear {
+        layer: usize,
+        before: Vec<Elements>,
+    },
+    AddLayer {
+        index: usize,
+        layer: Layer,
+    },
+    RemoveLayer {
+        index: usize,
+        layer: Layer,
+    },
+    // the layer at `index` and the one above trade places
+    SwapLayers {
+        index: usize,
+    },
+    // the layer at `index` goes onto the one below, its `count` elements
+    // end up last there
+    MergeDown {
+        index: usize,
+        upper: LayerProps,
+        count: usize,
+    },
+    // name, visibility, opacity and lock from the Layers window
+    EditLayer {
+        index: usize,
+        before: LayerProps,
+        after: LayerProps,
+    },
+    // commands undone and redone as one step
+    Batch(Vec<Command>),
+}
+
+impl Command {
+    fn redo(&self, layers: &mut Vec<Layer>) {
+        match self {
+            Command::Add {
+                layer,
+                index,
+                element,
+            } => layers[*layer].elements.insert(*index, element.clone()),
+            Command::Erase { splices } | Command::Replace { splices } => {
+                // from the back, the indices before each splice stay valid
+                for splice in splices.iter().rev() {
+                    let range = splice.index..splice.index + splice.before.len();
+                    layers[splice.layer]
+                        .elements
+                        .splice(range, splice.after.iter().cloned());
+                }
+            }
+            Command::Recolor { items, after } => {
+                for &(l, i, _) in items {
+                    layers[l].elements[i].set_color(*after);
+                }
+            }
+            Command::Move { items, delta } => {
+                for &(l, i) in items {
+                    layers[l].elements[i].translate(*delta);
+                }
+            }
+            Command::Clear { layer, .. } => layers[*layer].elements.clear(),
+            Command::AddLayer { index, layer } => layers.insert(*index, layer.clone()),
+            Command::RemoveLayer { index, .. } => {
+                layers.remove(*index);
+            }
+            Command::SwapLayers { index } => layers.swap(*index, *index + 1),
+            Command::MergeDown { index, .. } => {
+                let upper = layers.remove(*index);
+                layers[*index - 1].elements.extend(upper.elements);
+            }
+            Command::EditLayer { index, after, .. } => layers[*index].set_props(after),
+            Command::Batch(commands) => commands.iter().for_each(|c| c.redo(layers)),
+        }
+    }
+
+    fn undo(&self, layers: &mut Vec<Layer>) {
+        match self {
+            Command::Add { layer, index, .. } => {
+                layers[*layer].elements.remove(*index);
+            }
+            Command::Erase { splices } | Command::Replace { splices } => {
+                for splice in splices.iter() {
+                    let range = splice.index..splice.index + splice.after.len();
+                    layers[splice.layer]
+                        .elements
+                        .splice(range, splice.before.iter().cloned());
+                }
+            }
+            Command::Recolor { items, .. } => {
+                for (l, i, before) in items {
+                    layers[*l].elements[*i] = before.clone();
+                }
+            }
+            Command::Move { items, delta } => {
+                for &(l, i) in items {
+                    layers[l].elements[i].translate(-*delta);
+                }
+            }
+            Command::Clear { layer, before } => layers[*layer].elements = before.clone(),
+            Command::AddLayer { index, .. } => {
+                layers.remove(*index);
+            }
+            Command::RemoveLayer { index, layer } => layers.insert(*index, layer.clone()),
+            Command::SwapLayers { index } => layers.swap(*index, *index + 1),
+            Command::MergeDown {
+                index,
+                upper,
+                count,
+            } => {
+                let lower = &mut layers[*index - 1].elements;
+                let mut layer = Layer::new(String::new());
+                layer.elements = lower.split_off(lower.len() - count);
+                layer.set_props(upper);
+                layers.insert(*index, layer);
+            }
+            Command::EditLayer { index, before, .. } => layers[*index].set_props(before),
+            Command::Batch(commands) => commands.iter().rev().for_each(|c| c.undo(layers)),
+        }
+    }
+}
+
+// follows a rubber stroke, for every element the index it had when the
+// stroke started and the originals of those it cut
+pub struct Erasure {
+    origins: Vec<Vec<usize>>,
+    originals: BTreeMap<(usize, usize), Elements>,
+}
+
+impl Erasure {
+    pub fn new(layers: &[Layer]) -> Self {
+        Self {
+            origins: layers
+                .iter()
+                .map(|layer| (0..layer.elements.len()).collect())
+                .collect(),
+            originals: BTreeMap::new(),
+        }
+    }
+
+    // `pieces` holds what each element of each layer is cut into, call it
+    // before the pieces replace the elements
+    pub fn cut(&mut self, layers: &[Layer], pieces: &[Vec<Option<Vec<Elements>>>]) {
+        for (l, (layer, pieces)) in layers.iter().zip(pieces).enumerate() {
+            let origins = std::mem::take(&mut self.origins[l]);
+            for ((origin, elem), pieces) in origins.into_iter().zip(&layer.elements).zip(pieces) {
+                match pieces {
+                    Some(pieces) => {
+                        self.originals
+                            .entry((l, origin))
+                            .or_insert_with(|| elem.clone());
+                        self.origins[l].extend(std::iter::repeat_n(origin, pieces.len()));
+                    }
+                    None => self.origins[l].push(origin),
+                }
+            }
+        }
+    }
+
+    pub fn finish(self, layers: &[Layer]) -> Option<Command> {
+        if self.originals.is_empty() {
+            return None;
+        }
+        let origins = self.origins;
+        let splices = self
+            .originals
+            .into_iter()
+            .map(|((layer, index), before)| Splice {
+                layer,
+                index,
+                before: vec![before],
+                after: layers[layer]
+                    .elements
+                    .iter()
+                    .zip(&origins[layer])
+                    .filter(|(_, &origin)| origin == index)
+                    .map(|(elem, _)| elem.clone())
+                    .collect(),
+            })
+            .collect();
+        Some(Command::Erase { splices })
+    }
+}
+
+pub struct History {
+    undo: Vec<Command>,
+    redo: Vec<Command>,
+    depth: usize,
+    // counts every change pushed, undone or redone
+    revision: u64,
+}
+
+impl History {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            depth,
+            revision: 0,
+        }
+    }
+
+    // runs the command on the elements and records it
+    pub fn apply(&mut self, command: Command, layers: &mut Vec<Layer>) {
+        command.redo(layers);
+        self.push(command);
+    }
+
+    pub fn clear_elements(&mut self, layers: &mut Vec<Layer>, layer: usize) {
+        if layers[layer].locked || layers[layer].elements.is_empty() {
+            return;
+        }
+        let before = layers[layer].elements.clone();
+        self.apply(Command::Clear { layer, before }, layers);
+    }
+
+    // records a command whose effect is already on the elements
+    pub fn push(&mut self, command: Command) {
+        self.redo.clear();
+        self.undo.push(command);
+        self.revision += 1;
+        self.trim();
+    }
+
+    pub fn undo(&mut self, layers: &mut Vec<Layer>) -> bool {
+        match self.undo.pop() {
+            Some(command) => {
+                command.undo(layers);
+                self.redo.push(command);
+                self.revision += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self, layers: &mut Vec<Layer>) -> bool {
+        match self.redo.pop() {
+            Some(command) => {
+                command.redo(layers);
+                self.undo.push(command);
+                self.revision += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn get_revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn get_depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        if self.undo.len() > self.depth {
+            let excess = self.undo.len() - self.depth;
+            self.undo.drain(..excess);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::elements::Line;
+
+    fn red() -> Hsva {
+        hsva(0., 1., 1., 1.)
+    }
+
+    fn line(x: f32) -> Elements {
+        let pixels = vec![(pt2(x, 0.), red()), (pt2(x + 10., 0.), red())];
+        Box::new(Line::new_param(pixels, 1., red()))
+    }
+
+    fn canvas() -> Vec<Layer> {
+        vec![Layer::new(String::from("Layer 1"))]
+    }
+
+    // first point of every element on the first layer
+    fn lefts(layers: &[Layer]) -> Vec<f32> {
+        layers[0]
+            .elements
+            .iter()
+            .map(|elem| elem.downcast_ref::<Line>().unwrap().get_line()[0].0.x)
+            .collect()
+    }
+
+    fn add(history: &mut History, layers: &mut Vec<Layer>, x: f32) {
+        let index = layers[0].elements.len();
+        let command = Command::Add {
+            layer: 0,
+            index,
+            element: line(x),
+        };
+        history.apply(command, layers);
+    }
+
+    #[test]
+    fn add_undo_redo() {
+        let (mut history, mut layers) = (History::new(10), canvas());
+        add(&mut history, &mut layers, 0.);
+        add(&mut history, &mut layers, 100.);
+        assert_eq!(lefts(&layers), vec![0., 100.]);
+        assert!(history.undo(&mut layers));
+        assert_eq!(lefts(&layers), vec![0.]);
+        assert!(history.redo(&mut layers));
+        assert_eq!(lefts(&layers), vec![0., 100.]);
+        assert!(!history.redo(&mut layers));
+    }
+
+    // cuts the elements of the first layer like the rubber does
+    fn cut(erasure: &mut Erasure, layers: &mut [Layer], pieces: Vec<Option<Vec<Elements>>>) {
+        let pieces = vec![pieces];
+        erasure.cut(layers, &pieces);
+        let elements = std::mem::take(&mut layers[0].elements);
+        for (elem, pieces) in elements.into_iter().zip(pieces.concat()) {
+            match pieces {
+                Some(pieces) => layers[0].elements.extend(pieces),
+                None => layers[0].elements.push(elem),
+            }
+        }
+    }
+
+    #[test]
+    fn erase_keeps_only_the_cut_elements() {
+        let (mut history, mut layers) = (History::new(10), canvas());
+        for x in [0., 100., 200.] {
+            add(&mut history, &mut layers, x);
+        }
+        let mut erasure = Erasure::new(&layers);
+        cut(
+            &mut erasure,
+            &mut layers,
+            vec![None, Some(vec![line(100.), line(150.)]), Some(vec![])],
+        );
+        // the piece cut again still belongs to the second element
+        cut(&mut erasure, &mut layers, vec![None, Some(vec![]), None]);
+        assert_eq!(lefts(&layers), vec![0., 150.]);
+        let command = erasure.finish(&layers).unwrap();
+        match &command {
+            Command::Erase { splices } => {
+                let places: Vec<_> = splices.iter().map(|s| (s.index, s.after.len())).collect();
+                assert_eq!(places, vec![(1, 1), (2, 0)]);
+            }
+            _ => panic!("not an erase"),
+        }
+        history.push(command);
+        history.undo(&mut layers);
+        assert_eq!(lefts(&layers), vec![0., 100., 200.]);
+        history.redo(&mut layers);
+        assert_eq!(lefts(&layers), vec![0., 150.]);
+        assert!(Erasure::new(&layers).finish(&layers).is_none());
+    }
+
+    fn colors(layers: &[Layer]) -> Vec<Hsva> {
+        let line = layers[0].elements[0].downcast_ref::<Line>().unwrap();
+        line.get_line().iter().map(|&(_, c)| c).collect()
+    }
+
+    #[test]
+    fn recolor_and_move() {
+        let (mut history, mut layers) = (History::new(10), canvas());
+        add(&mut history, &mut layers, 0.);
+        let blue = hsva(240. / 360., 1., 1., 1.);
+        // a line with a color per point gets all of them back
+        let pixels = vec![(pt2(0., 0.), red()), (pt2(10., 0.), blue)];
+        layers[0].elements[0] = Box::new(Line::new_param(pixels, 1., red()));
+        let recolor = Command::Recolor {
+            items: vec![(0, 0, layers[0].elements[0].clone())],
+            after: blue,
+        };
+        history.apply(recolor, &mut layers);
+        assert_eq!(colors(&layers), vec![blue; 2]);
+        history.undo(&mut layers);
+        assert_eq!(colors(&layers), vec![red(), blue]);
+
+        add(&mut history, &mut layers, 100.);
+        let step = Command::Move {
+            items: vec![(0, 0), (0, 1)],
+            delta: vec2(5., 0.),
+        };
+        history.apply(step, &mut layers);
+        assert_eq!(lefts(&layers), vec![5., 105.]);
+        history.undo(&mut layers);
+        assert_eq!(lefts(&layers), vec![0., 100.]);
+        history.redo(&mut layers);
+        assert_eq!(lefts(&layers), vec![5., 105.]);
+    }
+
+    fn names(layers: &[Layer]) -> Vec<&str> {
+        layers.iter().map(|layer| layer.name.as_str()).collect()
+    }
+
+    #[test]
+    fn layer_commands_undo() {
+        let (mut history, mut layers) = (History::new(10), canvas());
+        add(&mut history, &mut layers, 0.);
+        let mut layer = Layer::new(String::from("Layer 2"));
+        layer.elements.push(line(100.));
+        history.apply(Command::AddLayer { index: 1, layer }, &mut layers);
+        history.apply(Command::SwapLayers { index: 0 }, &mut layers);
+        assert_eq!(names(&layers), vec!["Layer 2", "Layer 1"]);
+
+        let mut after = layers[1].get_props();
+        after.opacity = 0.5;
+        let edit = Command::EditLayer {
+            index: 1,
+            before: layers[1].get_props(),
+            after,
+        };
+        history.apply(edit, &mut layers);
+        let merge = Command::MergeDown {
+            index: 1,
+            upper: layers[1].get_props(),
+            count: 1,
+        };
+        history.apply(merge, &mut layers);
+        assert_eq!(names(&layers), vec!["Layer 2"]);
+        assert_eq!(lefts(&layers), vec![100., 0.]);
+        history.undo(&mut layers);
+        assert_eq!(names(&layers), vec!["Layer 2", "Layer 1"]);
+        assert_eq!(lefts(&layers), vec![100.]);
+        assert_eq!(layers[1].opacity, 0.5);
+        history.undo(&mut layers);
+        assert_eq!(layers[1].opacity, 1.);
+
+        let layer = layers.remove(0);
+        history.push(Command::RemoveLayer { index: 0, layer });
+        history.undo(&mut layers);
+        assert_eq!(names(&layers), vec!["Layer 2", "Layer 1"]);
+        history.undo(&mut layers);
+        history.undo(&mut layers);
+        assert_eq!(names(&layers), vec!["Layer 1"]);
+        history.redo(&mut layers);
+        assert_eq!(lefts(&layers), vec![0.]);
+        assert_eq!(layers[1].elements.len(), 1);
+    }
+
+    #[test]
+    fn a_batch_is_one_step() {
+        let (mut history, mut layers) = (History::new(10), canvas());
+        let batch = Command::Batch(vec![
+            Command::Add {
+                layer: 0,
+                index: 0,
+                element: line(0.),
+            },
+            Command::AddLayer {
+                index: 1,
+                layer: Layer::new(String::from("Layer 2")),
+            },
+            Command::Add {
+                layer: 1,
+                index: 0,
+                element: line(100.),
+            },
+        ]);
+        history.apply(batch, &mut layers);
+        assert_eq!(names(&layers), vec!["Layer 1", "Layer 2"]);
+        assert_eq!(layers[1].elements.len(), 1);
+        assert!(history.undo(&mut layers));
+        assert!(!history.can_undo());
+        assert_eq!(names(&layers), vec!["Layer 1"]);
+        assert!(layers[0].elements.is_empty());
+    }
+
+    #[test]
+    fn clear_restores_the_layer() {
+        let (mut history, mut layers) = (History::new(10), canvas());
+        add(&mut history, &mut layers, 0.);
+        add(&mut history, &mut layers, 100.);
+        history.clear_elements(&mut layers, 0);
+        assert!(layers[0].elements.is_empty());
+        history.undo(&mut layers);
+        assert_eq!(lefts(&layers), vec![0., 100.]);
+        layers[0].locked = true;
+        history.clear_elements(&mut layers, 0);
+        assert_eq!(layers[0].elements.len(), 2);
+    }
+
+    #[test]
+    fn every_change_bumps_the_revision() {
+        let (mut history, mut layers) = (History::new(10), canvas());
+        add(&mut history, &mut layers, 0.);
+        assert_eq!(history.get_revision(), 1);
+        history.undo(&mut layers);
+        history.redo(&mut layers);
+        assert_eq!(history.get_revision(), 3);
+        // nothing left to redo, nothing changed
+        history.redo(&mut layers);
+        assert_eq!(history.get_revision(), 3);
+    }
+
+    #[test]
+    fn new_commands_drop_the_redo_stack() {
+        let (mut history, mut layers) = (History::new(10), canvas());
+        add(&mut history, &mut layers, 0.);
+        history.undo(&mut layers);
+        add(&mut history, &mut layers, 100.);
+        assert!(!history.can_redo());
+        assert_eq!(lefts(&layers), vec![100.]);
+    }
+
+    #[test]
+    fn depth_drops_the_oldest_commands() {
+        let (mut history, mut layers) = (History::new(2), canvas());
+        for x in [0., 100., 200.] {
+            add(&mut history, &mut layers, x);
+        }
+        assert!(history.undo(&mut layers));
+        assert!(history.undo(&mut layers));
+        assert!(!history.undo(&mut layers));
+        // the first add fell off the history and stays
+        assert_eq!(lefts(&layers), vec![0.]);
+
+        let (mut history, mut layers) = (History::new(10), canvas());
+        for x in [0., 100., 200.] {
+            add(&mut history, &mut layers, x);
+        }
+        history.set_depth(1);
+        assert!(history.undo(&mut layers));
+        assert!(!history.can_undo());
+        assert_eq!(lefts(&layers), vec![0., 100.]);
+    }
+}
@@ -0,0 +1,70 @@
+use super::{elements::Elements, picture::TextureCache};
+use nannou::prelude::*;
+
+#[derive(Clone)]
+pub struct Layer {
+    pub name: String,
+    pub elements: Vec<Elements>,
+    pub visible: bool,
+    pub opacity: f32,
+    pub locked: bool,
+}
+
+impl Layer {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            elements: Vec::new(),
+            visible: true,
+            opacity: 1.,
+            locked: false,
+        }
+    }
+
+    pub fn draw(&self, draw: &Draw, app: &App, textures: &TextureCache) {
+        if !self.visible {
+            return;
+        }
+        for elem in self.elements.iter() {
+            elem.draw_elem(draw, app, textures, self.opacity);
+        }
+    }
+
+    // visible and unlocked layers are the only ones tools can change
+    pub fn editable(&self) -> bool {
+        self.visible && !self.locked
+    }
+
+    pub fn get_props(&self) -> LayerProps {
+        LayerProps {
+            name: self.name.clone(),
+            visible: self.visible,
+            opacity: self.opacity,
+            locked: self.locked,
+        }
+    }
+
+    pub fn set_props(&mut self, props: &LayerProps) {
+        self.name = props.name.clone();
+        self.visible = props.visible;
+        self.opacity = props.opacity;
+        self.locked = props.locked;
+    }
+}
+
+// what the Layers window edits on a layer besides its elements
+#[derive(Clone, PartialEq, Debug)]
+pub struct LayerProps {
+    pub name: String,
+    pub visible: bool,
+    pub opacity: f32,
+    pub locked: bool,
+}
+
+pub enum LayerAction {
+    Add,
+    Delete(usize),
+    Raise(usize),
+    Lower(usize),
+    MergeDown(usize),
+}
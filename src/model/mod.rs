@@ -1,21 +1,93 @@
-use nannou::{prelude::*, wgpu::Texture};
+use nannou::{image::DynamicImage, prelude::*, wgpu::Texture};
 use nannou_egui::Egui;
+use std::{error::Error, path::Path};
+pub(crate) mod blend;
+pub(crate) mod board;
+pub(crate) mod brush;
+pub(crate) mod camera;
+pub(crate) mod document;
 pub(crate) mod elements;
-use elements::{Elements, Ellipse, Forms, Line, Pencil, Rectangle, Rectangle_Custom, Tool};
+pub(crate) mod fill;
+pub(crate) mod history;
+pub(crate) mod layers;
+pub(crate) mod picture;
+pub(crate) mod selection;
+pub(crate) mod shapes;
+pub(crate) mod stroke;
+pub(crate) mod style;
+use crate::{
+    canvas::{raster, svg},
+    diffusion::{
+        add_diffusion_segment, add_layer, add_outpaint_segment, add_sketch_segment,
+        create_new_board, erase_from_canvas,
+        jobs::{JobTarget, Jobs},
+        text_2_image, DiffusionConfig,
+    },
+};
+use blend::Blend;
+use board::{board_path, next_board_id, Board, BoardAction};
+use brush::{load_brushes, Brush, BRUSH_DIR};
+use camera::Camera;
+use document::{Document, SettingsDoc};
+use elements::{drag_box, Element, Elements, Ellipse, Line, Rectangle, RectangleCustom, Tool};
+use fill::Fill;
+use history::{Command, Erasure, History, Splice};
+use layers::{Layer, LayerAction, LayerProps};
+use picture::{CanvasCache, CanvasKey, Picture, TextureCache};
+use selection::Selection;
+use shapes::{snap_angle, Arrow, ArrowHead, Polygon, Segment, Star};
+use stroke::Smoothing;
+use style::ShapeStyle;
 
 #[derive(Clone)]
 pub struct Settings {
-    pub color: Hsv,
+    pub color: Hsva,
     pub weight: f32,
     shapes: bool,
+    // cleanup of pencil strokes, the tolerance is in screen points
+    pub smoothing: Smoothing,
+    pub tolerance: f32,
+    pub passes: u32,
+    // pencil strokes thin out with speed, or follow the pen pressure
+    pub variable_width: bool,
+    pub brush: Brush,
+    // how far colors may be from the clicked one and still get filled, 0 to 1
+    pub fill_tolerance: f32,
+    pub fill_antialias: bool,
+    // corners of the Polygon tool and tips of the Star tool
+    pub sides: u32,
+    pub star_points: u32,
+    // radius of the inner corners of stars as a share of the tips
+    pub star_ratio: f32,
+    pub arrow_head: ArrowHead,
+    // style new shapes get, the width is in screen points
+    pub style: ShapeStyle,
+    // how new elements of every tool mix with what is under them
+    pub blend: Blend,
 }
 
 impl Settings {
-    pub fn new(color: Hsv, weight: f32, shapes: bool) -> Self {
+    pub fn new(color: Hsva, weight: f32, shapes: bool) -> Self {
         Self {
             color,
             weight,
             shapes,
+            smoothing: Smoothing::Off,
+            tolerance: 1.5,
+            passes: 2,
+            variable_width: true,
+            brush: Brush::default(),
+            fill_tolerance: 0.1,
+            fill_antialias: true,
+            sides: 5,
+            star_points: 5,
+            star_ratio: 0.5,
+            arrow_head: ArrowHead::Filled,
+            style: ShapeStyle {
+                stroke: color,
+                ..ShapeStyle::default()
+            },
+            blend: Blend::Normal,
         }
     }
 
@@ -27,7 +99,7 @@ impl Settings {
         self.weight
     }
 
-    pub fn get_color(&self) -> Hsv {
+    pub fn get_color(&self) -> Hsva {
         self.color
     }
 
@@ -36,36 +108,97 @@ impl Settings {
     }
 }
 
+pub struct Export {
+    pub path: String,
+    pub svg_path: String,
+    pub width: f32,
+    pub height: f32,
+    pub scale: f32,
+}
+
+impl Default for Export {
+    fn default() -> Self {
+        Self {
+            path: String::from("canvas.png"),
+            svg_path: String::from("canvas.svg"),
+            width: 1350.,
+            height: 850.,
+            scale: 1.,
+        }
+    }
+}
+
 pub struct Model {
     pub egui: Egui,
     pub settings: Settings,
     pub line: Line,
     pub ellipse: Ellipse,
     pub rect: Rectangle,
-    pub rect_custom: Rectangle_Custom,
-    pub elements: Vec<Elements>,
+    pub rect_custom: RectangleCustom,
+    pub segment: Segment,
+    pub polygon: Polygon,
+    pub star: Star,
+    pub arrow: Arrow,
+    pub layers: Vec<Layer>,
+    pub active_layer: usize,
     pub tool: Tool,
     pub drawing: bool,
+    // world position the left button went down at
+    pub drag_start: Vec2,
     pub texture: Texture,
-    pub window_id: WindowId,
-    pub prompt: String
+    pub background: DynamicImage,
+    pub prompt: String,
+    pub history: History,
+    pub project_path: String,
+    pub export: Export,
+    pub diffusion: DiffusionConfig,
+    pub jobs: Jobs,
+    pub textures: TextureCache,
+    // strokes marking what inpainting should redo
+    pub mask: Vec<Line>,
+    pub selection: Selection,
+    pub camera: Camera,
+    // pen pressure from 0 to 1 while the tablet reports one
+    pub pressure: Option<f32>,
+    // the last file action that failed, shown until dismissed
+    pub status: Option<String>,
+    // presets the brush picker offers
+    pub brushes: Vec<Brush>,
+    // the other boards in tab order, the active board is the one in the
+    // fields above
+    pub boards: Vec<Board>,
+    // tab of the active board
+    pub active_board: usize,
+    pub board_name: String,
+    board_id: u64,
+    // the rubber stroke in progress
+    erasure: Option<Erasure>,
+    // layer the Layers window is editing and its props before
+    layer_edit: Option<(usize, LayerProps)>,
+    // the rendered canvas shown while it has overlay elements
+    canvas: CanvasCache,
 }
 
 impl Model {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         egui: Egui,
         settings: Settings,
         line: Line,
         ellipse: Ellipse,
         rect: Rectangle,
-        rect_custom: Rectangle_Custom,
-        elements: Vec<Elements>,
+        rect_custom: RectangleCustom,
+        layers: Vec<Layer>,
         tool: Tool,
         drawing: bool,
         texture: Texture,
-        window_id: WindowId,
-        prompt: String
+        background: DynamicImage,
+        prompt: String,
+        history: History,
     ) -> Self {
+        let board_id = next_board_id();
+        let mut jobs = Jobs::new();
+        jobs.set_board(board_id);
         Model {
             egui,
             settings,
@@ -73,50 +206,170 @@ impl Model {
             ellipse,
             rect,
             rect_custom,
-            elements,
+            segment: Segment::default(),
+            polygon: Polygon::default(),
+            star: Star::default(),
+            arrow: Arrow::default(),
+            layers,
+            active_layer: 0,
             tool,
             drawing,
+            drag_start: Vec2::ZERO,
             texture,
-            window_id,
-            prompt
+            background,
+            prompt,
+            history,
+            project_path: String::from("canvas.json"),
+            export: Export::default(),
+            diffusion: DiffusionConfig::default(),
+            jobs,
+            textures: TextureCache::default(),
+            mask: Vec::new(),
+            selection: Selection::default(),
+            camera: Camera::default(),
+            pressure: None,
+            status: None,
+            brushes: Brush::presets(),
+            boards: Vec::new(),
+            active_board: 0,
+            board_name: String::from("Board 1"),
+            board_id,
+            erasure: None,
+            layer_edit: None,
+            canvas: CanvasCache::default(),
         }
     }
 
-    pub fn display(&self, draw: &Draw, _app: &App) {
-        match self.tool {
-            Tool::Pencil => {
-                draw.polyline()
-                    .start_cap_round()
-                    .caps_round()
-                    .end_cap_round()
-                    .join_round()
-                    .stroke_weight(self.get_line().get_weight())
-                    .points_colored(self.get_line().get_pixels());
-            }
-            Tool::Ellipse => {
-                draw.ellipse()
-                    .xy(self.ellipse.get_center())
-                    .color(self.get_settings().get_color())
-                    .radius(self.ellipse.get_radius());
-            }
-            Tool::Rect => {
-                draw.rect()
-                    .xy(self.rect.get_center())
-                    .width(self.rect.get_wh().x)
-                    .height(self.rect.get_wh().y)
-                    .color(self.rect.get_color())
-                    .no_fill()
-                    .stroke_color(self.rect.get_color())
-                    .stroke_weight(self.settings.get_weight());
+    // the world rect the window shows
+    fn view_bounds(&self, app: &App) -> geom::Rect {
+        let window = app.window_rect();
+        geom::Rect::from_xy_wh(
+            self.camera.to_world(Vec2::ZERO),
+            vec2(window.w(), window.h()) / self.camera.zoom,
+        )
+    }
+
+    // the background and the layers, the window has no overlay blend so a
+    // canvas with overlay elements is drawn the way exports render it
+    pub fn draw_canvas(&self, draw: &Draw, app: &App) {
+        let overlay = self
+            .layers
+            .iter()
+            .filter(|layer| layer.visible)
+            .flat_map(|layer| layer.elements.iter())
+            .any(|elem| elem.get_blend() == Blend::Overlay);
+        if !overlay {
+            self.canvas.clear();
+            draw.texture(&self.texture)
+                .wh(vec2(raster::BACKGROUND_SIZE, raster::BACKGROUND_SIZE))
+                .xy(pt2(0., 0.));
+            for layer in self.layers.iter() {
+                layer.draw(draw, app, &self.textures);
             }
-            Tool::Rect_Custom => {
+            return;
+        }
+        let bounds = self.view_bounds(app);
+        // rubber strokes and selection drags change elements before they
+        // reach history
+        let settled = self.erasure.is_none() && !self.selection.is_changing();
+        let key = settled.then(|| CanvasKey {
+            board: self.board_id,
+            revision: self.history.get_revision(),
+            bounds,
+            layers: self.layers.iter().map(Layer::get_props).collect(),
+        });
+        let render = || {
+            raster::render(
+                &self.layers,
+                Some(&self.background),
+                bounds,
+                self.camera.zoom,
+            )
+        };
+        self.canvas.with_texture(app, key, render, |texture| {
+            draw.texture(texture).xy(bounds.xy()).wh(bounds.wh());
+        });
+    }
+
+    pub fn display(&self, draw: &Draw, app: &App) {
+        let current = (self.tool == Tool::Mask).then_some(&self.line);
+        for stroke in self.mask.iter().chain(current) {
+            draw.polyline()
+                .caps_round()
+                .join_round()
+                .stroke_weight(stroke.get_weight())
+                .color(rgba(1., 0., 0., 0.4))
+                .points(stroke.get_line().iter().map(|(p, _)| *p));
+        }
+        match self.tool {
+            Tool::Pencil => self.line.draw_elem(draw, app, &self.textures, 1.),
+            Tool::Ellipse => self.ellipse.draw_elem(draw, app, &self.textures, 1.),
+            Tool::Rect => self.rect.draw_elem(draw, app, &self.textures, 1.),
+            Tool::RectCustom => {
                 draw.rect()
                     .xy(self.rect_custom.get_center())
                     .width(self.rect_custom.get_wh().x)
                     .height(self.rect_custom.get_wh().y)
                     .color(self.rect_custom.get_color());
             }
-            Tool::Rubber => {}
+            Tool::Rubber => {
+                draw.ellipse()
+                    .xy(self.get_mouse(app))
+                    .radius(self.get_brush_weight() / 2.)
+                    .no_fill()
+                    .stroke_color(WHITE)
+                    .stroke_weight(1. / self.camera.zoom);
+            }
+            Tool::Segment => self.segment.draw_elem(draw, app, &self.textures, 1.),
+            Tool::Polygon => self.polygon.draw_elem(draw, app, &self.textures, 1.),
+            Tool::Star => self.star.draw_elem(draw, app, &self.textures, 1.),
+            Tool::Arrow => self.arrow.draw_elem(draw, app, &self.textures, 1.),
+            Tool::Mask | Tool::Fill => {}
+            Tool::Select => self.selection.draw(
+                draw,
+                &self.layers,
+                self.get_mouse(app),
+                1. / self.camera.zoom,
+            ),
+        }
+    }
+
+    // the style of new shapes, its width in world units like the brush and
+    // its outline in the color pencils draw with
+    pub fn get_shape_style(&self) -> ShapeStyle {
+        let style = &self.settings.style;
+        ShapeStyle {
+            stroke: self.settings.color,
+            width: style.width / self.camera.zoom,
+            blend: self.settings.blend,
+            ..style.clone()
+        }
+    }
+
+    // mouse position in world coordinates
+    pub fn get_mouse(&self, app: &App) -> Vec2 {
+        self.camera.to_world(pt2(app.mouse.x, app.mouse.y))
+    }
+
+    // the brush size is set in screen points, strokes are stored in world
+    // units so they keep their look on screen while drawn at any zoom
+    pub fn get_brush_weight(&self) -> f32 {
+        self.settings.get_weight() / self.camera.zoom
+    }
+
+    // width of the next pencil point as a share of the weight, the pen
+    // pressure when there is one, otherwise from the mouse speed
+    fn next_width(&self, mouse_pos: Vec2) -> f32 {
+        if let Some(pressure) = self.pressure {
+            return pressure.max(stroke::MIN_WIDTH);
+        }
+        match self.line.get_line().last() {
+            Some((last, _)) => {
+                let speed = last.distance(mouse_pos) * self.camera.zoom;
+                let index = self.line.get_line().len() - 1;
+                stroke::speed_width(self.line.width_at(index), speed)
+            }
+            None => 1.,
         }
     }
 
@@ -124,6 +377,10 @@ impl Model {
         self.drawing
     }
 
+    pub fn set_drawing(&mut self, drawing: bool) {
+        self.drawing = drawing
+    }
+
     pub fn get_settings(&self) -> &Settings {
         &self.settings
     }
@@ -132,79 +389,890 @@ impl Model {
         &self.tool
     }
 
-    pub fn get_line(&self) -> &Line {
-        &self.line
+    pub fn get_mut_line(&mut self) -> &mut Line {
+        &mut self.line
     }
 
-    pub fn get_rect_line(&self) -> &Rectangle_Custom {
-        &self.rect_custom
+    pub fn set_line(&mut self) {
+        self.add_element(Box::new(self.line.clone()))
     }
 
-    pub fn get_mut_line(&mut self) -> &mut Line {
-        &mut self.line
+    // runs the stroke cleanup picked in the settings over the current line
+    pub fn smooth_line(&mut self) {
+        let tolerance = self.settings.tolerance / self.camera.zoom;
+        let variable = !self.line.get_widths().is_empty();
+        let points = stroke::process(
+            &self.line.get_points(),
+            self.settings.smoothing,
+            tolerance,
+            self.settings.passes,
+        );
+        self.line.set_points(points, variable);
     }
 
-    pub fn get_mut_rect_line(&mut self) -> &mut Rectangle_Custom {
-        &mut self.rect_custom
+    pub fn set_rect_line(&mut self) {
+        self.add_element(Box::new(self.rect_custom.clone()))
     }
 
-    pub fn set_line(&mut self) {
-        self.elements.push(Elements::L(Box::new(self.line.clone())))
+    pub fn get_active_layer(&self) -> &Layer {
+        &self.layers[self.active_layer]
     }
 
-    pub fn set_rect_line(&mut self) {
-        self.elements
-            .push(Elements::L(Box::new(self.rect_custom.clone())))
+    pub fn add_element(&mut self, element: Elements) {
+        if !self.get_active_layer().editable() {
+            return;
+        }
+        let layer = self.active_layer;
+        let index = self.layers[layer].elements.len();
+        self.history.apply(
+            Command::Add {
+                layer,
+                index,
+                element,
+            },
+            &mut self.layers,
+        );
     }
 
-    pub fn set_drawing(&mut self, drawing: bool) {
-        self.drawing = drawing
+    // gives the selected elements the current color as one undoable step
+    pub fn recolor_selection(&mut self) {
+        self.selection
+            .retain_valid(&self.layers, self.history.get_revision());
+        let items: Vec<_> = self
+            .selection
+            .get_items()
+            .iter()
+            .map(|&(l, i)| (l, i, self.layers[l].elements[i].clone()))
+            .collect();
+        if items.is_empty() {
+            return;
+        }
+        let after = self.settings.color;
+        self.history
+            .apply(Command::Recolor { items, after }, &mut self.layers);
+        self.selection.set_revision(self.history.get_revision());
+    }
+
+    pub fn move_selection(&mut self, delta: Vec2) {
+        self.selection
+            .retain_valid(&self.layers, self.history.get_revision());
+        let items = self.selection.get_items().clone();
+        if items.is_empty() {
+            return;
+        }
+        self.history
+            .apply(Command::Move { items, delta }, &mut self.layers);
+        self.selection.set_revision(self.history.get_revision());
+    }
+
+    pub fn undo(&mut self) {
+        self.end_layer_edit();
+        self.history.undo(&mut self.layers);
+    }
+
+    pub fn redo(&mut self) {
+        self.end_layer_edit();
+        self.history.redo(&mut self.layers);
+    }
+
+    pub fn apply_layer_action(&mut self, action: LayerAction) {
+        self.end_layer_edit();
+        match action {
+            LayerAction::Add => {
+                let index = add_layer(&mut self.layers, self.active_layer);
+                let layer = self.layers[index].clone();
+                self.history.push(Command::AddLayer { index, layer });
+                self.active_layer = index;
+            }
+            LayerAction::Delete(index) => {
+                if self.layers.len() == 1 {
+                    return;
+                }
+                let layer = self.layers.remove(index);
+                self.history.push(Command::RemoveLayer { index, layer });
+                // the active layer keeps its place when one below it goes
+                if index < self.active_layer {
+                    self.active_layer -= 1;
+                }
+            }
+            LayerAction::Raise(index) => {
+                if index + 1 >= self.layers.len() {
+                    return;
+                }
+                self.history
+                    .apply(Command::SwapLayers { index }, &mut self.layers);
+                self.active_layer = index + 1;
+            }
+            LayerAction::Lower(index) => {
+                if index == 0 {
+                    return;
+                }
+                let command = Command::SwapLayers { index: index - 1 };
+                self.history.apply(command, &mut self.layers);
+                self.active_layer = index - 1;
+            }
+            LayerAction::MergeDown(index) => {
+                if index == 0 {
+                    return;
+                }
+                let command = Command::MergeDown {
+                    index,
+                    upper: self.layers[index].get_props(),
+                    count: self.layers[index].elements.len(),
+                };
+                self.history.apply(command, &mut self.layers);
+                self.active_layer = index - 1;
+            }
+        }
+        self.active_layer = self.active_layer.min(self.layers.len() - 1);
+    }
+
+    // `changed` is the layer the Layers window changed this frame and how
+    // it was before, a name or opacity still being edited is recorded
+    // once the edit is left
+    pub fn edit_layer(&mut self, changed: Option<(usize, LayerProps)>, editing: bool) {
+        if let Some((index, before)) = changed {
+            if matches!(self.layer_edit, Some((l, _)) if l != index) {
+                self.end_layer_edit();
+            }
+            self.layer_edit.get_or_insert((index, before));
+        }
+        if !editing {
+            self.end_layer_edit();
+        }
+    }
+
+    fn end_layer_edit(&mut self) {
+        if let Some((index, before)) = self.layer_edit.take() {
+            let after = match self.layers.get(index) {
+                Some(layer) => layer.get_props(),
+                None => return,
+            };
+            if after != before {
+                self.history.push(Command::EditLayer {
+                    index,
+                    before,
+                    after,
+                });
+            }
+        }
+    }
+
+    // names of all boards in tab order
+    pub fn get_board_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.boards.iter().map(|b| b.name.clone()).collect();
+        names.insert(self.active_board, self.board_name.clone());
+        names
+    }
+
+    // the first of `candidates` no board is called yet
+    fn free_board_name(&self, mut candidates: impl Iterator<Item = String>) -> String {
+        let names = self.get_board_names();
+        candidates.find(|name| !names.contains(name)).unwrap()
+    }
+
+    // files the other boards save to
+    fn other_board_paths(&self) -> Vec<&str> {
+        self.boards
+            .iter()
+            .map(|b| b.project_path.as_str())
+            .collect()
+    }
+
+    // a new board saves next to the others without taking their files
+    fn new_board(&self, app: &App, name: String) -> Board {
+        let mut taken = self.other_board_paths();
+        taken.push(&self.project_path);
+        let path = board_path(&name, &taken);
+        create_new_board(app, name, path, self.history.get_depth())
+    }
+
+    // the active board was renamed, its file follows the name
+    pub fn rename_board(&mut self) {
+        self.project_path = board_path(&self.board_name, &self.other_board_paths());
+    }
+
+    pub fn apply_board_action(&mut self, app: &App, action: BoardAction) {
+        match action {
+            BoardAction::New => {
+                let name =
+                    self.free_board_name((self.boards.len() + 2..).map(|n| format!("Board {}", n)));
+                let board = self.new_board(app, name);
+                self.insert_board(board);
+            }
+            BoardAction::Duplicate => {
+                let copy = format!("{} copy", self.board_name);
+                let name = self.free_board_name(
+                    std::iter::once(copy.clone()).chain((2..).map(|n| format!("{} {}", copy, n))),
+                );
+                let board = Board {
+                    layers: self.layers.clone(),
+                    active_layer: self.active_layer,
+                    texture: Texture::from_image(app, &self.background),
+                    background: self.background.clone(),
+                    prompt: self.prompt.clone(),
+                    camera: self.camera.clone(),
+                    mask: self.mask.clone(),
+                    ..self.new_board(app, name)
+                };
+                self.insert_board(board);
+            }
+            BoardAction::Select(tab) => self.switch_board(tab),
+            BoardAction::Close(tab) => {
+                if self.boards.is_empty() || tab > self.boards.len() {
+                    return;
+                }
+                if tab == self.active_board {
+                    // a neighbour takes over before the board goes away
+                    let next = if tab < self.boards.len() {
+                        tab + 1
+                    } else {
+                        tab - 1
+                    };
+                    self.switch_board(next);
+                }
+                let slot = if tab < self.active_board {
+                    tab
+                } else {
+                    tab - 1
+                };
+                self.boards.remove(slot);
+                if tab < self.active_board {
+                    self.active_board -= 1;
+                }
+            }
+        }
+        self.selection.clear();
+    }
+
+    // the new board gets the tab after the active one and is switched to
+    fn insert_board(&mut self, board: Board) {
+        self.boards.insert(self.active_board, board);
+        self.switch_board(self.active_board + 1);
+    }
+
+    // makes the board in `tab` the active one, the boards keep their order
+    fn switch_board(&mut self, tab: usize) {
+        if tab == self.active_board || tab > self.boards.len() {
+            return;
+        }
+        self.end_layer_edit();
+        // `boards` skips the active tab
+        let slot = if tab < self.active_board {
+            tab
+        } else {
+            tab - 1
+        };
+        let mut board = self.boards.remove(slot);
+        self.swap_board(&mut board);
+        let old = if self.active_board < tab {
+            self.active_board
+        } else {
+            self.active_board - 1
+        };
+        self.boards.insert(old, board);
+        self.active_board = tab;
+    }
+
+    // trades the state of the active board with `board`
+    fn swap_board(&mut self, board: &mut Board) {
+        std::mem::swap(&mut self.board_id, &mut board.id);
+        std::mem::swap(&mut self.board_name, &mut board.name);
+        std::mem::swap(&mut self.layers, &mut board.layers);
+        std::mem::swap(&mut self.active_layer, &mut board.active_layer);
+        std::mem::swap(&mut self.texture, &mut board.texture);
+        std::mem::swap(&mut self.background, &mut board.background);
+        std::mem::swap(&mut self.prompt, &mut board.prompt);
+        std::mem::swap(&mut self.history, &mut board.history);
+        std::mem::swap(&mut self.project_path, &mut board.project_path);
+        std::mem::swap(&mut self.camera, &mut board.camera);
+        std::mem::swap(&mut self.mask, &mut board.mask);
+        self.jobs.set_board(self.board_id);
+    }
+
+    // tab of the board with this id, if it is still open
+    fn board_tab(&self, id: u64) -> Option<usize> {
+        if id == self.board_id {
+            return Some(self.active_board);
+        }
+        let slot = self.boards.iter().position(|b| b.id == id)?;
+        Some(if slot < self.active_board {
+            slot
+        } else {
+            slot + 1
+        })
+    }
+
+    // the built-in presets and the brush files next to the program, the
+    // files that do not load are listed in the status line
+    pub fn load_brushes(&mut self) {
+        let (brushes, errors) = load_brushes(Path::new(BRUSH_DIR));
+        self.brushes = brushes;
+        if !errors.is_empty() {
+            self.status = Some(format!("brush file error: {}", errors.join(", ")));
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        Document::new(
+            SettingsDoc::new(&self.settings, &self.tool, &self.diffusion),
+            &self.prompt,
+            Some(&self.background),
+            &self.layers,
+        )?
+        .save(Path::new(&self.project_path))
+    }
+
+    // queues the prompt on the diffusion server, the image arrives in update
+    // at the middle of the window
+    pub fn synth(&mut self) {
+        let config = self.diffusion.clone();
+        let prompt = self.prompt.clone();
+        self.jobs.submit(
+            format!("txt2img: {}", self.prompt.trim()),
+            JobTarget::NewImage {
+                center: self.camera.to_world(Vec2::ZERO),
+            },
+            Some(config.url.clone()),
+            Box::new(move || text_2_image(&config, &prompt).map_err(|err| err.to_string())),
+        );
+    }
+
+    // frame of the selected Custom Rect, or of the one drawn last on the
+    // active layer when none is selected
+    pub fn get_region(&self) -> Option<(Vec2, Vec2)> {
+        let selected = self
+            .selection
+            .get_items()
+            .iter()
+            .rev()
+            .filter_map(|&(l, i)| self.layers.get(l)?.elements.get(i));
+        let active = self.get_active_layer().elements.iter().rev();
+        selected
+            .chain(active)
+            .find_map(|elem| elem.downcast_ref::<RectangleCustom>())
+            .map(|rect| (rect.get_center(), rect.get_wh()))
+    }
+
+    pub fn synth_region(&mut self) {
+        if let Some((center, wh)) = self.get_region() {
+            add_diffusion_segment(&mut self.jobs, &self.diffusion, &self.prompt, center, wh);
+        }
+    }
+
+    // the picture takes the place of the Custom Rect with the same frame, or
+    // goes on the active layer when that rect is gone or can not be changed
+    fn place_in_region(&mut self, picture: Picture, center: Vec2, wh: Vec2) {
+        let found = self.layers.iter().enumerate().find_map(|(l, layer)| {
+            layer
+                .elements
+                .iter()
+                .position(|elem| is_region(elem, center, wh))
+                .map(|index| (l, index))
+        });
+        match found {
+            Some((layer, index)) if self.layers[layer].editable() => {
+                let splice = Splice {
+                    layer,
+                    index,
+                    before: vec![self.layers[layer].elements[index].clone()],
+                    after: vec![Box::new(picture)],
+                };
+                let command = Command::Replace {
+                    splices: vec![splice],
+                };
+                self.history.apply(command, &mut self.layers);
+            }
+            _ if self.get_active_layer().editable() => self.add_element(Box::new(picture)),
+            _ => {
+                self.status = Some(String::from(
+                    "generated image error: the layer is hidden or locked",
+                ))
+            }
+        }
+    }
+
+    // extends the picture the region overlaps out to the region
+    pub fn outpaint(&mut self) -> Result<(), Box<dyn Error>> {
+        let (center, wh) = self
+            .get_region()
+            .ok_or("draw a Custom Rect over an image edge")?;
+        let (min, max) = (center - wh.abs() / 2., center + wh.abs() / 2.);
+        let picture = self
+            .layers
+            .iter()
+            .rev()
+            .filter(|layer| layer.editable())
+            .flat_map(|layer| layer.elements.iter().rev())
+            .filter_map(|elem| elem.downcast_ref::<Picture>())
+            .find(|picture| {
+                let half = picture.get_wh().abs() / 2.;
+                let (p_min, p_max) = (picture.get_center() - half, picture.get_center() + half);
+                min.cmplt(p_max).all() && max.cmpgt(p_min).all()
+            })
+            .ok_or("the Custom Rect does not overlap an image")?;
+        add_outpaint_segment(
+            &mut self.jobs,
+            &self.diffusion,
+            &self.prompt,
+            picture,
+            (center, wh),
+        )?;
+        Ok(())
+    }
+
+    // sends what the canvas shows inside the region, or a frame of the
+    // generation size in the middle of the window, to be rendered by img2img
+    pub fn sketch(&mut self) {
+        let (center, wh) = self.get_region().unwrap_or((
+            self.camera.to_world(Vec2::ZERO),
+            vec2(self.diffusion.width as f32, self.diffusion.height as f32),
+        ));
+        // the frame itself is not part of the sketch
+        let layers: Vec<Layer> = self
+            .layers
+            .iter()
+            .map(|layer| {
+                let mut layer = layer.clone();
+                layer.elements.retain(|elem| !is_region(elem, center, wh));
+                layer
+            })
+            .collect();
+        add_sketch_segment(
+            &mut self.jobs,
+            &self.diffusion,
+            &self.prompt,
+            layers,
+            self.background.clone(),
+            center,
+            wh,
+        );
+    }
+
+    // puts the picture alone on a new layer above the active one
+    fn add_picture_layer(&mut self, picture: Picture) {
+        let index = add_layer(&mut self.layers, self.active_layer);
+        self.layers[index].elements.push(Box::new(picture));
+        let layer = self.layers[index].clone();
+        self.history.push(Command::AddLayer { index, layer });
+        self.active_layer = index;
+    }
+
+    pub fn add_mask_stroke(&mut self) {
+        if !self.line.get_line().is_empty() {
+            self.mask.push(self.line.clone());
+        }
+        self.line.clear_line();
+    }
+
+    // topmost picture on an editable layer under `p`
+    fn picture_at(&self, p: Point2) -> Option<&Picture> {
+        self.layers
+            .iter()
+            .rev()
+            .filter(|layer| layer.editable())
+            .find_map(|layer| {
+                layer
+                    .elements
+                    .iter()
+                    .rev()
+                    .filter_map(|elem| elem.downcast_ref::<Picture>())
+                    .find(|picture| picture.contains(p))
+            })
+    }
+
+    // sends the picture under the first mask stroke to be inpainted where
+    // the strokes cover it
+    pub fn inpaint(&mut self) {
+        let start = match self.mask.first().and_then(|s| s.get_line().first()) {
+            Some((p, _)) => *p,
+            None => return,
+        };
+        // pictures share their pixels, the clone frees self for the job
+        if let Some(picture) = self.picture_at(start).cloned() {
+            erase_from_canvas(
+                &mut self.jobs,
+                &self.diffusion,
+                &self.prompt,
+                &picture,
+                &self.mask,
+            );
+            self.mask.clear();
+        }
+    }
+
+    // new pixels for a picture, dropped if it was removed in the meantime
+    fn replace_picture(&mut self, id: u64, image: DynamicImage) {
+        let found = self.layers.iter().enumerate().find_map(|(l, layer)| {
+            layer
+                .elements
+                .iter()
+                .position(|elem| is_picture(elem, id))
+                .map(|index| (l, index))
+        });
+        if let Some((layer, index)) = found {
+            let before = self.layers[layer].elements[index].clone();
+            let mut after = before.clone();
+            if let Some(picture) = after.downcast_mut::<Picture>() {
+                picture.set_image(image);
+            }
+            let splice = Splice {
+                layer,
+                index,
+                before: vec![before],
+                after: vec![after],
+            };
+            let command = Command::Replace {
+                splices: vec![splice],
+            };
+            self.history.apply(command, &mut self.layers);
+        }
+    }
+
+    // the grown picture keeps its place in the layer, the Custom Rect that
+    // framed the new area goes away in the same step
+    fn extend_picture(
+        &mut self,
+        id: u64,
+        image: DynamicImage,
+        center: Vec2,
+        wh: Vec2,
+        region: (Vec2, Vec2),
+    ) {
+        let found = self.layers.iter().enumerate().find_map(|(l, layer)| {
+            layer
+                .elements
+                .iter()
+                .position(|elem| is_picture(elem, id))
+                .map(|index| (l, index))
+        });
+        let (layer, index) = match found {
+            Some(found) => found,
+            None => return,
+        };
+        let before = self.layers[layer].elements[index].clone();
+        let mut after = before.clone();
+        if let Some(picture) = after.downcast_mut::<Picture>() {
+            picture.set_image(image);
+            picture.set_center(center);
+            picture.set_wh(wh);
+        }
+        let mut splices = vec![Splice {
+            layer,
+            index,
+            before: vec![before],
+            after: vec![after],
+        }];
+        let rect = self.layers.iter().enumerate().find_map(|(l, layer)| {
+            layer
+                .elements
+                .iter()
+                .position(|elem| is_region(elem, region.0, region.1))
+                .map(|index| (l, index))
+        });
+        if let Some((l, i)) = rect {
+            splices.push(Splice {
+                layer: l,
+                index: i,
+                before: vec![self.layers[l].elements[i].clone()],
+                after: Vec::new(),
+            });
+            // splices are sorted by layer and index
+            splices.sort_by_key(|splice| (splice.layer, splice.index));
+        }
+        self.history
+            .apply(Command::Replace { splices }, &mut self.layers);
+    }
+
+    // fills the area of matching color under `p` as the window shows it,
+    // the fill goes on the active layer
+    pub fn fill(&mut self, app: &App, p: Point2) {
+        let bounds = self.view_bounds(app);
+        let canvas = raster::composite(
+            &self.layers,
+            Some(&self.background),
+            bounds,
+            self.camera.zoom,
+        );
+        if let Some((mask, center, wh)) = canvas.flood_fill(
+            p,
+            self.settings.fill_tolerance,
+            self.settings.fill_antialias,
+        ) {
+            let mut fill = Fill::new(mask, self.settings.get_color(), center, wh);
+            fill.set_blend(self.settings.blend);
+            self.add_element(Box::new(fill));
+        }
+    }
+
+    pub fn add_image_file(&mut self, path: &Path, center: Vec2) -> Result<(), Box<dyn Error>> {
+        let image = nannou::image::open(path)?;
+        self.add_element(Box::new(Picture::from_image(image, center)));
+        Ok(())
+    }
+
+    // images for a board in the background are placed while it is swapped
+    // in, those for closed boards are dropped
+    pub fn poll_jobs(&mut self) {
+        for (board, target, image) in self.jobs.poll() {
+            let tab = match self.board_tab(board) {
+                Some(tab) => tab,
+                None => continue,
+            };
+            let active = self.active_board;
+            // the result shifts elements, the rubber stroke so far goes
+            // to history first
+            if tab == active {
+                self.end_erase();
+            }
+            self.switch_board(tab);
+            match target {
+                JobTarget::NewImage { center } => {
+                    self.add_element(Box::new(Picture::from_image(image, center)))
+                }
+                JobTarget::Region { center, wh } => {
+                    let picture = Picture::new(image, center, wh.abs());
+                    self.place_in_region(picture, center, wh);
+                }
+                JobTarget::NewLayer { center, wh } => {
+                    self.add_picture_layer(Picture::new(image, center, wh.abs()))
+                }
+                JobTarget::Picture { id } => self.replace_picture(id, image),
+                JobTarget::Extend {
+                    id,
+                    center,
+                    wh,
+                    region,
+                } => self.extend_picture(id, image, center, wh, region),
+            }
+            self.switch_board(active);
+        }
+    }
+
+    pub fn export_png(&self) -> Result<(), Box<dyn Error>> {
+        raster::export_png(
+            &self.layers,
+            Some(&self.background),
+            geom::Rect::from_w_h(self.export.width, self.export.height),
+            self.export.scale,
+            Path::new(&self.export.path),
+        )
+    }
+
+    pub fn export_svg(&self) -> Result<(), Box<dyn Error>> {
+        svg::export_svg(
+            &self.layers,
+            geom::Rect::from_w_h(self.export.width, self.export.height),
+            Path::new(&self.export.svg_path),
+        )
+    }
+
+    // loose elements go on the active layer and the layers of the file
+    // above it, all as one undoable step
+    pub fn import_svg(&mut self) -> Result<(), Box<dyn Error>> {
+        let (elements, layers) = svg::import_svg(Path::new(&self.export.svg_path))?;
+        if !elements.is_empty() && !self.get_active_layer().editable() {
+            return Err("the active layer is hidden or locked".into());
+        }
+        let active = self.active_layer;
+        let start = self.layers[active].elements.len();
+        let mut commands: Vec<Command> = elements
+            .into_iter()
+            .enumerate()
+            .map(|(i, element)| Command::Add {
+                layer: active,
+                index: start + i,
+                element,
+            })
+            .collect();
+        commands.extend(
+            layers
+                .into_iter()
+                .enumerate()
+                .map(|(i, layer)| Command::AddLayer {
+                    index: active + 1 + i,
+                    layer,
+                }),
+        );
+        if !commands.is_empty() {
+            self.history
+                .apply(Command::Batch(commands), &mut self.layers);
+        }
+        Ok(())
+    }
+
+    pub fn open(&mut self, app: &App) -> Result<(), Box<dyn Error>> {
+        let document = Document::open(Path::new(&self.project_path))?;
+        // nothing changes unless the whole file loads
+        let layers = document.get_layers()?;
+        if let Some(background) = document.get_background()? {
+            self.texture = Texture::from_image(app, &background);
+            self.background = background;
+        }
+        self.layers = layers;
+        if self.layers.is_empty() {
+            self.layers.push(Layer::new(String::from("Layer 1")));
+        }
+        self.active_layer = 0;
+        self.settings = document.settings.to_settings(self.settings.get_shapes());
+        self.tool = document.settings.tool.clone();
+        self.drawing = false;
+        // the server stays the one this app talks to
+        self.diffusion = DiffusionConfig {
+            url: std::mem::take(&mut self.diffusion.url),
+            timeout: self.diffusion.timeout,
+            ..document.settings.diffusion.clone()
+        };
+        self.prompt = document.prompt;
+        self.history = History::new(self.history.get_depth());
+        self.erasure = None;
+        self.layer_edit = None;
+        self.canvas.clear();
+        self.mask.clear();
+        self.selection.clear();
+        Ok(())
+    }
+
+    pub fn erase_elements(&mut self, mouse_pos: Vec2) {
+        let r = self.get_brush_weight() / 2.;
+        let erased: Vec<Vec<Option<Vec<Elements>>>> = self
+            .layers
+            .iter()
+            .map(|layer| {
+                layer
+                    .elements
+                    .iter()
+                    .map(|elem| {
+                        if layer.editable() {
+                            elem.erase(mouse_pos, r)
+                        } else {
+                            None
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        if erased.iter().flatten().all(Option::is_none) {
+            return;
+        }
+        self.erasure
+            .get_or_insert_with(|| Erasure::new(&self.layers))
+            .cut(&self.layers, &erased);
+        for (layer, erased) in self.layers.iter_mut().zip(erased) {
+            let elements = std::mem::take(&mut layer.elements);
+            for (elem, erased) in elements.into_iter().zip(erased) {
+                match erased {
+                    Some(pieces) => layer.elements.extend(pieces),
+                    None => layer.elements.push(elem),
+                }
+            }
+        }
+    }
+
+    pub fn start_select(&mut self, mouse_pos: Vec2) {
+        self.selection
+            .press(&self.layers, mouse_pos, 1. / self.camera.zoom);
+    }
+
+    pub fn end_select(&mut self, mouse_pos: Vec2) {
+        if let Some(command) = self.selection.release(&self.layers, mouse_pos) {
+            self.history.push(command);
+            // the selected elements made this change and keep their places
+            self.selection.set_revision(self.history.get_revision());
+        }
+    }
+
+    pub fn end_erase(&mut self) {
+        if let Some(command) = self
+            .erasure
+            .take()
+            .and_then(|erasure| erasure.finish(&self.layers))
+        {
+            self.history.push(command);
+        }
     }
 
     pub fn update(&mut self, app: &App) {
-        let mouse_pos = pt2(app.mouse.x, app.mouse.y);
+        let screen = pt2(app.mouse.x, app.mouse.y);
+        if self.camera.is_panning() {
+            self.camera.pan_to(screen);
+        }
+        let mouse_pos = self.camera.to_world(screen);
+        // undoing a layer change can leave the active index past the end
+        self.active_layer = self.active_layer.min(self.layers.len() - 1);
+        self.selection
+            .retain_valid(&self.layers, self.history.get_revision());
+        self.poll_jobs();
         if self.get_drawing() {
             match self.tool {
-                Tool::Pencil => self
+                Tool::Pencil if self.settings.variable_width => {
+                    let width = self.next_width(mouse_pos);
+                    self.line
+                        .push_point(mouse_pos, self.get_settings().get_color(), width)
+                }
+                Tool::Pencil | Tool::Mask => self
                     .line
                     .pixels
                     .push((mouse_pos, self.get_settings().get_color())),
-                Tool::Rect => self
-                    .rect
-                    .set_wh((mouse_pos - self.rect.get_center()).abs() * 2.),
-                Tool::Ellipse => self.ellipse.set_radius(
-                    ((mouse_pos.x - self.ellipse.get_center().x).pow(2) as f32
-                        - (mouse_pos.y - self.ellipse.get_center().y).pow(2) as f32)
-                        .sqrt()
-                        * 2.,
-                ),
-                Tool::Rect_Custom => {
+                Tool::Rect => {
+                    let (center, wh) = self.drag_box(app, mouse_pos);
+                    self.rect.set_center(center);
+                    self.rect.set_wh(wh);
+                }
+                Tool::Ellipse => {
+                    let (center, wh) = self.drag_box(app, mouse_pos);
+                    self.ellipse.set_center(center);
+                    self.ellipse.set_radii(wh / 2.);
+                }
+                Tool::RectCustom => {
                     self.rect_custom
                         .set_wh((mouse_pos - self.rect_custom.get_center()).abs() * 2.);
                 }
-
-                _ => {}
+                Tool::Rubber => self.erase_elements(mouse_pos),
+                Tool::Select => self.selection.drag_to(&mut self.layers, mouse_pos),
+                Tool::Segment => {
+                    let end = self.shape_end(app, self.segment.get_start(), mouse_pos);
+                    self.segment.set_end(end)
+                }
+                Tool::Arrow => {
+                    let end = self.shape_end(app, self.arrow.get_start(), mouse_pos);
+                    self.arrow.set_end(end)
+                }
+                Tool::Polygon => self.polygon.drag_to(mouse_pos),
+                Tool::Star => self.star.drag_to(mouse_pos),
+                Tool::Fill => {}
             }
         }
     }
-}
 
-fn check_range(p: &Vec2, m: Vec2, r: f32) -> bool {
-    let range_x: std::ops::Range<f32> = m.x - r..m.x + r;
-    let range_y = m.y - r..m.y + r;
-    if range_x.contains(&p.x) && range_y.contains(&p.y) {
-        true
-    } else {
-        false
+    // frame of a rectangle or ellipse being dragged, Alt grows it from where
+    // the drag started and Shift keeps it square
+    fn drag_box(&self, app: &App, mouse_pos: Vec2) -> (Vec2, Vec2) {
+        drag_box(
+            self.drag_start,
+            mouse_pos,
+            app.keys.mods.alt(),
+            app.keys.mods.shift(),
+        )
+    }
+
+    // where a line or arrow dragged from `start` ends, Shift snaps its angle
+    fn shape_end(&self, app: &App, start: Vec2, mouse_pos: Vec2) -> Vec2 {
+        if app.keys.mods.shift() {
+            snap_angle(start, mouse_pos)
+        } else {
+            mouse_pos
+        }
     }
 }
 
-// pub fn erase_elements(pencil: &mut Model, mouse_pos: Vec2) {
-//     model.elements.iter_mut().for_each(|pencil| {
-//         pencil.vec_pix.iter_mut().for_each(|(p, c)| {
-//             if check_range(p, mouse_pos, 5.) {
-//                 *c = color::hsv(0., 0., 0.)
-//             }
-//         });
-//     });
-// }
+// whether the element is the Custom Rect with this frame
+fn is_region(elem: &Elements, center: Vec2, wh: Vec2) -> bool {
+    elem.downcast_ref::<RectangleCustom>()
+        .is_some_and(|rect| rect.get_center() == center && rect.get_wh() == wh)
+}
+
+fn is_picture(elem: &Elements, id: u64) -> bool {
+    elem.downcast_ref::<Picture>()
+        .is_some_and(|picture| picture.get_id() == id)
+}
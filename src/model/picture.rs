@@ -0,0 +1,290 @@
+use super::{
+    blend::Blend,
+    document::{encode_png, ElementDoc},
+    elements::{transform_point, Element, Elements},
+    layers::LayerProps,
+};
+use crate::canvas::raster::doc_to_rgba;
+use nannou::{
+    image::{DynamicImage, GenericImageView, GrayImage, Rgba, RgbaImage},
+    prelude::*,
+    wgpu::Texture,
+};
+use std::{
+    any::Any,
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    error::Error,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+static NEXT_PICTURE_ID: AtomicU64 = AtomicU64::new(0);
+
+// opacities are rounded to this many steps so sliding one does not
+// upload a texture every frame
+const ALPHA_STEPS: f32 = 32.;
+
+// raster image on the canvas, clones share the pixels and the id so they
+// also share the texture
+#[derive(Clone)]
+pub struct Picture {
+    id: u64,
+    image: Arc<DynamicImage>,
+    center: Vec2,
+    wh: Vec2,
+    rotation: f32,
+    opacity: f32,
+    blend: Blend,
+}
+
+impl Picture {
+    pub fn new(image: DynamicImage, center: Vec2, wh: Vec2) -> Self {
+        Self {
+            id: NEXT_PICTURE_ID.fetch_add(1, Ordering::SeqCst),
+            image: Arc::new(image),
+            center,
+            wh,
+            rotation: 0.,
+            opacity: 1.,
+            blend: Blend::Normal,
+        }
+    }
+
+    // shown at the size of its pixels
+    pub fn from_image(image: DynamicImage, center: Vec2) -> Self {
+        let (w, h) = image.dimensions();
+        Self::new(image, center, vec2(w as f32, h as f32))
+    }
+
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn get_image(&self) -> &DynamicImage {
+        &self.image
+    }
+
+    // new pixels get a new id so the old texture is not reused
+    pub fn set_image(&mut self, image: DynamicImage) {
+        self.id = NEXT_PICTURE_ID.fetch_add(1, Ordering::SeqCst);
+        self.image = Arc::new(image);
+    }
+
+    pub fn get_center(&self) -> Vec2 {
+        self.center
+    }
+
+    pub fn set_center(&mut self, center: Vec2) {
+        self.center = center
+    }
+
+    pub fn get_wh(&self) -> Vec2 {
+        self.wh
+    }
+
+    pub fn set_wh(&mut self, wh: Vec2) {
+        self.wh = wh
+    }
+
+    pub fn get_rotation(&self) -> f32 {
+        self.rotation
+    }
+
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.rotation = rotation
+    }
+
+    pub fn get_opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity.clamp(0., 1.)
+    }
+
+    // position of a world point in image pixels, origin at the center of
+    // the image and y going up
+    pub fn to_pixels(&self, p: Point2) -> Vec2 {
+        let (w, h) = self.image.dimensions();
+        let local = self.to_frame(p);
+        vec2(
+            local.x * w as f32 / self.wh.x,
+            local.y * h as f32 / self.wh.y,
+        )
+    }
+
+    pub fn contains(&self, p: Point2) -> bool {
+        let local = self.to_frame(p);
+        local.x.abs() <= self.wh.x.abs() / 2. && local.y.abs() <= self.wh.y.abs() / 2.
+    }
+
+    // world point relative to the center of the unrotated frame
+    fn to_frame(&self, p: Point2) -> Vec2 {
+        let (sin, cos) = (-self.rotation).sin_cos();
+        let d = p - self.center;
+        vec2(d.x * cos - d.y * sin, d.x * sin + d.y * cos)
+    }
+}
+
+impl Element for Picture {
+    fn draw_elem(&self, draw: &Draw, app: &App, textures: &TextureCache, alpha: f32) {
+        let draw = self.blend.draw(draw);
+        textures.with_texture(app, self, self.opacity * alpha, |texture| {
+            draw.texture(texture)
+                .xy(self.center)
+                .wh(self.wh)
+                .rotate(self.rotation);
+        });
+    }
+    // pictures have no color of their own
+    fn get_color(&self) -> Hsva {
+        hsva(0., 0., 1., 1.)
+    }
+    fn set_color(&mut self, _color: Hsva) {}
+    fn get_blend(&self) -> Blend {
+        self.blend
+    }
+    fn set_blend(&mut self, blend: Blend) {
+        self.blend = blend;
+    }
+    fn bounds(&self) -> (Vec2, Vec2) {
+        let (sin, cos) = self.rotation.sin_cos();
+        let half = self.wh.abs() / 2.;
+        let extent = vec2(
+            half.x * cos.abs() + half.y * sin.abs(),
+            half.x * sin.abs() + half.y * cos.abs(),
+        );
+        (self.center - extent, self.center + extent)
+    }
+    fn hit(&self, p: Vec2, _r: f32) -> bool {
+        self.contains(p)
+    }
+    fn translate(&mut self, delta: Vec2) {
+        self.center += delta;
+    }
+    fn transform(&mut self, pivot: Vec2, scale: f32, angle: f32) {
+        self.center = transform_point(self.center, pivot, scale, angle);
+        self.wh *= scale;
+        self.rotation += angle;
+    }
+    // pictures are changed through inpainting, not the rubber
+    fn erase(&self, _mouse_pos: Vec2, _r: f32) -> Option<Vec<Elements>> {
+        None
+    }
+    fn clone_box(&self) -> Elements {
+        Box::new(self.clone())
+    }
+    fn to_doc(&self) -> Result<ElementDoc, Box<dyn Error>> {
+        Ok(ElementDoc::Image {
+            png: encode_png(&self.image)?,
+            center: [self.center.x, self.center.y],
+            wh: [self.wh.x, self.wh.y],
+            rotation: self.rotation,
+            opacity: self.opacity,
+            blend: self.blend,
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// textures for the pictures on the canvas, filled lazily from view
+#[derive(Default)]
+pub struct TextureCache {
+    textures: RefCell<HashMap<(u64, u8), Texture>>,
+    used: RefCell<HashSet<(u64, u8)>>,
+}
+
+impl TextureCache {
+    pub fn with_texture(&self, app: &App, picture: &Picture, alpha: f32, f: impl FnOnce(&Texture)) {
+        let step = (alpha.clamp(0., 1.) * ALPHA_STEPS).round() as u8;
+        let key = (picture.id, step);
+        self.used.borrow_mut().insert(key);
+        let mut textures = self.textures.borrow_mut();
+        let texture = textures.entry(key).or_insert_with(|| {
+            let image = with_opacity(picture.get_image(), step as f32 / ALPHA_STEPS);
+            Texture::from_image(app, &DynamicImage::ImageRgba8(image))
+        });
+        f(texture);
+    }
+
+    // drops the textures nothing drew since the last call
+    pub fn end_frame(&self) {
+        let mut used = self.used.borrow_mut();
+        self.textures
+            .borrow_mut()
+            .retain(|key, _| used.contains(key));
+        used.clear();
+    }
+}
+
+// what the canvas was last rendered for, anything else on it changes
+// through history
+#[derive(PartialEq)]
+pub struct CanvasKey {
+    pub board: u64,
+    pub revision: u64,
+    pub bounds: geom::Rect,
+    pub layers: Vec<LayerProps>,
+}
+
+// the canvas rendered the way exports mix it, for what the window can not
+// blend itself
+#[derive(Default)]
+pub struct CanvasCache(RefCell<Option<(CanvasKey, Texture)>>);
+
+impl CanvasCache {
+    // without a key the canvas is rendered again every time
+    pub fn with_texture(
+        &self,
+        app: &App,
+        key: Option<CanvasKey>,
+        render: impl FnOnce() -> RgbaImage,
+        f: impl FnOnce(&Texture),
+    ) {
+        let mut cache = self.0.borrow_mut();
+        if let (Some((cached, texture)), Some(key)) = (&*cache, &key) {
+            if cached == key {
+                f(texture);
+                return;
+            }
+        }
+        let texture = Texture::from_image(app, &DynamicImage::ImageRgba8(render()));
+        f(&texture);
+        *cache = key.map(|key| (key, texture));
+    }
+
+    pub fn clear(&self) {
+        self.0.borrow_mut().take();
+    }
+}
+
+// an image in one color with the gray levels of `mask` as its alpha
+pub fn tint(mask: &GrayImage, color: Hsva) -> RgbaImage {
+    let rgba = doc_to_rgba(color.into());
+    RgbaImage::from_fn(mask.width(), mask.height(), |x, y| {
+        Rgba([
+            (rgba[0] * 255.).round() as u8,
+            (rgba[1] * 255.).round() as u8,
+            (rgba[2] * 255.).round() as u8,
+            (mask.get_pixel(x, y).0[0] as f32 * rgba[3]).round() as u8,
+        ])
+    })
+}
+
+fn with_opacity(image: &DynamicImage, alpha: f32) -> RgbaImage {
+    let mut rgba = image.to_rgba8();
+    if alpha < 1. {
+        for px in rgba.pixels_mut() {
+            px.0[3] = (px.0[3] as f32 * alpha).round() as u8;
+        }
+    }
+    rgba
+}
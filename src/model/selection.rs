@@ -0,0 +1,318 @@
+use super::{
+    elements::Elements,
+    history::{Command, Splice},
+    layers::Layer,
+};
+use nannou::prelude::*;
+
+// sizes on screen, scaled into world units by the camera zoom
+// half the size of the handles, also how close a click has to be
+const HANDLE_SIZE: f32 = 5.;
+// how far above the box the rotation handle sits
+const ROTATE_OFFSET: f32 = 25.;
+// how far from an element a click still picks it
+const PICK_RANGE: f32 = 3.;
+
+enum Drag {
+    Move,
+    Scale { pivot: Vec2 },
+    Rotate { pivot: Vec2 },
+    Marquee { start: Vec2 },
+}
+
+// elements picked with the Select tool and the drag changing them
+#[derive(Default)]
+pub struct Selection {
+    // layer and element index
+    items: Vec<(usize, usize)>,
+    drag: Option<Drag>,
+    last: Vec2,
+    // the items before the drag, copied once something moves and pushed
+    // to history when the drag ends
+    before: Option<Vec<Elements>>,
+    // history revision the items were picked at, any other change in
+    // history can shift their indices
+    revision: u64,
+}
+
+impl Selection {
+    pub fn get_items(&self) -> &Vec<(usize, usize)> {
+        &self.items
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.drag = None;
+        self.before = None;
+    }
+
+    // whether a drag changed the items and history has not heard of it yet
+    pub fn is_changing(&self) -> bool {
+        self.before.is_some()
+    }
+
+    pub fn set_revision(&mut self, revision: u64) {
+        self.revision = revision;
+    }
+
+    // drops the items once history changed the elements, and those on
+    // layers that were hidden or locked since
+    pub fn retain_valid(&mut self, layers: &[Layer], revision: u64) {
+        if revision != self.revision {
+            self.clear();
+            self.revision = revision;
+        }
+        self.items.retain(|&(l, i)| {
+            l < layers.len() && i < layers[l].elements.len() && layers[l].editable()
+        });
+    }
+
+    pub fn bounds(&self, layers: &[Layer]) -> Option<(Vec2, Vec2)> {
+        self.items
+            .iter()
+            .map(|&(l, i)| layers[l].elements[i].bounds())
+            .reduce(|(min, max), (a, b)| (min.min(a), max.max(b)))
+    }
+
+    // `scale` is world units per screen point, for the handle sizes
+    pub fn press(&mut self, layers: &[Layer], p: Vec2, scale: f32) {
+        self.last = p;
+        self.before = None;
+        let drag = match self.bounds(layers) {
+            Some((min, max))
+                if p.distance(rotate_handle(min, max, scale)) <= HANDLE_SIZE * scale =>
+            {
+                Drag::Rotate {
+                    pivot: (min + max) / 2.,
+                }
+            }
+            Some((min, max)) => match corner_at(min, max, p, scale) {
+                // scaling keeps the opposite corner in place
+                Some(corner) => Drag::Scale {
+                    pivot: min + max - corner,
+                },
+                None if p.cmpge(min).all() && p.cmple(max).all() => Drag::Move,
+                None => self.pick(layers, p, scale),
+            },
+            None => self.pick(layers, p, scale),
+        };
+        self.drag = Some(drag);
+    }
+
+    // selects the element under `p`, or starts a marquee when there is none
+    fn pick(&mut self, layers: &[Layer], p: Vec2, scale: f32) -> Drag {
+        match element_at(layers, p, PICK_RANGE * scale) {
+            Some(item) => {
+                self.items = vec![item];
+                Drag::Move
+            }
+            None => {
+                self.items.clear();
+                Drag::Marquee { start: p }
+            }
+        }
+    }
+
+    pub fn drag_to(&mut self, layers: &mut [Layer], p: Vec2) {
+        if p == self.last {
+            return;
+        }
+        let last = std::mem::replace(&mut self.last, p);
+        let (items, before) = (&self.items, &mut self.before);
+        let mut each = |f: &dyn Fn(&mut Elements)| {
+            before.get_or_insert_with(|| {
+                items
+                    .iter()
+                    .map(|&(l, i)| layers[l].elements[i].clone())
+                    .collect()
+            });
+            for &(l, i) in items.iter() {
+                f(&mut layers[l].elements[i]);
+            }
+        };
+        match self.drag {
+            Some(Drag::Move) => each(&|elem| elem.translate(p - last)),
+            Some(Drag::Scale { pivot }) => {
+                let (from, to) = (last.distance(pivot), p.distance(pivot));
+                if from < 1. || to < 1. {
+                    return;
+                }
+                each(&|elem| elem.transform(pivot, to / from, 0.))
+            }
+            Some(Drag::Rotate { pivot }) => {
+                let (from, to) = (last - pivot, p - pivot);
+                let angle = to.y.atan2(to.x) - from.y.atan2(from.x);
+                each(&|elem| elem.transform(pivot, 1., angle))
+            }
+            Some(Drag::Marquee { .. }) | None => {}
+        }
+    }
+
+    // ends the drag, returns the change to record in history if there is one
+    pub fn release(&mut self, layers: &[Layer], p: Vec2) -> Option<Command> {
+        if let Some(Drag::Marquee { start }) = self.drag.take() {
+            let (min, max) = (start.min(p), start.max(p));
+            self.items = layers
+                .iter()
+                .enumerate()
+                .filter(|(_, layer)| layer.editable())
+                .flat_map(|(l, layer)| {
+                    layer
+                        .elements
+                        .iter()
+                        .enumerate()
+                        .filter_map(move |(i, elem)| {
+                            let (a, b) = elem.bounds();
+                            (a.cmpge(min).all() && b.cmple(max).all()).then_some((l, i))
+                        })
+                })
+                .collect();
+        }
+        let before = self.before.take()?;
+        let mut splices: Vec<Splice> = self
+            .items
+            .iter()
+            .zip(before)
+            .map(|(&(layer, index), before)| Splice {
+                layer,
+                index,
+                before: vec![before],
+                after: vec![layers[layer].elements[index].clone()],
+            })
+            .collect();
+        splices.sort_by_key(|splice| (splice.layer, splice.index));
+        Some(Command::Replace { splices })
+    }
+
+    pub fn draw(&self, draw: &Draw, layers: &[Layer], mouse: Vec2, scale: f32) {
+        if let Some(Drag::Marquee { start }) = self.drag {
+            draw.rect()
+                .xy((start + mouse) / 2.)
+                .wh((mouse - start).abs())
+                .no_fill()
+                .stroke_color(WHITE)
+                .stroke_weight(scale);
+        }
+        let (min, max) = match self.bounds(layers) {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        draw.rect()
+            .xy((min + max) / 2.)
+            .wh(max - min)
+            .no_fill()
+            .stroke_color(WHITE)
+            .stroke_weight(scale);
+        let top = pt2((min.x + max.x) / 2., max.y);
+        draw.line()
+            .start(top)
+            .end(rotate_handle(min, max, scale))
+            .color(WHITE)
+            .weight(scale);
+        draw.ellipse()
+            .xy(rotate_handle(min, max, scale))
+            .radius(HANDLE_SIZE * scale)
+            .color(WHITE);
+        for corner in corners(min, max) {
+            draw.rect()
+                .xy(corner)
+                .w_h(HANDLE_SIZE * 2. * scale, HANDLE_SIZE * 2. * scale)
+                .color(WHITE);
+        }
+    }
+}
+
+fn corners(min: Vec2, max: Vec2) -> [Vec2; 4] {
+    [min, pt2(min.x, max.y), max, pt2(max.x, min.y)]
+}
+
+fn corner_at(min: Vec2, max: Vec2, p: Vec2, scale: f32) -> Option<Vec2> {
+    corners(min, max)
+        .into_iter()
+        .find(|c| (p - *c).abs().cmple(Vec2::splat(HANDLE_SIZE * scale)).all())
+}
+
+fn rotate_handle(min: Vec2, max: Vec2, scale: f32) -> Vec2 {
+    pt2((min.x + max.x) / 2., max.y + ROTATE_OFFSET * scale)
+}
+
+// topmost element under `p` on a layer tools can change
+fn element_at(layers: &[Layer], p: Vec2, r: f32) -> Option<(usize, usize)> {
+    layers
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, layer)| layer.editable())
+        .find_map(|(l, layer)| {
+            layer
+                .elements
+                .iter()
+                .rposition(|elem| elem.hit(p, r))
+                .map(|i| (l, i))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::elements::Line;
+
+    // one layer with a line from (0, 0) to (10, 0)
+    fn canvas() -> Vec<Layer> {
+        let red = hsva(0., 1., 1., 1.);
+        let pixels = vec![(pt2(0., 0.), red), (pt2(10., 0.), red)];
+        let mut layer = Layer::new(String::from("Layer 1"));
+        layer
+            .elements
+            .push(Box::new(Line::new_param(pixels, 1., red)));
+        vec![layer]
+    }
+
+    #[test]
+    fn clicks_do_not_copy_the_canvas() {
+        let mut layers = canvas();
+        let mut selection = Selection::default();
+        selection.press(&layers, pt2(5., 0.), 1.);
+        assert_eq!(selection.items, vec![(0, 0)]);
+        assert!(selection.before.is_none());
+        selection.drag_to(&mut layers, pt2(5., 0.));
+        assert!(selection.release(&layers, pt2(5., 0.)).is_none());
+    }
+
+    #[test]
+    fn drags_record_the_elements_before_them() {
+        let mut layers = canvas();
+        let mut selection = Selection::default();
+        selection.press(&layers, pt2(5., 0.), 1.);
+        selection.drag_to(&mut layers, pt2(8., 0.));
+        selection.drag_to(&mut layers, pt2(9., 0.));
+        match selection.release(&layers, pt2(9., 0.)) {
+            Some(Command::Replace { splices }) => {
+                assert_eq!(splices.len(), 1);
+                assert_eq!((splices[0].layer, splices[0].index), (0, 0));
+                assert_eq!(splices[0].before[0].bounds().0.x.floor(), -1.);
+                assert_eq!(splices[0].after[0].bounds().0.x.floor(), 3.);
+            }
+            _ => panic!("the drag should be recorded"),
+        }
+    }
+
+    #[test]
+    fn history_changes_drop_the_selection() {
+        let mut layers = canvas();
+        let mut selection = Selection::default();
+        selection.press(&layers, pt2(5., 0.), 1.);
+        selection.release(&layers, pt2(5., 0.));
+        selection.retain_valid(&layers, 0);
+        assert_eq!(selection.items, vec![(0, 0)]);
+
+        layers[0].locked = true;
+        selection.retain_valid(&layers, 0);
+        assert!(selection.items.is_empty());
+
+        layers[0].locked = false;
+        selection.press(&layers, pt2(5., 0.), 1.);
+        selection.retain_valid(&layers, 1);
+        assert!(selection.items.is_empty());
+    }
+}
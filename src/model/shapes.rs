@@ -0,0 +1,508 @@
+use super::{
+    blend::Blend,
+    document::ElementDoc,
+    elements::{points_bounds, segment_distance, transform_point, Element, Elements},
+    picture::TextureCache,
+    style::{is_closed, ShapeStyle},
+};
+use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{any::Any, error::Error};
+
+// Shift turns drags in steps of this many degrees
+pub const SNAP_DEGREES: f32 = 15.;
+// length of an arrow head on top of three times the stroke width
+const HEAD_SIZE: f32 = 10.;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ArrowHead {
+    // two strokes
+    Open,
+    // a solid triangle
+    Filled,
+    // solid triangles at both ends
+    Double,
+}
+
+// `end` turned around `start` to the nearest snap angle
+pub fn snap_angle(start: Vec2, end: Vec2) -> Vec2 {
+    let d = end - start;
+    let step = SNAP_DEGREES.to_radians();
+    let angle = (d.y.atan2(d.x) / step).round() * step;
+    start + vec2(angle.cos(), angle.sin()) * d.length()
+}
+
+// corners of a regular polygon with the first one repeated at the end, the
+// first points along `rotation`
+pub fn polygon_points(center: Vec2, radius: f32, sides: u32, rotation: f32) -> Vec<Vec2> {
+    let sides = sides.max(3);
+    let mut points: Vec<Vec2> = (0..sides)
+        .map(|i| {
+            let angle = rotation + i as f32 * 2. * PI / sides as f32;
+            center + vec2(angle.cos(), angle.sin()) * radius
+        })
+        .collect();
+    points.push(points[0]);
+    points
+}
+
+// tips and inner corners of a star, closed like `polygon_points`
+pub fn star_points(center: Vec2, radius: f32, points: u32, ratio: f32, rotation: f32) -> Vec<Vec2> {
+    let corners = points.max(2) * 2;
+    let mut points: Vec<Vec2> = (0..corners)
+        .map(|i| {
+            let angle = rotation + i as f32 * 2. * PI / corners as f32;
+            let r = if i % 2 == 0 { radius } else { radius * ratio };
+            center + vec2(angle.cos(), angle.sin()) * r
+        })
+        .collect();
+    points.push(points[0]);
+    points
+}
+
+// the shaft of an arrow and its heads as left, tip and right corners, filled
+// heads shorten the shaft so it does not poke through their tip
+fn arrow_parts(
+    start: Vec2,
+    end: Vec2,
+    width: f32,
+    head: ArrowHead,
+) -> ((Vec2, Vec2), Vec<[Vec2; 3]>) {
+    let length = start.distance(end);
+    let size = (HEAD_SIZE + width * 3.).min(length / 2.);
+    let dir = (end - start).normalize_or_zero();
+    let across = vec2(-dir.y, dir.x) * size / 2.;
+    let head_at =
+        |tip: Vec2, dir: Vec2| [tip - dir * size + across, tip, tip - dir * size - across];
+    match head {
+        ArrowHead::Open => ((start, end), vec![head_at(end, dir)]),
+        ArrowHead::Filled => ((start, end - dir * size), vec![head_at(end, dir)]),
+        ArrowHead::Double => (
+            (start + dir * size, end - dir * size),
+            vec![head_at(end, dir), head_at(start, -dir)],
+        ),
+    }
+}
+
+fn near_path(points: &[Vec2], p: Vec2, reach: f32) -> bool {
+    points
+        .windows(2)
+        .any(|w| segment_distance(p, w[0], w[1]) <= reach)
+}
+
+// even-odd test against a closed outline
+pub fn inside(points: &[Vec2], p: Vec2) -> bool {
+    let mut inside = false;
+    for w in points.windows(2) {
+        let (a, b) = (w[0], w[1]);
+        if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+// the outlines an arrow is drawn with and the style of each, heads are solid
+// in the stroke color unless they are open
+pub fn arrow_outlines(
+    start: Vec2,
+    end: Vec2,
+    head: ArrowHead,
+    style: &ShapeStyle,
+) -> Vec<(Vec<Vec2>, ShapeStyle)> {
+    let ((from, to), heads) = arrow_parts(start, end, style.width, head);
+    let head_style = match head {
+        ArrowHead::Open => ShapeStyle {
+            dash: Vec::new(),
+            ..style.clone()
+        },
+        ArrowHead::Filled | ArrowHead::Double => ShapeStyle {
+            fill: Some(style.stroke),
+            width: 0.,
+            ..style.clone()
+        },
+    };
+    let mut outlines = vec![(vec![from, to], style.clone())];
+    for [left, tip, right] in heads {
+        let outline = match head {
+            ArrowHead::Open => vec![left, tip, right],
+            ArrowHead::Filled | ArrowHead::Double => vec![left, tip, right, left],
+        };
+        outlines.push((outline, head_style.clone()));
+    }
+    outlines
+}
+
+#[derive(Clone, Default)]
+pub struct Segment {
+    start: Point2,
+    end: Point2,
+    style: ShapeStyle,
+}
+
+impl Element for Segment {
+    fn draw_elem(&self, draw: &Draw, _app: &App, _textures: &TextureCache, alpha: f32) {
+        self.style.draw(draw, &[self.start, self.end], alpha);
+    }
+    fn get_color(&self) -> Hsva {
+        self.style.get_color()
+    }
+    fn set_color(&mut self, color: Hsva) {
+        self.style.set_color(color);
+    }
+    fn get_blend(&self) -> Blend {
+        self.style.blend
+    }
+    fn set_blend(&mut self, blend: Blend) {
+        self.style.blend = blend;
+    }
+    fn bounds(&self) -> (Vec2, Vec2) {
+        points_bounds(&[self.start, self.end], self.style.reach())
+    }
+    fn hit(&self, p: Vec2, r: f32) -> bool {
+        segment_distance(p, self.start, self.end) <= self.style.width / 2. + r
+    }
+    fn translate(&mut self, delta: Vec2) {
+        self.start += delta;
+        self.end += delta;
+    }
+    fn transform(&mut self, pivot: Vec2, scale: f32, angle: f32) {
+        self.start = transform_point(self.start, pivot, scale, angle);
+        self.end = transform_point(self.end, pivot, scale, angle);
+        self.style.width *= scale;
+    }
+    fn erase(&self, mouse_pos: Vec2, r: f32) -> Option<Vec<Elements>> {
+        let hit = self.hit(mouse_pos, r);
+        self.style.erase(&[self.start, self.end], hit, mouse_pos, r)
+    }
+    fn clone_box(&self) -> Elements {
+        Box::new(self.clone())
+    }
+    fn to_doc(&self) -> Result<ElementDoc, Box<dyn Error>> {
+        Ok(ElementDoc::Segment {
+            start: [self.start.x, self.start.y],
+            end: [self.end.x, self.end.y],
+            style: (&self.style).into(),
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Segment {
+    pub fn new(start: Vec2, end: Vec2, style: ShapeStyle) -> Self {
+        Self { start, end, style }
+    }
+
+    pub fn get_start(&self) -> Vec2 {
+        self.start
+    }
+
+    pub fn set_end(&mut self, end: Vec2) {
+        self.end = end
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Polygon {
+    center: Point2,
+    radius: f32,
+    sides: u32,
+    rotation: f32,
+    style: ShapeStyle,
+}
+
+impl Element for Polygon {
+    fn draw_elem(&self, draw: &Draw, _app: &App, _textures: &TextureCache, alpha: f32) {
+        self.style.draw(draw, &self.points(), alpha);
+    }
+    fn get_color(&self) -> Hsva {
+        self.style.get_color()
+    }
+    fn set_color(&mut self, color: Hsva) {
+        self.style.set_color(color);
+    }
+    fn get_blend(&self) -> Blend {
+        self.style.blend
+    }
+    fn set_blend(&mut self, blend: Blend) {
+        self.style.blend = blend;
+    }
+    fn bounds(&self) -> (Vec2, Vec2) {
+        points_bounds(&self.points(), self.style.reach())
+    }
+    fn hit(&self, p: Vec2, r: f32) -> bool {
+        let points = self.points();
+        inside(&points, p) || near_path(&points, p, self.style.width / 2. + r)
+    }
+    fn translate(&mut self, delta: Vec2) {
+        self.center += delta;
+    }
+    fn transform(&mut self, pivot: Vec2, scale: f32, angle: f32) {
+        self.center = transform_point(self.center, pivot, scale, angle);
+        self.radius *= scale;
+        self.rotation += angle;
+        self.style.width *= scale;
+    }
+    fn erase(&self, mouse_pos: Vec2, r: f32) -> Option<Vec<Elements>> {
+        self.style
+            .erase(&self.points(), self.hit(mouse_pos, r), mouse_pos, r)
+    }
+    fn clone_box(&self) -> Elements {
+        Box::new(self.clone())
+    }
+    fn to_doc(&self) -> Result<ElementDoc, Box<dyn Error>> {
+        Ok(ElementDoc::Polygon {
+            center: [self.center.x, self.center.y],
+            radius: self.radius,
+            sides: self.sides,
+            rotation: self.rotation,
+            style: (&self.style).into(),
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Polygon {
+    pub fn new(center: Vec2, radius: f32, sides: u32, style: ShapeStyle) -> Self {
+        Self {
+            center,
+            radius,
+            sides,
+            rotation: 0.,
+            style,
+        }
+    }
+
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.rotation = rotation
+    }
+
+    // a corner follows the mouse
+    pub fn drag_to(&mut self, p: Vec2) {
+        let d = p - self.center;
+        self.radius = d.length();
+        self.rotation = d.y.atan2(d.x);
+    }
+
+    fn points(&self) -> Vec<Vec2> {
+        polygon_points(self.center, self.radius, self.sides, self.rotation)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Star {
+    center: Point2,
+    radius: f32,
+    points: u32,
+    // radius of the inner corners as a share of the tips
+    ratio: f32,
+    rotation: f32,
+    style: ShapeStyle,
+}
+
+impl Element for Star {
+    fn draw_elem(&self, draw: &Draw, _app: &App, _textures: &TextureCache, alpha: f32) {
+        self.style.draw(draw, &self.corners(), alpha);
+    }
+    fn get_color(&self) -> Hsva {
+        self.style.get_color()
+    }
+    fn set_color(&mut self, color: Hsva) {
+        self.style.set_color(color);
+    }
+    fn get_blend(&self) -> Blend {
+        self.style.blend
+    }
+    fn set_blend(&mut self, blend: Blend) {
+        self.style.blend = blend;
+    }
+    fn bounds(&self) -> (Vec2, Vec2) {
+        points_bounds(&self.corners(), self.style.reach())
+    }
+    fn hit(&self, p: Vec2, r: f32) -> bool {
+        let corners = self.corners();
+        inside(&corners, p) || near_path(&corners, p, self.style.width / 2. + r)
+    }
+    fn translate(&mut self, delta: Vec2) {
+        self.center += delta;
+    }
+    fn transform(&mut self, pivot: Vec2, scale: f32, angle: f32) {
+        self.center = transform_point(self.center, pivot, scale, angle);
+        self.radius *= scale;
+        self.rotation += angle;
+        self.style.width *= scale;
+    }
+    fn erase(&self, mouse_pos: Vec2, r: f32) -> Option<Vec<Elements>> {
+        self.style
+            .erase(&self.corners(), self.hit(mouse_pos, r), mouse_pos, r)
+    }
+    fn clone_box(&self) -> Elements {
+        Box::new(self.clone())
+    }
+    fn to_doc(&self) -> Result<ElementDoc, Box<dyn Error>> {
+        Ok(ElementDoc::Star {
+            center: [self.center.x, self.center.y],
+            radius: self.radius,
+            points: self.points,
+            ratio: self.ratio,
+            rotation: self.rotation,
+            style: (&self.style).into(),
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Star {
+    pub fn new(center: Vec2, radius: f32, points: u32, ratio: f32, style: ShapeStyle) -> Self {
+        Self {
+            center,
+            radius,
+            points,
+            ratio,
+            rotation: 0.,
+            style,
+        }
+    }
+
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.rotation = rotation
+    }
+
+    // a tip follows the mouse
+    pub fn drag_to(&mut self, p: Vec2) {
+        let d = p - self.center;
+        self.radius = d.length();
+        self.rotation = d.y.atan2(d.x);
+    }
+
+    fn corners(&self) -> Vec<Vec2> {
+        star_points(
+            self.center,
+            self.radius,
+            self.points,
+            self.ratio,
+            self.rotation,
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct Arrow {
+    start: Point2,
+    end: Point2,
+    head: ArrowHead,
+    style: ShapeStyle,
+}
+
+impl Default for Arrow {
+    fn default() -> Self {
+        Self::new(
+            Vec2::ZERO,
+            Vec2::ZERO,
+            ArrowHead::Filled,
+            ShapeStyle::default(),
+        )
+    }
+}
+
+impl Element for Arrow {
+    fn draw_elem(&self, draw: &Draw, _app: &App, _textures: &TextureCache, alpha: f32) {
+        for (outline, style) in self.outlines() {
+            style.draw(draw, &outline, alpha);
+        }
+    }
+    fn get_color(&self) -> Hsva {
+        self.style.get_color()
+    }
+    fn set_color(&mut self, color: Hsva) {
+        self.style.set_color(color);
+    }
+    fn get_blend(&self) -> Blend {
+        self.style.blend
+    }
+    fn set_blend(&mut self, blend: Blend) {
+        self.style.blend = blend;
+    }
+    fn bounds(&self) -> (Vec2, Vec2) {
+        let points: Vec<Vec2> = self
+            .outlines()
+            .into_iter()
+            .flat_map(|(outline, _)| outline)
+            .collect();
+        points_bounds(&points, self.style.reach())
+    }
+    fn hit(&self, p: Vec2, r: f32) -> bool {
+        self.outlines().iter().any(|(outline, _)| {
+            (is_closed(outline) && inside(outline, p))
+                || near_path(outline, p, self.style.width / 2. + r)
+        })
+    }
+    fn translate(&mut self, delta: Vec2) {
+        self.start += delta;
+        self.end += delta;
+    }
+    fn transform(&mut self, pivot: Vec2, scale: f32, angle: f32) {
+        self.start = transform_point(self.start, pivot, scale, angle);
+        self.end = transform_point(self.end, pivot, scale, angle);
+        self.style.width *= scale;
+    }
+    // an arrow cut in two points nowhere, touching one removes it
+    fn erase(&self, mouse_pos: Vec2, r: f32) -> Option<Vec<Elements>> {
+        self.hit(mouse_pos, r).then(Vec::new)
+    }
+    fn clone_box(&self) -> Elements {
+        Box::new(self.clone())
+    }
+    fn to_doc(&self) -> Result<ElementDoc, Box<dyn Error>> {
+        Ok(ElementDoc::Arrow {
+            start: [self.start.x, self.start.y],
+            end: [self.end.x, self.end.y],
+            head: self.head,
+            style: (&self.style).into(),
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Arrow {
+    pub fn new(start: Vec2, end: Vec2, head: ArrowHead, style: ShapeStyle) -> Self {
+        Self {
+            start,
+            end,
+            head,
+            style,
+        }
+    }
+
+    pub fn get_start(&self) -> Vec2 {
+        self.start
+    }
+
+    pub fn set_end(&mut self, end: Vec2) {
+        self.end = end
+    }
+
+    fn outlines(&self) -> Vec<(Vec<Vec2>, ShapeStyle)> {
+        arrow_outlines(self.start, self.end, self.head, &self.style)
+    }
+}
@@ -0,0 +1,324 @@
+use super::elements::segment_distance;
+use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// a curve piece is cut into points about this many tolerances apart
+const SAMPLE_SPACING: f32 = 4.;
+// the most points a single curve piece is cut into
+const MAX_SAMPLES: usize = 32;
+// points around each round end of a filled stroke
+const CAP_STEPS: usize = 8;
+// thinnest a stroke gets, as a share of the brush weight
+pub const MIN_WIDTH: f32 = 0.3;
+// speed in screen points per frame at which a stroke is thinnest
+const THIN_SPEED: f32 = 40.;
+// how quickly the width follows the speed, lower is steadier
+const WIDTH_FOLLOW: f32 = 0.3;
+
+// how a pencil stroke is cleaned up when the mouse is released
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Smoothing {
+    Off,
+    // corner cutting, the stroke gets rounder with every pass
+    Chaikin,
+    // spline through the recorded points
+    CatmullRom,
+    // Ramer-Douglas-Peucker, drops the points the shape does not need
+    Decimate,
+    // cubic Béziers fitted to the points
+    Bezier,
+}
+
+// `tolerance` is how far the result may stray from the recorded points,
+// in the units of the points, what comes with each point is carried along
+pub fn process<T: Copy>(
+    pixels: &[(Point2, T)],
+    smoothing: Smoothing,
+    tolerance: f32,
+    passes: u32,
+) -> Vec<(Point2, T)> {
+    let mut pixels = pixels.to_vec();
+    // a still mouse records the same point every frame
+    pixels.dedup_by(|a, b| a.0 == b.0);
+    if pixels.len() < 3 || tolerance <= 0. {
+        return pixels;
+    }
+    match smoothing {
+        Smoothing::Off => pixels,
+        Smoothing::Chaikin => (0..passes).fold(pixels, |pixels, _| chaikin(&pixels)),
+        Smoothing::CatmullRom => catmull_rom(&pixels, tolerance),
+        Smoothing::Decimate => decimate(&pixels, tolerance),
+        Smoothing::Bezier => fit_bezier(&pixels, tolerance),
+    }
+}
+
+fn chaikin<T: Copy>(pixels: &[(Point2, T)]) -> Vec<(Point2, T)> {
+    let mut out = Vec::with_capacity(pixels.len() * 2);
+    out.push(pixels[0]);
+    for w in pixels.windows(2) {
+        let ((a, color), (b, _)) = (w[0], w[1]);
+        out.push((a.lerp(b, 0.25), color));
+        out.push((a.lerp(b, 0.75), color));
+    }
+    out.push(pixels[pixels.len() - 1]);
+    out
+}
+
+fn catmull_rom<T: Copy>(pixels: &[(Point2, T)], tolerance: f32) -> Vec<(Point2, T)> {
+    let last = pixels.len() - 1;
+    // the ends repeat so the spline reaches them
+    let at = |i: usize| pixels[i.min(last)].0;
+    let mut out = Vec::new();
+    for (i, &(_, value)) in pixels[..last].iter().enumerate() {
+        let (p0, p1, p2, p3) = (at(i.saturating_sub(1)), at(i), at(i + 1), at(i + 2));
+        let steps = samples(p1.distance(p2), tolerance);
+        for step in 0..steps {
+            let t = step as f32 / steps as f32;
+            let (t2, t3) = (t * t, t * t * t);
+            let p = 0.5
+                * (2. * p1
+                    + (p2 - p0) * t
+                    + (2. * p0 - 5. * p1 + 4. * p2 - p3) * t2
+                    + (3. * p1 - p0 - 3. * p2 + p3) * t3);
+            out.push((p, value));
+        }
+    }
+    out.push(pixels[last]);
+    out
+}
+
+fn decimate<T: Copy>(pixels: &[(Point2, T)], tolerance: f32) -> Vec<(Point2, T)> {
+    let last = pixels.len() - 1;
+    let mut keep = vec![false; pixels.len()];
+    keep[0] = true;
+    keep[last] = true;
+    let mut ranges = vec![(0, last)];
+    while let Some((first, last)) = ranges.pop() {
+        let (a, b) = (pixels[first].0, pixels[last].0);
+        let farthest = (first + 1..last)
+            .map(|i| (i, segment_distance(pixels[i].0, a, b)))
+            .max_by(|x, y| x.1.total_cmp(&y.1));
+        if let Some((i, distance)) = farthest {
+            if distance > tolerance {
+                keep[i] = true;
+                ranges.push((first, i));
+                ranges.push((i, last));
+            }
+        }
+    }
+    pixels
+        .iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(pixel, _)| *pixel)
+        .collect()
+}
+
+fn fit_bezier<T: Copy>(pixels: &[(Point2, T)], tolerance: f32) -> Vec<(Point2, T)> {
+    let points: Vec<Vec2> = pixels.iter().map(|(p, _)| *p).collect();
+    let last = points.len() - 1;
+    let start = (points[1] - points[0]).normalize_or_zero();
+    let end = (points[last - 1] - points[last]).normalize_or_zero();
+    let mut curves = Vec::new();
+    fit_range(&points, 0, start, end, tolerance, &mut curves);
+
+    let mut out = Vec::new();
+    for (first, curve) in curves {
+        let steps = samples(curve[0].distance(curve[3]), tolerance);
+        for step in 0..steps {
+            out.push((
+                bezier_at(&curve, step as f32 / steps as f32),
+                pixels[first].1,
+            ));
+        }
+    }
+    out.push(pixels[last]);
+    out
+}
+
+// fits one cubic to `points` and splits at the worst point until every
+// piece is within the tolerance, the pieces are stored with the index of
+// their first point
+fn fit_range(
+    points: &[Vec2],
+    offset: usize,
+    start: Vec2,
+    end: Vec2,
+    tolerance: f32,
+    curves: &mut Vec<(usize, [Vec2; 4])>,
+) {
+    let last = points.len() - 1;
+    let params = chord_params(points);
+    let curve = bezier_through(points, &params, start, end);
+    let (split, error) = (1..last)
+        .map(|i| (i, bezier_at(&curve, params[i]).distance(points[i])))
+        .fold((0, 0.), |worst, e| if e.1 > worst.1 { e } else { worst });
+    if error <= tolerance || last < 2 {
+        curves.push((offset, curve));
+        return;
+    }
+    // both pieces leave the split point along the same line
+    let mut center = (points[split - 1] - points[split + 1]).normalize_or_zero();
+    if center == Vec2::ZERO {
+        center = (points[split - 1] - points[split]).normalize_or_zero();
+    }
+    fit_range(&points[..=split], offset, start, center, tolerance, curves);
+    fit_range(
+        &points[split..],
+        offset + split,
+        -center,
+        end,
+        tolerance,
+        curves,
+    );
+}
+
+// position of each point along the polyline, from 0 to 1
+fn chord_params(points: &[Vec2]) -> Vec<f32> {
+    let mut params = vec![0.];
+    for w in points.windows(2) {
+        params.push(params[params.len() - 1] + w[0].distance(w[1]));
+    }
+    let length = params[params.len() - 1];
+    if length > 0. {
+        params.iter_mut().for_each(|t| *t /= length);
+    }
+    params
+}
+
+// least squares cubic from the first to the last point leaving along the
+// given tangents, only the distance of the inner control points is solved
+fn bezier_through(points: &[Vec2], params: &[f32], start: Vec2, end: Vec2) -> [Vec2; 4] {
+    let (first, last) = (points[0], points[points.len() - 1]);
+    let (mut c00, mut c01, mut c11, mut x0, mut x1) = (0., 0., 0., 0., 0.);
+    for (p, &t) in points.iter().zip(params) {
+        let s = 1. - t;
+        let (b0, b1, b2, b3) = (s * s * s, 3. * s * s * t, 3. * s * t * t, t * t * t);
+        let (a1, a2) = (start * b1, end * b2);
+        let rest = *p - (first * (b0 + b1) + last * (b2 + b3));
+        c00 += a1.dot(a1);
+        c01 += a1.dot(a2);
+        c11 += a2.dot(a2);
+        x0 += a1.dot(rest);
+        x1 += a2.dot(rest);
+    }
+    let length = first.distance(last);
+    let det = c00 * c11 - c01 * c01;
+    let (mut left, mut right) = if det.abs() > f32::EPSILON {
+        ((x0 * c11 - x1 * c01) / det, (c00 * x1 - c01 * x0) / det)
+    } else {
+        (0., 0.)
+    };
+    // a solve that folds the curve back falls back to a third of the chord
+    let least = length * 1e-3;
+    if left < least || right < least {
+        left = length / 3.;
+        right = length / 3.;
+    }
+    [first, first + start * left, last + end * right, last]
+}
+
+fn bezier_at(curve: &[Vec2; 4], t: f32) -> Vec2 {
+    let s = 1. - t;
+    curve[0] * (s * s * s)
+        + curve[1] * (3. * s * s * t)
+        + curve[2] * (3. * s * t * t)
+        + curve[3] * (t * t * t)
+}
+
+fn samples(length: f32, tolerance: f32) -> usize {
+    ((length / (tolerance * SAMPLE_SPACING)).ceil() as usize).clamp(1, MAX_SAMPLES)
+}
+
+// width of the next point as a share of the weight, fast strokes get thin
+// the way ink does, `speed` is in screen points per frame
+pub fn speed_width(last: f32, speed: f32) -> f32 {
+    let target = (1. - speed / THIN_SPEED).clamp(MIN_WIDTH, 1.);
+    last + (target - last) * WIDTH_FOLLOW
+}
+
+// one quad across each segment of a stroke whose half width changes along
+// it, with circles of the half widths at the points they cover the stroke
+// without the holes a single outline leaves where it crosses itself
+pub fn quads(pixels: &[(Point2, Hsva)], halves: &[f32]) -> Vec<[(Point2, Hsva); 4]> {
+    pixels
+        .windows(2)
+        .zip(halves.windows(2))
+        .filter_map(|(pair, half)| {
+            let ((a, ca), (b, cb)) = (pair[0], pair[1]);
+            let dir = (b - a).try_normalize()?;
+            let normal = vec2(-dir.y, dir.x);
+            Some([
+                (a + normal * half[0], ca),
+                (b + normal * half[1], cb),
+                (b - normal * half[1], cb),
+                (a - normal * half[0], ca),
+            ])
+        })
+        .collect()
+}
+
+// closed outline of a stroke whose half width changes along it, with round
+// ends, to be drawn filled
+pub fn outline(pixels: &[(Point2, Hsva)], halves: &[f32]) -> Vec<(Point2, Hsva)> {
+    let last = match pixels.len() {
+        0 => return Vec::new(),
+        n => n - 1,
+    };
+    let at = |i: usize| pixels[i.min(last)].0;
+    // the sides run across the direction at each point
+    let normals: Vec<Vec2> = (0..=last)
+        .map(|i| {
+            let dir = (at(i + 1) - at(i.saturating_sub(1))).normalize_or_zero();
+            vec2(-dir.y, dir.x)
+        })
+        .collect();
+    let side = |i: usize, sign: f32| (pixels[i].0 + normals[i] * halves[i] * sign, pixels[i].1);
+    let cap = |i: usize, from: f32| {
+        let (p, color) = pixels[i];
+        // a lone point has no direction, its cap goes all the way round
+        let (steps, sweep) = if last == 0 {
+            (CAP_STEPS * 2, 2. * PI)
+        } else {
+            (CAP_STEPS, PI)
+        };
+        (1..steps).map(move |k| {
+            let angle = from - sweep * k as f32 / steps as f32;
+            (p + vec2(angle.cos(), angle.sin()) * halves[i], color)
+        })
+    };
+    let angle = |n: Vec2| n.y.atan2(n.x);
+    let mut out: Vec<_> = (0..=last).map(|i| side(i, 1.)).collect();
+    out.extend(cap(last, angle(normals[last])));
+    out.extend((0..=last).rev().map(|i| side(i, -1.)));
+    out.extend(cap(0, angle(normals[0]) - PI));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quads_run_across_each_segment() {
+        let red = hsva(0., 1., 1., 1.);
+        let pixels = [
+            (pt2(0., 0.), red),
+            (pt2(10., 0.), red),
+            (pt2(10., 0.), red),
+            (pt2(10., 10.), red),
+        ];
+        let quads = quads(&pixels, &[1., 2., 2., 4.]);
+        // the segment without a length has no direction to go across
+        assert_eq!(quads.len(), 2);
+        let corners = |quad: &[(Point2, Hsva); 4]| quad.map(|(p, _)| p);
+        assert_eq!(
+            corners(&quads[0]),
+            [pt2(0., 1.), pt2(10., 2.), pt2(10., -2.), pt2(0., -1.)]
+        );
+        assert_eq!(
+            corners(&quads[1]),
+            [pt2(8., 0.), pt2(6., 10.), pt2(14., 10.), pt2(12., 0.)]
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use super::{
+    blend::Blend,
+    document::StyleDoc,
+    elements::{split_pixels, walk_pixels, with_alpha, Element, Elements, Line},
+};
+use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// joins sharper than this ratio of miter length to width fall back to bevel
+pub const MITER_LIMIT: f32 = 4.;
+// shortest dash or gap as a multiple of the width, a pattern needs lengths to
+// move along the outline
+const MIN_DASH: f32 = 0.05;
+// corners of the polygons standing in for round joins and caps
+const ROUND_STEPS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StrokeJoin {
+    Miter,
+    Round,
+    Bevel,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StrokeCap {
+    Butt,
+    Round,
+    Square,
+}
+
+// how a shape fills and strokes its outline
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShapeStyle {
+    pub fill: Option<Hsva>,
+    pub stroke: Hsva,
+    // 0 leaves the outline out
+    pub width: f32,
+    // dash and gap lengths taking turns, as multiples of the width, empty is
+    // solid
+    pub dash: Vec<f32>,
+    pub join: StrokeJoin,
+    pub cap: StrokeCap,
+    pub blend: Blend,
+}
+
+impl Default for ShapeStyle {
+    fn default() -> Self {
+        Self {
+            fill: None,
+            stroke: Hsva::default(),
+            width: 1.,
+            dash: Vec::new(),
+            join: StrokeJoin::Miter,
+            cap: StrokeCap::Butt,
+            blend: Blend::Normal,
+        }
+    }
+}
+
+impl From<&ShapeStyle> for StyleDoc {
+    fn from(style: &ShapeStyle) -> Self {
+        Self {
+            fill: style.fill.map(Into::into),
+            stroke: style.stroke.into(),
+            width: style.width,
+            dash: style.dash.clone(),
+            join: style.join,
+            cap: style.cap,
+            blend: style.blend,
+        }
+    }
+}
+
+impl From<&StyleDoc> for ShapeStyle {
+    fn from(style: &StyleDoc) -> Self {
+        Self {
+            fill: style.fill.map(Into::into),
+            stroke: style.stroke.into(),
+            width: style.width,
+            // NaN and negative entries from files become the shortest dash
+            dash: style.dash.iter().map(|d| d.max(MIN_DASH)).collect(),
+            join: style.join,
+            cap: style.cap,
+            blend: style.blend,
+        }
+    }
+}
+
+impl ShapeStyle {
+    pub fn has_stroke(&self) -> bool {
+        self.width > 0.
+    }
+
+    // the color the picker and recoloring work on, the stroke unless the
+    // shape has none
+    pub fn get_color(&self) -> Hsva {
+        match self.fill {
+            Some(fill) if !self.has_stroke() => fill,
+            _ => self.stroke,
+        }
+    }
+
+    pub fn set_color(&mut self, color: Hsva) {
+        match self.fill {
+            Some(_) if !self.has_stroke() => self.fill = Some(color),
+            _ => self.stroke = color,
+        }
+    }
+
+    // how far the paint reaches past the outline
+    pub fn reach(&self) -> f32 {
+        let half = self.width / 2.;
+        match self.join {
+            StrokeJoin::Miter => half * MITER_LIMIT,
+            _ => half * 2f32.sqrt(),
+        }
+    }
+
+    // `outline` is closed when its last point repeats the first, only closed
+    // outlines are filled
+    pub fn draw(&self, draw: &Draw, outline: &[Vec2], alpha: f32) {
+        let draw = &self.blend.draw(draw);
+        if let Some(fill) = self.fill.filter(|_| is_closed(outline)) {
+            draw.polygon()
+                .color(with_alpha(fill, alpha))
+                .points(outline[..outline.len() - 1].iter().copied());
+        }
+        if !self.has_stroke() {
+            return;
+        }
+        let color = with_alpha(self.stroke, alpha);
+        if self.dash.is_empty() && is_closed(outline) {
+            let polygon = draw
+                .polygon()
+                .no_fill()
+                .stroke_color(color)
+                .stroke_weight(self.width);
+            let polygon = match self.join {
+                StrokeJoin::Miter => polygon.join_miter(),
+                StrokeJoin::Round => polygon.join_round(),
+                StrokeJoin::Bevel => polygon.join_bevel(),
+            };
+            polygon.points(outline[..outline.len() - 1].iter().copied());
+            return;
+        }
+        for run in self.dashes(outline) {
+            let line = draw.polyline().weight(self.width).color(color);
+            let line = match self.join {
+                StrokeJoin::Miter => line.join_miter(),
+                StrokeJoin::Round => line.join_round(),
+                StrokeJoin::Bevel => line.join_bevel(),
+            };
+            let line = match self.cap {
+                StrokeCap::Butt => line.caps_butt(),
+                StrokeCap::Round => line.caps_round(),
+                StrokeCap::Square => line.caps_square(),
+            };
+            line.points(run);
+        }
+    }
+
+    // the pieces of the outline the dash pattern keeps, the whole outline
+    // when it is solid
+    pub fn dashes(&self, outline: &[Vec2]) -> Vec<Vec<Vec2>> {
+        let mut pattern: Vec<f32> = self.dash.iter().map(|d| d * self.width).collect();
+        // an odd list repeats so dashes and gaps keep alternating, like svg
+        if pattern.len() % 2 == 1 {
+            pattern = pattern.repeat(2);
+        }
+        if pattern.iter().sum::<f32>() <= f32::EPSILON {
+            return vec![outline.to_vec()];
+        }
+        let mut runs = Vec::new();
+        let mut current = Vec::new();
+        let mut index = 0;
+        let mut left = pattern[0];
+        for w in outline.windows(2) {
+            let (a, b) = (w[0], w[1]);
+            let length = a.distance(b);
+            let mut at = 0.;
+            if index % 2 == 0 && current.is_empty() {
+                current.push(a);
+            }
+            while length - at > left {
+                at += left;
+                // a dash ends here or the next one starts
+                current.push(a.lerp(b, at / length));
+                if index % 2 == 0 {
+                    runs.push(std::mem::take(&mut current));
+                }
+                index = (index + 1) % pattern.len();
+                left = pattern[index];
+            }
+            left -= length - at;
+            if index % 2 == 0 {
+                current.push(b);
+            }
+        }
+        if current.len() > 1 {
+            runs.push(current);
+        }
+        runs
+    }
+
+    // convex polygons whose union is the stroke, closed like outlines, for
+    // the software renderer
+    pub fn stroke_pieces(&self, outline: &[Vec2]) -> Vec<Vec<Vec2>> {
+        let mut pieces = Vec::new();
+        if !self.has_stroke() {
+            return pieces;
+        }
+        let half = self.width / 2.;
+        let closed = self.dash.is_empty() && is_closed(outline);
+        for run in self.dashes(outline) {
+            let mut run = run;
+            run.dedup();
+            if run.len() < 2 {
+                // zero length dashes are dots when the caps have a size
+                if let Some(&p) = run.first() {
+                    pieces.push(cap_piece(p, Vec2::X, half, self.cap));
+                    pieces.push(cap_piece(p, -Vec2::X, half, self.cap));
+                }
+                continue;
+            }
+            for w in run.windows(2) {
+                let (a, b) = (w[0], w[1]);
+                let n = (b - a).normalize_or_zero().perp() * half;
+                pieces.push(vec![a + n, b + n, b - n, a - n, a + n]);
+            }
+            for w in run.windows(3) {
+                pieces.extend(join_piece(w[0], w[1], w[2], half, self.join));
+            }
+            if closed {
+                let n = run.len();
+                pieces.extend(join_piece(run[n - 2], run[0], run[1], half, self.join));
+            } else {
+                let n = run.len();
+                pieces.push(cap_piece(run[0], run[0] - run[1], half, self.cap));
+                pieces.push(cap_piece(
+                    run[n - 1],
+                    run[n - 1] - run[n - 2],
+                    half,
+                    self.cap,
+                ));
+            }
+        }
+        pieces.retain(|piece| piece.len() > 3);
+        pieces
+    }
+
+    // what the rubber leaves: filled shapes go whole, outlines are cut into
+    // strokes of their width
+    pub fn erase(
+        &self,
+        outline: &[Vec2],
+        hit: bool,
+        mouse_pos: Vec2,
+        r: f32,
+    ) -> Option<Vec<Elements>> {
+        if (self.fill.is_some() && is_closed(outline)) || !self.has_stroke() {
+            return hit.then(Vec::new);
+        }
+        let pixels = walk_pixels(outline, self.stroke);
+        let segments = split_pixels(&pixels, mouse_pos, r + self.width / 2.)?;
+        Some(
+            segments
+                .into_iter()
+                .map(|pixels| {
+                    let mut line = Line::new_param(pixels, self.width, self.stroke);
+                    line.set_blend(self.blend);
+                    Box::new(line) as Elements
+                })
+                .collect(),
+        )
+    }
+}
+
+pub fn is_closed(outline: &[Vec2]) -> bool {
+    outline.len() > 3 && outline.first() == outline.last()
+}
+
+fn circle(center: Vec2, radius: f32) -> Vec<Vec2> {
+    (0..=ROUND_STEPS)
+        .map(|i| {
+            let angle = i as f32 * 2. * PI / ROUND_STEPS as f32;
+            center + vec2(angle.cos(), angle.sin()) * radius
+        })
+        .collect()
+}
+
+// the end of a run facing `out`
+fn cap_piece(p: Vec2, out: Vec2, half: f32, cap: StrokeCap) -> Vec<Vec2> {
+    let dir = out.normalize_or_zero() * half;
+    let n = dir.perp();
+    match cap {
+        StrokeCap::Butt => Vec::new(),
+        StrokeCap::Round => circle(p, half),
+        StrokeCap::Square => vec![p + n, p + n + dir, p - n + dir, p - n, p + n],
+    }
+}
+
+// fills the wedge on the outer side of the corner at `b`
+fn join_piece(a: Vec2, b: Vec2, c: Vec2, half: f32, join: StrokeJoin) -> Option<Vec<Vec2>> {
+    let (d0, d1) = ((b - a).normalize_or_zero(), (c - b).normalize_or_zero());
+    let turn = d0.perp_dot(d1);
+    if turn.abs() <= f32::EPSILON {
+        return None;
+    }
+    // the outer side is to the right of a left turn
+    let side = -turn.signum();
+    let (n0, n1) = (d0.perp() * half * side, d1.perp() * half * side);
+    let bevel = vec![b, b + n0, b + n1, b];
+    match join {
+        StrokeJoin::Round => Some(circle(b, half)),
+        StrokeJoin::Bevel => Some(bevel),
+        StrokeJoin::Miter => {
+            let mid = (n0 + n1) / 2.;
+            // miter length over width
+            let ratio = half / mid.length().max(f32::EPSILON);
+            if ratio > MITER_LIMIT {
+                return Some(bevel);
+            }
+            let tip = b + mid.normalize_or_zero() * half * ratio;
+            Some(vec![b, b + n0, tip, b + n1, b])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::document::ColorDoc;
+
+    #[test]
+    fn dash_lengths_from_files_stay_positive() {
+        let red = ColorDoc {
+            hue: 0.,
+            saturation: 1.,
+            value: 1.,
+            alpha: 1.,
+        };
+        let doc = StyleDoc {
+            fill: None,
+            stroke: red,
+            width: 1.,
+            dash: vec![-1., 0., f32::NAN, 2.],
+            join: StrokeJoin::Miter,
+            cap: StrokeCap::Butt,
+            blend: Blend::Normal,
+        };
+        let style = ShapeStyle::from(&doc);
+        assert_eq!(style.dash, vec![MIN_DASH, MIN_DASH, MIN_DASH, 2.]);
+        let runs = style.dashes(&[pt2(0., 0.), pt2(10., 0.)]);
+        // two short dashes in every 2.15 long turn of the pattern
+        assert_eq!(runs.len(), 10);
+        assert!(runs.iter().all(|run| run.len() == 2));
+    }
+}